@@ -3,6 +3,7 @@ mod page;
 mod pager;
 mod schema;
 mod sql;
+mod statement;
 
 use std::fs::File;
 use std::path::Path;
@@ -13,17 +14,33 @@ use db_info::DBInfo;
 use pager::Pager;
 use schema::{Schema, SchemaType};
 
+pub use page::ColumnContent;
+pub use sql::{PlanIndex, PlanStep, QueryPlan, Rows, SyntaxError};
+pub use statement::Statement;
+
 #[derive(Debug)]
 pub struct DB {
     db_info: DBInfo,
     pager: Pager<File>,
+    /// statistics gathered by ANALYZE, empty if the database was not analyzed
+    stats: Vec<Stat>,
 }
 
 impl DB {
     pub fn new(file: impl AsRef<Path>) -> Result<Self> {
         let file = File::open(&file).context("open DB file")?;
         let (pager, db_info) = Pager::new(file)?;
-        Ok(Self { db_info, pager })
+        let mut db = Self {
+            db_info,
+            pager,
+            stats: Vec::new(),
+        };
+        // statistics only guide the choice of indexes, the default estimates are used without them
+        db.stats = db.read_stats().unwrap_or_else(|err| {
+            eprintln!("ignoring sqlite_stat1: {err:#}");
+            Vec::new()
+        });
+        Ok(db)
     }
 
     pub fn page_size(&self) -> u16 {
@@ -117,15 +134,54 @@ impl DB {
     }
 
     pub fn schemas_sql(&self) -> Vec<String> {
-        self.db_info.schemas.iter().map(|s| s.sql.clone()).collect()
+        self.db_info
+            .schemas
+            .iter()
+            .filter(|s| !s.sql.is_empty()) // automatic indexes have no sql
+            .map(|s| {
+                if s.typ != SchemaType::View.to_string() {
+                    return s.sql.clone();
+                }
+                // like sqlite3, append names of the view columns
+                match self
+                    .view(&s.tbl_name)
+                    .and_then(|view| match view.columns.is_empty() {
+                        true => sql::view_columns(self, &view),
+                        false => Ok(view.columns),
+                    }) {
+                    Ok(columns) => {
+                        format!("{}\n/* {}({}) */", s.sql, s.tbl_name, columns.join(","))
+                    }
+                    Err(_) => s.sql.clone(),
+                }
+            })
+            .collect()
     }
 
     pub fn execute(&mut self, sql: &str) -> Result<Vec<Vec<String>>> {
-        let cmd = sql::parse_command(sql)?;
-        let res = cmd.execute(self)?;
+        let stmt = self.prepare(sql)?;
+        let res = stmt.execute(self)?;
         Ok(res)
     }
 
+    /// Returns the result rows of the query, read from the file one at a time
+    /// as they are iterated over
+    pub fn query(&mut self, sql: &str) -> Result<Rows<'_>> {
+        let stmt = self.prepare(sql)?;
+        stmt.query(self)
+    }
+
+    /// Returns the plan of the query, how its tables are read, like EXPLAIN QUERY PLAN
+    pub fn query_plan(&mut self, sql: &str) -> Result<QueryPlan> {
+        let stmt = self.prepare(sql)?;
+        stmt.query_plan(self)
+    }
+
+    /// Parses the SQL statement, it can be executed repeatedly with different parameter values
+    pub fn prepare(&self, sql: &str) -> Result<Statement> {
+        Statement::new(sql)
+    }
+
     pub(crate) fn root_page_num(&self, table: &str, schema_type: SchemaType) -> Result<u64> {
         let schema = self
             .schema(table, schema_type)
@@ -139,23 +195,52 @@ impl DB {
         You'll need to parse the table's CREATE TABLE statement to do this. */
         let schema = self
             .schema(name, SchemaType::Table)
-            .ok_or(anyhow!("table {} does not exist", name))?;
+            .ok_or_else(|| anyhow!("table {} does not exist", name))?;
 
         let cmd = sql::parse_command(&schema.sql).context("parse schema")?;
 
-        let (columns, primary_key_column_index) = match cmd {
-            sql::Command::CreateTable {
-                columns,
-                primary_key,
-                ..
-            } => (columns, primary_key),
-            _ => bail!("Table schema is broken"),
+        let sql::Command::CreateTable(definition) = cmd else {
+            bail!("Table schema is broken");
         };
 
         Ok(Table {
-            name: name.to_string(),
+            name: schema.tbl_name.clone(),
+            columns: definition.columns.iter().map(|c| c.name.clone()).collect(),
+            defaults: definition
+                .columns
+                .iter()
+                .map(|c| c.default.clone())
+                .collect(),
+            affinities: definition.columns.iter().map(|c| c.affinity()).collect(),
+            generated: definition
+                .columns
+                .iter()
+                .map(|c| c.generated.clone())
+                .collect(),
+            rowid_column: definition.rowid_column().map(|i| i as u16),
+            primary_key: definition.primary_key(),
+            without_rowid: definition.without_rowid,
+        })
+    }
+
+    pub(crate) fn view(&self, name: &str) -> Result<View> {
+        let schema = self
+            .schema(name, SchemaType::View)
+            .ok_or_else(|| anyhow!("view {} does not exist", name))?;
+
+        let cmd = sql::parse_command(&schema.sql).context("parse view schema")?;
+
+        let (columns, select) = match cmd {
+            sql::Command::CreateView {
+                columns, select, ..
+            } => (columns, *select),
+            _ => bail!("View schema is broken"),
+        };
+
+        Ok(View {
+            name: schema.tbl_name.clone(),
             columns,
-            primary_key_column_index,
+            select,
         })
     }
 
@@ -163,7 +248,7 @@ impl DB {
         self.db_info
             .schemas(schema_type)
             .iter()
-            .find(|&s| s.tbl_name == table && s.typ == schema_type.to_string())
+            .find(|&s| s.tbl_name.eq_ignore_ascii_case(table) && s.typ == schema_type.to_string())
             .copied()
     }
 
@@ -171,29 +256,127 @@ impl DB {
         self.db_info
             .schemas(SchemaType::Index)
             .iter()
-            .filter(|&&s| s.tbl_name == table && s.typ == SchemaType::Index.to_string())
+            .filter(|&&s| {
+                s.tbl_name.eq_ignore_ascii_case(table) && s.typ == SchemaType::Index.to_string()
+            })
             .copied()
             .collect()
     }
 
-    pub(crate) fn index_on_column(&self, table: &str, column: &String) -> Option<&Schema> {
+    /// Number of rows of the table estimated by ANALYZE, None if the table was not analyzed
+    pub(crate) fn table_rows(&self, table: &str) -> Option<u64> {
+        // a partial index has less entries than the table
+        self.stats
+            .iter()
+            .filter(|stat| stat.table.eq_ignore_ascii_case(table))
+            .map(|stat| stat.counts[0])
+            .max()
+    }
+
+    /// Statistics of the index by ANALYZE: the number of its entries, then the average number
+    /// of entries with the same values of the first key, of the first two keys and so on
+    pub(crate) fn index_stat(&self, index: &str) -> Option<&[u64]> {
+        self.stats
+            .iter()
+            .find(|stat| {
+                stat.index
+                    .as_ref()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(index))
+            })
+            .map(|stat| stat.counts.as_slice())
+    }
+
+    /// Reads the rows of sqlite_stat1 written by ANALYZE
+    fn read_stats(&mut self) -> Result<Vec<Stat>> {
+        if self.schema("sqlite_stat1", SchemaType::Table).is_none() {
+            return Ok(Vec::new());
+        }
+        let rows = self.execute("SELECT tbl, idx, stat FROM sqlite_stat1")?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let [table, index, stat] = <[String; 3]>::try_from(row).ok()?;
+                // the numbers may be followed by options like `unordered` or `sz=NNN`
+                let counts: Vec<u64> = stat
+                    .split_whitespace()
+                    .map_while(|count| count.parse().ok())
+                    .collect();
+                (!counts.is_empty()).then(|| Stat {
+                    table,
+                    index: (!index.is_empty()).then_some(index),
+                    counts,
+                })
+            })
+            .collect())
+    }
+
+    /// Indexes of the table with their definitions, the definitions of automatic indexes
+    /// (which have no sql) are derived from the constraints of the table
+    pub(crate) fn table_indexes(&self, table: &str) -> Vec<Index> {
+        let auto_indexes = match self.schema(table, SchemaType::Table) {
+            Some(schema) => match sql::parse_command(&schema.sql) {
+                Ok(sql::Command::CreateTable(definition)) => definition.auto_indexes(),
+                _ => Vec::new(),
+            },
+            None => Vec::new(),
+        };
+
         self.indices(table)
             .iter()
-            .find(|&&s| {
-                sql::parse_command(&s.sql).map_or(false, |cmd| match cmd {
-                    sql::Command::CreateIndex { columns, .. } => {
-                        // we support only index on one column, but it could be easily extended
-                        columns.len() == 1 && columns.contains(column)
-                    }
-                    _ => false,
+            .filter_map(|s| {
+                let definition = match sql::parse_command(&s.sql) {
+                    Ok(sql::Command::CreateIndex(definition)) => definition,
+                    _ if s.sql.is_empty() => auto_indexes
+                        .iter()
+                        .find(|index| index.name.eq_ignore_ascii_case(&s.name))?
+                        .clone(),
+                    _ => return None,
+                };
+                Some(Index {
+                    root_page: s.rootpage,
+                    definition,
                 })
             })
-            .copied()
+            .collect()
     }
 }
 
 pub(crate) struct Table {
     name: String,
     columns: Vec<String>,
-    primary_key_column_index: u16,
+    /// the INTEGER PRIMARY KEY column, its value is the rowid
+    rowid_column: Option<u16>,
+    /// DEFAULT of the columns as SQL text
+    defaults: Vec<Option<String>>,
+    /// affinity of the columns by their declared types
+    affinities: Vec<sql::Affinity>,
+    /// `AS (expr)` of the generated columns, VIRTUAL columns are not stored in the records
+    generated: Vec<Option<sql::Generated>>,
+    /// indexes of the PRIMARY KEY columns in the order of the key, true for DESC columns
+    primary_key: Vec<(usize, bool)>,
+    /// the table is stored in an index b-tree keyed by the primary key, without rowid
+    without_rowid: bool,
+}
+
+pub(crate) struct Index {
+    root_page: u64,
+    /// the index key is the values of the key expressions followed by the rowid
+    definition: sql::IndexDef,
+}
+
+/// Row of sqlite_stat1
+#[derive(Debug)]
+struct Stat {
+    table: String,
+    /// None for the row of a table without indexes
+    index: Option<String>,
+    counts: Vec<u64>,
+}
+
+pub(crate) struct View {
+    name: String,
+    /// column names listed in the view definition, empty if the names are taken from the select
+    columns: Vec<String>,
+    select: sql::Command,
 }
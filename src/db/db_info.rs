@@ -202,6 +202,14 @@ impl DBInfo {
                         )?;
                         val_len
                     }
+                    // views and triggers have rootpage 0
+                    ColumnType::Int0(val_len) => val_len,
+                    ColumnType::Int1(val_len) => {
+                        num = 1;
+                        val_len
+                    }
+                    // automatic indexes have no sql
+                    ColumnType::Null(val_len) => val_len,
                     _ => bail!("Invalid column type in schema definition"),
                 };
 
@@ -40,17 +40,20 @@ pub(crate) struct Page {
     /// that contains records with keys greater than the largest key in the page.
     pub rightmost_pointer: Option<u32>,
     cell_pointers: Vec<u8>,
-    pub cells: Vec<Cell>,
+    /// Content of the whole page, cells are parsed when they are read
+    data: Bytes,
 }
 
 impl Page {
+    /// Reads the page at once, its cells are parsed later by `cell`
     pub(crate) fn load(db_file: &mut (impl Read + Seek), page_size: u16) -> Result<Self> {
+        let mut data = BytesMut::zeroed(page_size as usize);
+        db_file.read_exact(&mut data).context("read the page")?;
+        let data = data.freeze();
+
         // The b-tree page header is 8 bytes in size for leaf pages and 12 bytes for interior pages.
         // https://www.sqlite.org/fileformat.html#b_tree_pages
-        let mut page_header = [0; 8];
-        db_file
-            .read_exact(&mut page_header)
-            .context("read page header (first 8 bytes)")?;
+        let page_header = &data[..8];
 
         let page_type = page_header[0].try_into()?;
 
@@ -73,124 +76,157 @@ impl Page {
         // The four-byte page number at offset 8 is the right-most pointer.
         // This value appears in the header of interior b-tree pages only and is omitted from all other pages.
         let mut rightmost_pointer = None;
-        let mut extra_header = [0; 4];
+        let mut page_header_len = page_header.len();
 
         if page_type == PageType::TableInterior || page_type == PageType::IndexInterior {
-            db_file
-                .read_exact(&mut extra_header)
-                .context("read page header (extra 4 bytes in interior pages)")?;
-            rightmost_pointer = Some(u32::from_be_bytes(extra_header));
+            rightmost_pointer = Some(u32::from_be_bytes(data[8..12].try_into()?));
+            page_header_len += 4;
         }
 
-        let page_header_len = match page_type {
-            PageType::TableInterior | PageType::IndexInterior => page_header.len() + 4,
-            _ => page_header.len(),
-        };
-
         // The cell pointer array of a b-tree page immediately follows the b-tree page header.
         // Let K be the number of cells on the btree. The cell pointer array consists of K 2-byte integer offsets to the cell contents.
-        // The cell pointer array consists of K 2-byte integer offsets to the cell contents.
-        let mut cell_pointers = vec![0; 2 * n_cells as usize];
-        db_file
-            .read_exact(&mut cell_pointers)
-            .context("read cell pointers")?;
-
-        let mut previous_offset = page_size;
-
-        db_file
-            .seek_relative((page_size as usize - page_header_len - cell_pointers.len()) as i64)
-            .context("move to the end of the page")?;
-
-        let mut cells = Vec::new();
-
-        for i_cell in 0..n_cells as usize {
-            let offset =
-                u16::from_be_bytes([cell_pointers[i_cell * 2], cell_pointers[i_cell * 2 + 1]]);
+        let cell_pointers = data
+            .get(page_header_len..page_header_len + 2 * n_cells as usize)
+            .context("read cell pointers")?
+            .to_vec();
 
-            let cell_size = (previous_offset - offset) as usize;
-
-            db_file
-                .seek_relative(-(cell_size as i64))
-                .context("move to the beginning of the cell")?;
-
-            let mut cell = BytesMut::zeroed(cell_size);
-
-            db_file
-                .read_exact(&mut cell)
-                .context("read the cell content")?;
-
-            let cell: Cell = match page_type {
-                PageType::TableLeaf => {
-                    // Size of the record (varint)
-                    let payload_size = varint(&mut cell)
-                        .with_context(|| format!("get int from varint {:?}", cell))?;
-
-                    // rowid (varint)
-                    let row_id = varint(&mut cell)
-                        .with_context(|| format!("get int from varint {:?}", cell))?
-                        as u64;
+        Ok(Self {
+            data_size,
+            page_type,
+            freeblocks,
+            n_cells,
+            content_start,
+            fragmented,
+            rightmost_pointer,
+            cell_pointers,
+            data,
+        })
+    }
 
-                    // discard unused cell content
-                    // cell now contains only valid record content
-                    cell.resize(payload_size as usize, 0);
+    /// Returns the number of cells of the page
+    pub(crate) fn len(&self) -> usize {
+        self.n_cells as usize
+    }
 
-                    let payload = Bytes::from(cell);
-                    TableLeafCell::new(row_id, payload)
-                        .context("get a TableLeafCell")?
-                        .into()
-                }
-                PageType::TableInterior => {
-                    let left_child_page = cell.get_u32();
-                    let key = varint(&mut cell)? as u64;
-                    TableInteriorCell::new(left_child_page, key).into()
-                }
-                PageType::IndexLeaf => {
-                    // Size of the payload (varint)
-                    let payload_size = varint(&mut cell)
-                        .with_context(|| format!("get int from varint {:?}", cell))?;
-
-                    // discard unused cell content
-                    // cell now contains only valid payload content
-                    cell.resize(payload_size as usize, 0);
-
-                    let payload = Bytes::from(cell);
-                    IndexLeafCell::new(payload)
-                        .context("get an IndexLeafCell")?
-                        .into()
-                }
-                PageType::IndexInterior => {
-                    let left_child_page = cell.get_u32();
-                    // Size of the payload (varint)
-                    let payload_size = varint(&mut cell)
-                        .with_context(|| format!("get int from varint {:?}", cell))?;
+    /// Parses the cell with the index, None if the page has less cells
+    pub(crate) fn cell(&self, i: usize) -> Result<Option<Cell>> {
+        if i >= self.len() {
+            return Ok(None);
+        }
+        let offset = u16::from_be_bytes([self.cell_pointers[i * 2], self.cell_pointers[i * 2 + 1]]);
+        if offset as usize >= self.data.len() {
+            bail!("cell offset {offset} out of the page");
+        }
+        let mut cell = self.data.slice(offset as usize..);
+
+        let cell: Cell = match self.page_type {
+            PageType::TableLeaf => {
+                // Size of the record (varint)
+                let payload_size =
+                    varint(&mut cell).with_context(|| format!("get int from varint {:?}", cell))?;
+
+                // rowid (varint)
+                let row_id = varint(&mut cell)
+                    .with_context(|| format!("get int from varint {:?}", cell))?
+                    as u64;
+
+                TableLeafCell::new(row_id, payload(cell, payload_size))
+                    .context("get a TableLeafCell")?
+                    .into()
+            }
+            PageType::TableInterior => {
+                let left_child_page = cell.get_u32();
+                let key = varint(&mut cell)? as u64;
+                TableInteriorCell::new(left_child_page, key).into()
+            }
+            PageType::IndexLeaf => {
+                // Size of the payload (varint)
+                let payload_size =
+                    varint(&mut cell).with_context(|| format!("get int from varint {:?}", cell))?;
+
+                IndexLeafCell::new(payload(cell, payload_size))
+                    .context("get an IndexLeafCell")?
+                    .into()
+            }
+            PageType::IndexInterior => {
+                let left_child_page = cell.get_u32();
+                // Size of the payload (varint)
+                let payload_size =
+                    varint(&mut cell).with_context(|| format!("get int from varint {:?}", cell))?;
+
+                IndexInteriorCell::new(left_child_page, payload(cell, payload_size))
+                    .context("get an IndexInteriorCell")?
+                    .into()
+            }
+        };
 
-                    // discard unused cell content
-                    // cell now contains only valid payload content
-                    cell.resize(payload_size as usize, 0);
+        Ok(Some(cell))
+    }
 
-                    let payload = Bytes::from(cell);
+    /// Parses all cells of the page in order
+    pub(crate) fn cells(&self) -> impl DoubleEndedIterator<Item = Result<Cell>> + '_ {
+        (0..self.len()).filter_map(|i| self.cell(i).transpose())
+    }
+}
 
-                    IndexInteriorCell::new(left_child_page, payload)
-                        .context("get an IndexInteriorCell")?
-                        .into()
-                }
-            };
+/// Returns the payload of the cell without the unused content after it,
+/// a payload longer than the rest of the page is padded with zeros
+fn payload(cell: Bytes, payload_size: i64) -> Bytes {
+    let payload_size = payload_size as usize;
+    if payload_size <= cell.len() {
+        return cell.slice(..payload_size);
+    }
+    let mut payload = BytesMut::from(&cell[..]);
+    payload.resize(payload_size, 0);
+    payload.freeze()
+}
 
-            cells.push(cell);
+/// Page of a b-tree with the number of its cells and its child pages, without the cell contents.
+/// Reading it avoids parsing the records when only the shape of the tree matters.
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
+    pub page_type: PageType,
+    pub n_cells: u16,
+    /// left child pages of the cells followed by the right-most pointer, empty for leaf pages
+    pub children: Vec<u32>,
+}
 
-            previous_offset = offset + cell_size as u16;
+impl Node {
+    pub(crate) fn load(db_file: &mut impl Read, page_size: u16) -> Result<Self> {
+        let mut page = vec![0; page_size as usize];
+        db_file.read_exact(&mut page).context("read page")?;
+
+        let page_type: PageType = page[0].try_into()?;
+        let n_cells = u16::from_be_bytes([page[3], page[4]]);
+        if page_type == PageType::TableLeaf || page_type == PageType::IndexLeaf {
+            return Ok(Self {
+                page_type,
+                n_cells,
+                children: Vec::new(),
+            });
         }
 
+        // interior cells start with the four-byte page number of the left child,
+        // the cell pointer array follows the 12 bytes of the interior page header
+        let bytes = |offset: usize, len: usize| {
+            page.get(offset..offset + len)
+                .with_context(|| format!("offset {offset} out of the page"))
+        };
+        let page_num = |offset: usize| -> Result<u32> {
+            Ok(u32::from_be_bytes(bytes(offset, 4)?.try_into()?))
+        };
+        let mut children = (0..n_cells as usize)
+            .map(|i| {
+                let offset = u16::from_be_bytes(bytes(12 + 2 * i, 2)?.try_into()?);
+                page_num(offset as usize)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        children.push(page_num(8)?);
+
         Ok(Self {
-            data_size,
             page_type,
-            freeblocks,
             n_cells,
-            content_start,
-            fragmented,
-            rightmost_pointer,
-            cell_pointers,
-            cells,
+            children,
         })
     }
 }
@@ -254,13 +290,23 @@ impl TableLeafCell {
         Ok(Self { row_id, record })
     }
 
-    /// Returns content of the column
+    pub(crate) fn row_id(&self) -> u64 {
+        self.row_id
+    }
+
+    /// Number of columns stored in the record, records written before
+    /// `ALTER TABLE ADD COLUMN` have less columns than the table
+    pub(crate) fn column_count(&self) -> usize {
+        self.record.columns.len()
+    }
+
+    /// Returns content of the column, the rowid for the column which is its alias
     pub(crate) fn column(
         &self,
         column_index: u16,
-        primary_key_column: u16,
+        rowid_column: Option<u16>,
     ) -> Result<ColumnContent> {
-        if column_index == primary_key_column {
+        if Some(column_index) == rowid_column {
             return Ok(ColumnContent::Int(self.row_id as i64));
         }
 
@@ -316,7 +362,7 @@ impl Record {
         let col = self
             .columns
             .get(column_index as usize)
-            .ok_or(anyhow!("column index {column_index} out of range"))?;
+            .ok_or_else(|| anyhow!("column index {column_index} out of range"))?;
 
         let column_type = &col.typ;
         let column_length = column_type.column_bytes_lenght();
@@ -339,39 +385,169 @@ impl Record {
                 6 => {
                     let mut alligned = vec![0u8; 2];
                     alligned.extend_from_slice(column_bytes);
-                    ColumnContent::Int(i64::from_be_bytes(alligned.as_slice().try_into()?) as i64)
+                    ColumnContent::Int(i64::from_be_bytes(alligned.as_slice().try_into()?))
                 }
 
-                8 => ColumnContent::Int(i64::from_be_bytes(column_bytes.try_into()?) as i64),
+                8 => ColumnContent::Int(i64::from_be_bytes(column_bytes.try_into()?)),
                 _ => bail!("Invalid INT column length: {:?} bytes", int_len),
             },
             ColumnType::Int0(_) => ColumnContent::Int(0),
             ColumnType::Int1(_) => ColumnContent::Int(1),
+            ColumnType::Float(_) => {
+                ColumnContent::Float(f64::from_be_bytes(column_bytes.try_into()?))
+            }
+            ColumnType::Blob(_) => ColumnContent::Blob(column_bytes.to_vec()),
             ColumnType::Null(_) => ColumnContent::Null,
-            _ => unimplemented!("column type: {:?}", column_type),
         };
 
         Ok(content)
     }
+
+    /// Returns contents of all columns of the record
+    fn values(&self) -> Result<Vec<ColumnContent>> {
+        (0..self.columns.len() as u16)
+            .map(|i| self.column(i))
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum ColumnContent {
     Text(String),
     Int(i64),
+    Float(f64),
+    Blob(Vec<u8>),
     Null,
 }
 
+impl ColumnContent {
+    /// Storage class rank in SQLite sort order: NULLs first, then numbers, then text,
+    /// then blobs.
+    fn rank(&self) -> u8 {
+        match self {
+            ColumnContent::Null => 0,
+            ColumnContent::Int(_) | ColumnContent::Float(_) => 1,
+            ColumnContent::Text(_) => 2,
+            ColumnContent::Blob(_) => 3,
+        }
+    }
+}
+
+impl PartialEq for ColumnContent {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for ColumnContent {}
+
+impl PartialOrd for ColumnContent {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ColumnContent {
+    /// Values are ordered the same way as keys in SQLite b-trees (NULL is equal to NULL here)
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self, other) {
+            (ColumnContent::Int(a), ColumnContent::Int(b)) => a.cmp(b),
+            // integers and floats are compared by their numeric value
+            (ColumnContent::Float(a), ColumnContent::Float(b)) => a.total_cmp(b),
+            (ColumnContent::Int(a), ColumnContent::Float(b)) => (*a as f64).total_cmp(b),
+            (ColumnContent::Float(a), ColumnContent::Int(b)) => a.total_cmp(&(*b as f64)),
+            (ColumnContent::Text(a), ColumnContent::Text(b)) => a.cmp(b),
+            (ColumnContent::Blob(a), ColumnContent::Blob(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
 impl std::fmt::Display for ColumnContent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ColumnContent::Text(s) => write!(f, "{}", s),
             ColumnContent::Int(i) => write!(f, "{}", i),
+            ColumnContent::Float(x) => write!(f, "{}", format_float(*x)),
+            ColumnContent::Blob(b) => write!(f, "{}", String::from_utf8_lossy(b)),
             ColumnContent::Null => write!(f, ""),
         }
     }
 }
 
+/// Formats the float like SQLite: 15 significant digits, at least one decimal
+/// and the exponent form for large and small values (`1000.0`, `0.3`, `1.0e+20`)
+fn format_float(x: f64) -> String {
+    if !x.is_finite() {
+        return match x {
+            x if x.is_nan() => String::new(),
+            x if x > 0.0 => "Inf".to_string(),
+            _ => "-Inf".to_string(),
+        };
+    }
+
+    // round to 15 significant digits, the shortest representation of the result
+    // doesn't have the noise of the last binary digits
+    let x: f64 = format!("{x:.14e}").parse().unwrap_or(x);
+    let exponent = if x == 0.0 {
+        0
+    } else {
+        x.abs().log10().floor() as i32
+    };
+
+    if (-4..15).contains(&exponent) {
+        let s = x.to_string();
+        if s.contains('.') {
+            s
+        } else {
+            format!("{s}.0")
+        }
+    } else {
+        let s = format!("{x:e}");
+        let (mantissa, exponent) = s.split_once('e').unwrap_or((&s, "0"));
+        let mantissa = if mantissa.contains('.') {
+            mantissa.to_string()
+        } else {
+            format!("{mantissa}.0")
+        };
+        let (sign, digits) = match exponent.strip_prefix('-') {
+            Some(digits) => ('-', digits),
+            None => ('+', exponent),
+        };
+        format!("{mantissa}e{sign}{digits:0>2}")
+    }
+}
+
+impl From<i64> for ColumnContent {
+    fn from(i: i64) -> Self {
+        ColumnContent::Int(i)
+    }
+}
+
+impl From<f64> for ColumnContent {
+    fn from(x: f64) -> Self {
+        ColumnContent::Float(x)
+    }
+}
+
+impl From<&str> for ColumnContent {
+    fn from(s: &str) -> Self {
+        ColumnContent::Text(s.to_string())
+    }
+}
+
+impl From<String> for ColumnContent {
+    fn from(s: String) -> Self {
+        ColumnContent::Text(s)
+    }
+}
+
+impl<T: Into<ColumnContent>> From<Option<T>> for ColumnContent {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(ColumnContent::Null, Into::into)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct RecordColumn {
     offset: usize,
@@ -390,14 +566,10 @@ impl IndexLeafCell {
         Ok(Self { record })
     }
 
-    /// Returns content of the index column
-    pub(crate) fn key(&self) -> Result<ColumnContent> {
-        self.record.column(0)
-    }
-
-    /// Returns content of the row_id
-    pub(crate) fn row_id(&self) -> Result<ColumnContent> {
-        self.record.column(1)
+    /// Returns contents of all columns of the record, the index key (indexed columns and the row_id)
+    /// or the full row of a WITHOUT ROWID table. Cells are ordered by the key in ascending order.
+    pub(crate) fn values(&self) -> Result<Vec<ColumnContent>> {
+        self.record.values()
     }
 }
 
@@ -429,14 +601,10 @@ impl IndexInteriorCell {
         self.left_child_page
     }
 
-    /// Returns content of the index key (ie. indexed column). Cells in interior pages are logically ordered by key in ascending order.
-    pub(crate) fn key(&self) -> Result<ColumnContent> {
-        self.record.column(0)
-    }
-
-    /// Returns content of the row_id
-    pub(crate) fn row_id(&self) -> Result<ColumnContent> {
-        self.record.column(1)
+    /// Returns contents of all columns of the record, the index key (indexed columns and the row_id)
+    /// or the full row of a WITHOUT ROWID table. Cells are ordered by the key in ascending order.
+    pub(crate) fn values(&self) -> Result<Vec<ColumnContent>> {
+        self.record.values()
     }
 }
 
@@ -1,14 +1,20 @@
 use super::{
     db_info::DBInfo,
-    page::{Cell, Page, TableLeafCell},
+    page::{Cell, ColumnContent, Node, Page, PageType, TableLeafCell},
 };
 
 use std::{
-    collections::HashMap,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
     io::{Read, Seek, SeekFrom},
+    ops::{Bound, Range},
+    rc::Rc,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+
+/// Maximum number of pages kept in the cache, the least recently used page is evicted
+const PAGE_CACHE_CAPACITY: usize = 1024;
 
 #[derive(Debug)]
 pub(super) struct Pager<T>
@@ -17,8 +23,11 @@ where
 {
     file: T,
     page_size: u16,
-    use_cache: bool,
-    page_cache: HashMap<u64, Page>,
+    /// cached pages with the number of the read that used them last
+    page_cache: HashMap<u64, (Rc<Page>, u64)>,
+    /// numbers of the cached pages by the number of the read that used them last
+    recent: BTreeMap<u64, u64>,
+    reads: u64,
 }
 
 impl<T> Pager<T>
@@ -31,26 +40,53 @@ where
             Self {
                 file,
                 page_size: db_info.page_size,
-                use_cache: false,
                 page_cache: HashMap::new(),
+                recent: BTreeMap::new(),
+                reads: 0,
             },
             db_info,
         ))
     }
 
-    pub(super) fn get_page(&mut self, num: u64) -> Result<Page> {
-        if !self.use_cache {
-            return self.load_page(num);
+    /// Returns the number of pages read with their cells so far, from the cache or the file
+    #[cfg(test)]
+    pub(super) fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Returns the page, from the cache if it is there. The page read from the file is added
+    /// to the cache if `cache`.
+    pub(super) fn get_page(&mut self, num: u64, cache: bool) -> Result<Rc<Page>> {
+        self.reads += 1;
+        if let Some((page, last_read)) = self.page_cache.get_mut(&num) {
+            self.recent.remove(last_read);
+            self.recent.insert(self.reads, num);
+            *last_read = self.reads;
+            return Ok(Rc::clone(page));
         }
 
-        // use cache
-        if let Some(page) = self.page_cache.get(&num) {
-            Ok(page.clone())
-        } else {
-            let page = self.load_page(num)?;
-            self.page_cache.insert(num, page.clone());
-            Ok(page)
+        let page = Rc::new(self.load_page(num)?);
+        if cache {
+            if self.page_cache.len() >= PAGE_CACHE_CAPACITY {
+                if let Some((_, least_recent)) = self.recent.pop_first() {
+                    self.page_cache.remove(&least_recent);
+                }
+            }
+            self.page_cache.insert(num, (Rc::clone(&page), self.reads));
+            self.recent.insert(self.reads, num);
         }
+        Ok(page)
+    }
+
+    /// Reads the page as a node of a b-tree, without its cells
+    pub(super) fn get_node(&mut self, num: u64) -> Result<Node> {
+        let page_start = (num - 1) * self.page_size as u64;
+
+        self.file
+            .seek(SeekFrom::Start(page_start))
+            .context("seek offset in the DB file")?;
+
+        Node::load(&mut self.file, self.page_size).context("loading node from file")
     }
 
     fn load_page(&mut self, num: u64) -> Result<Page> {
@@ -69,8 +105,6 @@ where
     T: Read + Seek,
 {
     pager: &'a mut Pager<T>,
-    table_root_page: u64,
-    cells: Vec<TableLeafCell>,
 }
 
 impl<'a, T> Tree<'a, T>
@@ -78,200 +112,551 @@ where
     T: Read + Seek,
 {
     pub(super) fn new(pager: &'a mut Pager<T>) -> Self {
-        Self {
-            pager,
-            table_root_page: 0,
-            cells: Vec::new(),
+        Self { pager }
+    }
+
+    /// Returns the number of entries of the b-tree, rows of a table or records of an index,
+    /// counting the cells of its pages without reading them
+    pub(super) fn count(&mut self, page: u64) -> Result<u64> {
+        let node = self
+            .pager
+            .get_node(page)
+            .with_context(|| format!("get node {page}"))?;
+
+        // cells of interior pages of a table have only the keys of their children
+        let mut count = match node.page_type {
+            PageType::TableInterior => 0,
+            _ => node.n_cells as u64,
+        };
+        for child in node.children {
+            count += self.count(child as u64)?;
         }
+        Ok(count)
     }
 
-    pub(super) fn cells(
-        &mut self,
-        page: u64,
-        filter: Option<CellFilter>,
-    ) -> Result<&Vec<TableLeafCell>> {
-        self.table_root_page = page;
-        let mut root_page = page;
-
-        if let Some(f) = &filter {
-            if let Some(index_root_page) = f.index_root_page {
-                self.pager.use_cache = true; // we want to use page cache when searching with index
-                root_page = index_root_page;
+    /// Returns the row_id of the first row of the table b-tree, of the last row if `last`,
+    /// None if the table is empty
+    pub(super) fn edge_row_id(&mut self, page: u64, last: bool) -> Result<Option<i64>> {
+        let page = self
+            .pager
+            .get_page(page, false)
+            .with_context(|| format!("get page {page}"))?;
+
+        let cell = match last {
+            true => page.cells().next_back(),
+            false => page.cells().next(),
+        }
+        .transpose()?;
+        match (page.rightmost_pointer, cell) {
+            (Some(page_num), _) if last => self.edge_row_id(page_num as u64, last),
+            (_, Some(Cell::TableInterior(cell))) => {
+                self.edge_row_id(cell.left_child_page() as u64, last)
             }
+            (_, Some(Cell::TableLeaf(cell))) => Ok(Some(cell.row_id() as i64)),
+            (_, None) => Ok(None),
+            _ => bail!("index b-tree page in a table b-tree"),
         }
+    }
+
+    /// Returns the row of the table b-tree with the row_id, None if there is no such row.
+    /// Only the cells on the path to the row are compared with the row_id.
+    pub(super) fn row(&mut self, page: u64, row_id: i64) -> Result<Option<TableLeafCell>> {
+        let page = self
+            .pager
+            .get_page(page, true)
+            .with_context(|| format!("get page {page}"))?;
+        let key = ColumnContent::Int(row_id);
 
-        self.load_page_cells(root_page, &filter)
-            .with_context(|| format!("load tree cells for root page {page}"))?;
-        Ok(&self.cells)
+        // the first cell with a key not lower than the row_id
+        let i = partition(&page, |cell_key| cell_key[0] < key)?;
+        match (&page.page_type, page.cell(i)?) {
+            (PageType::TableInterior, Some(Cell::TableInterior(cell))) => {
+                self.row(cell.left_child_page() as u64, row_id)
+            }
+            (PageType::TableInterior, _) => match page.rightmost_pointer {
+                Some(page_num) => self.row(page_num as u64, row_id),
+                None => bail!("interior page without the right-most pointer"),
+            },
+            (PageType::TableLeaf, Some(Cell::TableLeaf(cell))) => {
+                Ok((cell.row_id() as i64 == row_id).then_some(cell))
+            }
+            (PageType::TableLeaf, _) => Ok(None),
+            _ => bail!("index b-tree page in a table b-tree"),
+        }
     }
 
-    fn load_page_cells(&mut self, page: u64, filter: &Option<CellFilter>) -> Result<()> {
+    /// Returns the first record of the index b-tree with a value in the first field,
+    /// the last record if `last`. Records with NULL are skipped, they are the first ones.
+    pub(super) fn edge_record(
+        &mut self,
+        page: u64,
+        last: bool,
+    ) -> Result<Option<Vec<ColumnContent>>> {
         let page = self
             .pager
-            .get_page(page)
+            .get_page(page, false)
             .with_context(|| format!("get page {page}"))?;
+        let has_value = |values: &[ColumnContent]| values.first() != Some(&ColumnContent::Null);
 
-        for (i, cell) in page.cells.into_iter().enumerate() {
-            match cell {
-                Cell::TableLeaf(table_leaf_cell) => {
-                    if let Some(f) = filter {
-                        let record_val = table_leaf_cell.column(f.col_i, f.primary_key_col_i)?;
-                        if f.val != record_val.to_string().to_lowercase() {
-                            continue;
-                        }
+        if last {
+            // the last record is in the right-most leaf page
+            return match (
+                page.rightmost_pointer,
+                page.cells().next_back().transpose()?,
+            ) {
+                (Some(page_num), _) => self.edge_record(page_num as u64, last),
+                (None, Some(Cell::IndexLeaf(cell))) => {
+                    let values = cell.values()?;
+                    Ok(has_value(&values).then_some(values))
+                }
+                (None, None) => Ok(None),
+                _ => bail!("table b-tree page in an index b-tree"),
+            };
+        }
+
+        for cell in page.cells() {
+            match cell? {
+                Cell::IndexLeaf(index_leaf_cell) => {
+                    let values = index_leaf_cell.values()?;
+                    if has_value(&values) {
+                        return Ok(Some(values));
                     }
-                    self.cells.push(table_leaf_cell)
                 }
-                Cell::TableInterior(table_interior_cell) => {
-                    if let Some(f) = filter {
-                        if f.col_i == f.primary_key_col_i {
-                            // filtering by row_id
-                            let searched_row_id = f
-                                .val
-                                .parse()
-                                .context("row_id value in filter is not number")?;
-
-                            if table_interior_cell.row_id() >= searched_row_id {
-                                // we either found searched key or are behind it => read from child page
-                                let page_num = table_interior_cell.left_child_page();
-                                self.load_page_cells(page_num as u64, filter).with_context(
-                                    || format!("load cells for table child page {page_num}"),
-                                )?;
-                            }
-
-                            if table_interior_cell.row_id() > searched_row_id {
-                                break;
-                            }
-                            // if we read the last cell on the page,
-                            // move on to the page on the right side of the tree if some
-                            if i == page.n_cells as usize - 1 {
-                                if let Some(page_num) = page.rightmost_pointer {
-                                    self.load_page_cells(page_num as u64, filter).with_context(
-                                        || {
-                                            format!(
-                                        "load cells for right-most table interior page {page_num}"
-                                    )
-                                        },
-                                    )?;
-                                }
-                            }
-                            continue;
-                        }
+                Cell::IndexInterior(index_interior_cell) => {
+                    let values = index_interior_cell.values()?;
+                    if !has_value(&values) {
+                        // the left child has only records with NULL
+                        continue;
                     }
+                    let page_num = index_interior_cell.left_child_page();
+                    let first = self.edge_record(page_num as u64, last)?;
+                    return Ok(first.or(Some(values)));
+                }
+                Cell::TableLeaf(_) | Cell::TableInterior(_) => {
+                    bail!("table b-tree page in an index b-tree")
+                }
+            }
+        }
+
+        match page.rightmost_pointer {
+            Some(page_num) => self.edge_record(page_num as u64, last),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Entry of a b-tree found by a cursor
+enum Entry {
+    Row(TableLeafCell),
+    Record(Vec<ColumnContent>),
+}
+
+/// Cell of a b-tree page or a child page in the order of the keys
+enum Item {
+    Entry(Entry),
+    Child(u32),
+}
+
+/// Page on the path from the root to the current entry with its items not read yet.
+///
+/// Items are numbered in the order of the keys: cells of a leaf page are its items,
+/// interior pages of a table have their children, interior pages of an index have
+/// a child before each cell and the right-most child after them.
+struct Frame {
+    page: Rc<Page>,
+    items: Range<usize>,
+}
+
+impl Frame {
+    /// Returns the item with the number
+    fn item(&self, item: usize) -> Result<Item> {
+        let page = &self.page;
+        let child = |i: usize| match page.cell(i)? {
+            Some(Cell::TableInterior(cell)) => Ok(cell.left_child_page()),
+            Some(Cell::IndexInterior(cell)) => Ok(cell.left_child_page()),
+            Some(_) => bail!("child of a leaf page"),
+            None => page
+                .rightmost_pointer
+                .context("interior page without the right-most pointer"),
+        };
+
+        Ok(match (&page.page_type, page.cell(item)?) {
+            (PageType::TableLeaf, Some(Cell::TableLeaf(cell))) => Item::Entry(Entry::Row(cell)),
+            (PageType::IndexLeaf, Some(Cell::IndexLeaf(cell))) => {
+                Item::Entry(Entry::Record(cell.values()?))
+            }
+            (PageType::TableInterior, _) => Item::Child(child(item)?),
+            (PageType::IndexInterior, _) if item % 2 == 0 => Item::Child(child(item / 2)?),
+            // interior cells of an index also contain index entries
+            (PageType::IndexInterior, _) => match page.cell(item / 2)? {
+                Some(Cell::IndexInterior(cell)) => Item::Entry(Entry::Record(cell.values()?)),
+                _ => bail!("table b-tree cell in an index b-tree"),
+            },
+            _ => bail!("cell {item} does not match the page type"),
+        })
+    }
+}
+
+/// Reads the entries of a b-tree one at a time in the order of the keys, keeping only
+/// the pages on the path from the root to the current entry.
+///
+/// The cursor does not hold the pager, it is passed to each read.
+pub(super) struct Cursor {
+    /// page the reading starts at, the root of the index for an index filter
+    root_page: u64,
+    /// table the rows found in the index are read from
+    table_root_page: u64,
+    filter: Option<CellFilter>,
+    /// searched pages are cached, seeks are usually repeated for many keys (joins)
+    use_cache: bool,
+    /// pages are read from the last key to the first one
+    backward: bool,
+    /// pages on the path from the root with their items not read yet
+    stack: Vec<Frame>,
+    /// the root page is read
+    started: bool,
+}
 
-                    // Full table scan
-
-                    let page_num = table_interior_cell.left_child_page();
-                    self.load_page_cells(page_num as u64, filter)
-                        .with_context(|| format!("load cells for table child page {page_num}"))?;
-
-                    // if we read the last cell on the page,
-                    // move on to the page on the right side of the tree if some
-                    if i == page.n_cells as usize - 1 {
-                        if let Some(page_num) = page.rightmost_pointer {
-                            self.load_page_cells(page_num as u64, filter)
-                                .with_context(|| {
-                                    format!(
-                                        "load cells for right-most table interior page {page_num}"
-                                    )
-                                })?;
-                        }
+impl Cursor {
+    /// Cursor over the rows of the table with the root `page` matching the filter,
+    /// an index filter looks the rows up in the index
+    pub(super) fn table(page: u64, filter: Option<CellFilter>) -> Self {
+        let root_page = filter
+            .as_ref()
+            .and_then(|filter| filter.index_root_page)
+            .unwrap_or(page);
+        Self {
+            root_page,
+            table_root_page: page,
+            use_cache: filter.is_some(),
+            filter,
+            backward: false,
+            stack: Vec::new(),
+            started: false,
+        }
+    }
+
+    /// Cursor over the records of the index found by the index filter,
+    /// without looking up the table rows
+    pub(super) fn entries(filter: CellFilter) -> Result<Self> {
+        let Some(index_root_page) = filter.index_root_page else {
+            bail!("index records need an index filter");
+        };
+        Ok(Self {
+            root_page: index_root_page,
+            table_root_page: 0,
+            filter: Some(filter),
+            use_cache: true,
+            backward: false,
+            stack: Vec::new(),
+            started: false,
+        })
+    }
+
+    /// Cursor over the records of the index b-tree with the root `page` starting with
+    /// the `prefix` values, in key order. WITHOUT ROWID tables are stored in index b-trees
+    /// keyed by the primary key. Fields are in the `descending` order where it is true.
+    pub(super) fn records(page: u64, prefix: &[ColumnContent], descending: &[bool]) -> Self {
+        let bound = Bound::Included(prefix.to_vec());
+        Self {
+            root_page: page,
+            table_root_page: 0,
+            filter: Some(CellFilter::index(
+                page,
+                bound.clone(),
+                bound,
+                descending.to_vec(),
+            )),
+            use_cache: !prefix.is_empty(),
+            backward: false,
+            stack: Vec::new(),
+            started: false,
+        }
+    }
+
+    /// Reads the entries in the reverse order of the keys
+    pub(super) fn backward(mut self, backward: bool) -> Self {
+        self.backward = backward;
+        self
+    }
+
+    /// Returns the next row of the table, looked up by the row_id (the last field)
+    /// of the next index record for an index filter
+    pub(super) fn next_row<T>(&mut self, pager: &mut Pager<T>) -> Result<Option<TableLeafCell>>
+    where
+        T: Read + Seek,
+    {
+        while let Some(entry) = self.next_entry(pager)? {
+            match entry {
+                Entry::Row(cell) => return Ok(Some(cell)),
+                Entry::Record(mut record) => {
+                    let Some(ColumnContent::Int(row_id)) = record.pop() else {
+                        bail!("index record without row_id");
+                    };
+                    let row = Tree::new(pager)
+                        .row(self.table_root_page, row_id)
+                        .with_context(|| {
+                            format!(
+                                "look up row_id {row_id} in table root page {}",
+                                self.table_root_page
+                            )
+                        })?;
+                    if row.is_some() {
+                        return Ok(row);
                     }
                 }
-                Cell::IndexLeaf(index_leaf_cell) => {
-                    if let Some(f) = filter {
-                        if f.val == index_leaf_cell.key()?.to_string() {
-                            // find the page with the row_id and get the cell
-                            let table_root_page_num = self.table_root_page;
-                            let mut row_id_filter = f.clone();
-                            row_id_filter.col_i = row_id_filter.primary_key_col_i; // serarching by row_id (primary key)
-                            row_id_filter.val = index_leaf_cell.row_id()?.to_string();
-
-                            self.load_page_cells(
-                                table_root_page_num,
-                                &Some(row_id_filter),
-                            )
-                            .with_context(|| {
-                                format!("load cells for table root page filtered by row_id {table_root_page_num}")
-                            })?;
-                        }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the next record of the index b-tree
+    pub(super) fn next_record<T>(
+        &mut self,
+        pager: &mut Pager<T>,
+    ) -> Result<Option<Vec<ColumnContent>>>
+    where
+        T: Read + Seek,
+    {
+        match self.next_entry(pager)? {
+            Some(Entry::Record(record)) => Ok(Some(record)),
+            Some(Entry::Row(_)) => bail!("table b-tree page in an index b-tree"),
+            None => Ok(None),
+        }
+    }
+
+    fn next_entry<T>(&mut self, pager: &mut Pager<T>) -> Result<Option<Entry>>
+    where
+        T: Read + Seek,
+    {
+        if !self.started {
+            self.started = true;
+            self.push_page(pager, self.root_page)
+                .with_context(|| format!("load tree cells for root page {}", self.root_page))?;
+        }
+
+        while let Some(frame) = self.stack.last_mut() {
+            let item = match self.backward {
+                true => frame.items.next_back(),
+                false => frame.items.next(),
+            };
+            let Some(item) = item else {
+                self.stack.pop();
+                continue;
+            };
+            match (frame.item(item)?, &self.filter) {
+                (Item::Entry(Entry::Row(cell)), Some(f)) => {
+                    let value = match f.column {
+                        Some(column) => cell.column(column, f.rowid_column)?,
+                        None => ColumnContent::Int(cell.row_id() as i64),
+                    };
+                    if f.contains(&[value]) {
+                        return Ok(Some(Entry::Row(cell)));
                     }
                 }
-                Cell::IndexInterior(index_interior_cell) => {
-                    if let Some(f) = filter {
-                        if index_interior_cell.key()?.to_string() >= f.val {
-                            if index_interior_cell.key()?.to_string() == f.val {
-                                // find the page with the row_id and get the cell
-                                let table_root_page_num = self.table_root_page;
-                                let mut row_id_filter = f.clone();
-                                row_id_filter.col_i = row_id_filter.primary_key_col_i; // serarching by row_id (primary key)
-                                row_id_filter.val = index_interior_cell.row_id()?.to_string();
-
-                                self.load_page_cells(
-                                    table_root_page_num,
-                                    &Some(row_id_filter),
-                                )
-                                .with_context(|| {
-                                    format!("load cells for table root page filtered by row_id {table_root_page_num}")
-                                })?;
-                            }
-
-                            // we either found searched key or are behind it => read from child page
-                            let page_num = index_interior_cell.left_child_page();
-                            self.load_page_cells(page_num as u64, filter)
-                                .with_context(|| {
-                                    format!("load cells for index child page {page_num}")
-                                })?;
-
-                            if index_interior_cell.key()?.to_string() > f.val {
-                                break;
-                            }
-                            // if we read last cell on the page and didn't find anything greater than searched value,
-                            // move on to the page on the right side of the tree if some
-                            if i == page.n_cells as usize - 1 {
-                                if let Some(page_num) = page.rightmost_pointer {
-                                    self.load_page_cells(page_num as u64, filter).with_context(
-                                        || {
-                                            format!(
-                                        "load cells for right-most index interior page {page_num}"
-                                    )
-                                        },
-                                    )?;
-                                }
-                            }
-                        } else {
-                            // skipping everything lesser the the searched value
-                            continue;
-                        }
+                (Item::Entry(Entry::Record(key)), Some(f)) => {
+                    if f.contains(&key) {
+                        return Ok(Some(Entry::Record(key)));
                     }
                 }
+                (Item::Entry(entry @ Entry::Row(_)), None) => return Ok(Some(entry)),
+                (Item::Entry(Entry::Record(_)), None) => {}
+                (Item::Child(page), _) => self
+                    .push_page(pager, page as u64)
+                    .with_context(|| format!("load cells for child page {page}"))?,
             }
         }
 
+        Ok(None)
+    }
+
+    /// Adds the page to the path with the items that can have the searched keys,
+    /// found by binary search of its cells
+    fn push_page<T>(&mut self, pager: &mut Pager<T>, page: u64) -> Result<()>
+    where
+        T: Read + Seek,
+    {
+        let page = pager
+            .get_page(page, self.use_cache)
+            .with_context(|| format!("get page {page}"))?;
+        let table = matches!(
+            page.page_type,
+            PageType::TableInterior | PageType::TableLeaf
+        );
+
+        // cells from `first` to `last` have keys between the bounds, children of interior
+        // pages are between the keys of the cells around them
+        let cells = page.len();
+        let (first, last) = match &self.filter {
+            Some(f) if !(table && f.column.is_some()) => (
+                partition(&page, |key| f.is_below(key))?,
+                partition(&page, |key| match page.page_type {
+                    // row_ids are unique, the child after the key has only greater ones
+                    PageType::TableInterior => !f.reaches_upper(key),
+                    _ => !f.is_above(key),
+                })?,
+            ),
+            // full table scan
+            _ => (0, cells),
+        };
+        let items = match page.page_type {
+            PageType::TableLeaf | PageType::IndexLeaf => first..last.max(first),
+            PageType::TableInterior => first..last.max(first) + 1,
+            PageType::IndexInterior => 2 * first..2 * last.max(first) + 1,
+        };
+
+        self.stack.push(Frame { page, items });
         Ok(())
     }
 }
 
+/// Returns the number of the first cells of the page with the keys matching the predicate,
+/// the cells are in the order of the keys so the predicate matches no cell after them
+fn partition(page: &Page, predicate: impl Fn(&[ColumnContent]) -> bool) -> Result<usize> {
+    let (mut low, mut high) = (0, page.len());
+    while low < high {
+        let middle = (low + high) / 2;
+        let key = match page.cell(middle)?.context("cell out of the page")? {
+            Cell::TableLeaf(cell) => vec![ColumnContent::Int(cell.row_id() as i64)],
+            Cell::TableInterior(cell) => vec![ColumnContent::Int(cell.row_id() as i64)],
+            Cell::IndexLeaf(cell) => cell.values()?,
+            Cell::IndexInterior(cell) => cell.values()?,
+        };
+        match predicate(&key) {
+            true => low = middle + 1,
+            false => high = middle,
+        }
+    }
+    Ok(low)
+}
+
+/// Values of a column (or the row_id) the cells are looked up for.
+///
+/// Index keys are compared field by field with the bounds, only the first fields of the key
+/// are compared if a bound has less values than the key (a prefix of a multi-column index).
+/// The bounds are in the order of the keys, for a DESC column the lower bound has the greater value.
 #[derive(Clone, Debug)]
 pub(super) struct CellFilter {
     index_root_page: Option<u64>,
-    col_i: u16,
-    val: String,
-    primary_key_col_i: u16,
+    /// column compared with the values, None for the row_id
+    column: Option<u16>,
+    lower: Bound<Vec<ColumnContent>>,
+    upper: Bound<Vec<ColumnContent>>,
+    /// fields of the index key stored in descending order
+    descending: Vec<bool>,
+    rowid_column: Option<u16>,
 }
 
 impl CellFilter {
+    /// Filter for the value equal to `val`
     pub(super) fn new(
         index_root_page: Option<u64>,
-        col_i: u16,
-        val: String,
-        primary_key_col_i: u16,
+        column: Option<u16>,
+        val: ColumnContent,
+        rowid_column: Option<u16>,
+    ) -> Self {
+        Self::range(
+            index_root_page,
+            column,
+            Bound::Included(val.clone()),
+            Bound::Included(val),
+            rowid_column,
+        )
+    }
+
+    /// Filter for the values between the bounds
+    pub(super) fn range(
+        index_root_page: Option<u64>,
+        column: Option<u16>,
+        lower: Bound<ColumnContent>,
+        upper: Bound<ColumnContent>,
+        rowid_column: Option<u16>,
     ) -> Self {
         Self {
             index_root_page,
-            col_i,
-            val,
-            primary_key_col_i,
+            column,
+            lower: lower.map(|val| vec![val]),
+            upper: upper.map(|val| vec![val]),
+            descending: Vec::new(),
+            rowid_column,
+        }
+    }
+
+    /// Filter for the index keys between the bounds
+    pub(super) fn index(
+        index_root_page: u64,
+        lower: Bound<Vec<ColumnContent>>,
+        upper: Bound<Vec<ColumnContent>>,
+        descending: Vec<bool>,
+    ) -> Self {
+        Self {
+            index_root_page: Some(index_root_page),
+            column: None,
+            lower,
+            upper,
+            descending,
+            rowid_column: None,
+        }
+    }
+
+    /// Returns true if a bound is NULL, nothing is equal, lower or greater than NULL
+    pub(super) fn has_null_bound(&self) -> bool {
+        [&self.lower, &self.upper]
+            .into_iter()
+            .any(|bound| match bound {
+                Bound::Included(values) | Bound::Excluded(values) => {
+                    values.contains(&ColumnContent::Null)
+                }
+                Bound::Unbounded => false,
+            })
+    }
+
+    fn contains(&self, key: &[ColumnContent]) -> bool {
+        !self.is_below(key) && !self.is_above(key)
+    }
+
+    /// The key is before the lower bound
+    fn is_below(&self, key: &[ColumnContent]) -> bool {
+        match &self.lower {
+            Bound::Included(lower) => self.compare(key, lower).is_lt(),
+            Bound::Excluded(lower) => self.compare(key, lower).is_le(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    /// The key is behind the upper bound
+    fn is_above(&self, key: &[ColumnContent]) -> bool {
+        match &self.upper {
+            Bound::Included(upper) => self.compare(key, upper).is_gt(),
+            Bound::Excluded(upper) => self.compare(key, upper).is_ge(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    /// All keys behind this key are above the upper bound
+    fn reaches_upper(&self, key: &[ColumnContent]) -> bool {
+        match &self.upper {
+            Bound::Included(upper) | Bound::Excluded(upper) => self.compare(key, upper).is_ge(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    fn compare(&self, key: &[ColumnContent], bound: &[ColumnContent]) -> Ordering {
+        compare_key(key, bound, &self.descending)
+    }
+}
+
+/// Compares first fields of the key, as many as the bound has, with the bound in the order
+/// of the keys, fields marked as `descending` are in the reverse order
+fn compare_key(key: &[ColumnContent], bound: &[ColumnContent], descending: &[bool]) -> Ordering {
+    for (i, (field, value)) in key.iter().zip(bound).enumerate() {
+        let ordering = match descending.get(i) {
+            Some(true) => value.cmp(field),
+            _ => field.cmp(value),
+        };
+        if ordering.is_ne() {
+            return ordering;
         }
     }
+    Ordering::Equal
 }
@@ -94,7 +94,7 @@ where
         ColumnType::Int0(0)
     } else if v == 9 {
         ColumnType::Int1(0)
-    } else if v > 12 {
+    } else if v >= 12 {
         if v % 2 == 0 {
             ColumnType::Blob((v - 12) / 2) // BLOB: v = (n*2) + 12 => n = (v - 12) / 2
         } else {
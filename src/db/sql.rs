@@ -1,232 +1,1033 @@
+mod cte;
+mod eval;
+mod join;
+mod operator;
+mod order;
 mod parser;
+mod plan;
 
-use anyhow::{anyhow, bail, Context, Result};
-use nom::branch::alt;
+use std::collections::BTreeSet;
+use std::rc::Rc;
 
-use super::{
-    pager::{self, Tree},
-    schema::SchemaType,
-};
-use crate::db::DB;
+use anyhow::{bail, Context, Result};
+
+use crate::db::{page::ColumnContent, View, DB};
+use eval::{Row, Scope};
+use operator::{Aggregate, Aggregation, Chain, Operator, Output, Project, Sort, Values};
+use order::SortKey;
 use parser::*;
 
+pub use parser::SyntaxError;
+pub(crate) use parser::{Affinity, Generated, IndexDef};
+pub use plan::{PlanIndex, PlanStep, QueryPlan};
+
+/// Largest parameter number `?NNN`, the default SQLITE_MAX_VARIABLE_NUMBER
+const MAX_PARAMETER_NUMBER: usize = 32766;
+
 pub(crate) fn parse_command(sql: &str) -> Result<Command> {
-    let parsed = match alt((parse_select, parse_create_table, parse_create_index))(sql) {
-        Ok((_, parsed)) => parsed,
-        Err(err) => bail!("Error parsing SQL: {:?}", err),
-    };
+    parse_sql(sql, parse_statement)
+}
 
-    let columns = parsed.columns;
-    let table = parsed.table;
+/// Returns names of the view columns, either listed in the view definition or taken from the select
+pub(crate) fn view_columns(db: &DB, view: &View) -> Result<Vec<String>> {
+    let names = view.select.column_names(db, None)?;
+    if view.columns.is_empty() {
+        return Ok(names);
+    }
+    if view.columns.len() != names.len() {
+        bail!(
+            "expected {} columns for '{}' but got {}",
+            view.columns.len(),
+            view.name,
+            names.len()
+        );
+    }
+    Ok(view.columns.clone())
+}
 
-    let where_cond = if let Some(cond) = parsed.where_cond {
-        Some(Condition {
-            column: cond.column,
-            value: cond.value,
-        })
-    } else {
-        None
-    };
+/// Result rows of a query, each of them is computed when it is needed
+pub struct Rows<'a> {
+    db: &'a mut DB,
+    operator: Box<dyn Operator>,
+}
 
-    let command = match parsed.command {
-        ParsedCommand::Count => Command::Count {
-            table,
-            column: columns
-                .first()
-                .ok_or(anyhow!("Count command is missing column field"))?
-                .to_string(),
-            where_cond,
-        },
-        ParsedCommand::Select => Command::Select {
-            columns,
-            table,
-            where_cond,
-        },
-        ParsedCommand::CreateTable(pk) => Command::CreateTable {
-            columns,
-            table,
-            primary_key: pk,
-        },
-        ParsedCommand::CreateIndex => Command::CreateIndex { columns, table },
-    };
+impl Iterator for Rows<'_> {
+    type Item = Result<Vec<ColumnContent>>;
 
-    Ok(command)
+    fn next(&mut self) -> Option<Self::Item> {
+        self.operator.next(self.db).transpose()
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub(crate) enum Command {
-    CreateTable {
+    CreateTable(TableDef),
+    CreateIndex(IndexDef),
+    /// CREATE VIEW name (columns) AS SELECT ...
+    CreateView {
+        name: String,
         columns: Vec<String>,
-        table: String,
-        /// primary key column index
-        primary_key: u16,
-    },
-    CreateIndex {
-        columns: Vec<String>,
-        table: String,
+        select: Box<Command>,
     },
     Select {
-        columns: Vec<String>,
-        table: String,
-        where_cond: Option<Condition>, // WHERE color = 'Yellow'
+        columns: Vec<ResultColumn>,
+        from: Option<FromClause>, // FROM apples JOIN oranges ON apples.id = oranges.id
+        where_cond: Option<Expr>, // WHERE color = 'Yellow'
     },
     Count {
         column: String,
-        table: String,
-        where_cond: Option<Condition>, // WHERE color = 'Yellow'
+        from: Option<FromClause>,
+        where_cond: Option<Expr>, // WHERE color = 'Yellow'
     },
-}
-
-#[derive(Debug, PartialEq)]
-pub struct Condition {
-    column: String,
-    value: String,
+    /// SELECT MIN(column) or SELECT MAX(column)
+    MinMax {
+        max: bool,
+        column: String,
+        from: Option<FromClause>,
+        where_cond: Option<Expr>,
+    },
+    /// SELECT ... UNION [ALL] | INTERSECT | EXCEPT SELECT ...
+    Compound {
+        op: CompoundOperator,
+        left: Box<Command>,
+        right: Box<Command>,
+    },
+    /// SELECT ... ORDER BY ... LIMIT ...
+    Ordered {
+        select: Box<Command>,
+        order_by: Vec<OrderingTerm>,
+        limit: Option<Limit>,
+    },
+    /// WITH [RECURSIVE] name AS (SELECT ...) SELECT ...
+    With {
+        recursive: bool,
+        ctes: Vec<Cte>,
+        select: Box<Command>,
+    },
+    /// EXPLAIN QUERY PLAN SELECT ...
+    ExplainQueryPlan(Box<Command>),
 }
 
 impl Command {
-    pub fn table(&self) -> &str {
-        match &self {
-            Command::Select { table, .. } => table,
-            Command::Count { table, .. } => table,
-            Command::CreateTable { table, .. } => table,
-            Command::CreateIndex { table, .. } => table,
+    pub fn execute(&self, db: &mut DB) -> Result<Vec<Vec<String>>> {
+        self.rows(db)?
+            .map(|row| Ok(row?.iter().map(|value| value.to_string()).collect()))
+            .collect()
+    }
+
+    /// Returns the result rows of the query, they are read as they are iterated over
+    pub(crate) fn rows<'a>(&self, db: &'a mut DB) -> Result<Rows<'a>> {
+        let operator = self.operator(db, None)?;
+        Ok(Rows { db, operator })
+    }
+
+    /// Executes the query and returns names of the result columns and the result rows.
+    ///
+    /// Subqueries get the scope and the current row of the enclosing query in `outer`.
+    fn query(
+        &self,
+        db: &mut DB,
+        outer: Option<(&Scope, &[ColumnContent])>,
+    ) -> Result<(Vec<String>, Vec<Row>)> {
+        if let Command::With { ctes, select, .. } = self {
+            let select = cte::expand(db, ctes, select)?;
+            return select.query(db, outer);
         }
+
+        let names = self.column_names(db, outer.map(|(scope, _)| scope))?;
+        let rows = self.operator(db, outer)?;
+        Ok((names, operator::collect(db, rows)?))
     }
 
-    pub fn execute(&self, db: &mut DB) -> Result<Vec<Vec<String>>> {
-        match self {
+    /// Returns the operator returning the result rows of the query as they are read
+    fn operator(
+        &self,
+        db: &mut DB,
+        outer: Option<(&Scope, &[ColumnContent])>,
+    ) -> Result<Box<dyn Operator>> {
+        Ok(match self {
             Command::Select {
                 columns,
+                from,
                 where_cond,
-                ..
-            } => Ok(Self::select_columns(db, columns, where_cond, self.table())?),
-            Command::Count { where_cond, .. } => Ok(Self::count(db, self.table(), where_cond)?),
+            } => Self::select_columns(db, columns, from.as_ref(), where_cond, &[], None, outer)?,
+            Command::Count {
+                column,
+                from,
+                where_cond,
+            } => Self::count(db, column, from.as_ref(), where_cond, outer)?,
+            Command::MinMax {
+                max,
+                column,
+                from,
+                where_cond,
+            } => Self::min_max(db, *max, column, from.as_ref(), where_cond, outer)?,
+            Command::Compound { op, left, right } => Self::compound(db, *op, left, right, outer)?,
+            Command::Ordered {
+                select,
+                order_by,
+                limit,
+            } => {
+                let names = select.column_names(db, outer.map(|(scope, _)| scope))?;
+                Self::ordered(db, select, order_by, limit.as_ref(), &names, outer)?
+            }
+            Command::With { ctes, select, .. } => {
+                let select = cte::expand(db, ctes, select)?;
+                select.operator(db, outer)?
+            }
+            Command::ExplainQueryPlan(select) => {
+                let steps = select.plan(db, outer.map(|(scope, _)| scope))?;
+                let rows = QueryPlan { steps }
+                    .rows()
+                    .into_iter()
+                    .map(|(id, parent, detail)| {
+                        vec![
+                            ColumnContent::Int(id as i64),
+                            ColumnContent::Int(parent as i64),
+                            ColumnContent::Int(0),
+                            ColumnContent::Text(detail),
+                        ]
+                    })
+                    .collect();
+                Box::new(Values::new(rows))
+            }
             Command::CreateTable { .. } => {
                 unimplemented!("CREATE TABLE command is not implemented")
             }
             Command::CreateIndex { .. } => {
                 unimplemented!("CREATE INDEX command is not implemented")
             }
+            Command::CreateView { .. } => bail!("CREATE VIEW is not supported"),
+        })
+    }
+
+    /// Returns names of the result columns of the query
+    fn column_names(&self, db: &DB, outer: Option<&Scope>) -> Result<Vec<String>> {
+        match self {
+            Command::Select { columns, from, .. } => {
+                let scope = join::scope(db, from.as_ref(), outer)?;
+                let mut names = Vec::new();
+                for column in columns {
+                    match column {
+                        ResultColumn::All => names
+                            .extend(scope.all().into_iter().map(|i| scope.name(i).1.to_string())),
+                        ResultColumn::AllFrom(table) => names.extend(
+                            scope
+                                .all_from(table)?
+                                .into_iter()
+                                .map(|i| scope.name(i).1.to_string()),
+                        ),
+                        ResultColumn::Expr { expr, alias } => names.push(match (expr, alias) {
+                            (_, Some(alias)) => alias.clone(),
+                            (Expr::Column(column), None) => column.column.clone(),
+                            (expr, None) => expr.to_string(),
+                        }),
+                    }
+                }
+                Ok(names)
+            }
+            Command::Count { column, .. } => Ok(vec![format!("count({column})")]),
+            Command::MinMax { max, column, .. } => Ok(vec![match max {
+                true => format!("max({column})"),
+                false => format!("min({column})"),
+            }]),
+            Command::Compound { left, .. } => left.column_names(db, outer),
+            Command::Ordered { select, .. } => select.column_names(db, outer),
+            Command::With { ctes, select, .. } => cte::inline(ctes, select).column_names(db, outer),
+            Command::ExplainQueryPlan(_) => Ok(["id", "parent", "notused", "detail"]
+                .map(String::from)
+                .to_vec()),
+            Command::CreateTable { .. }
+            | Command::CreateIndex { .. }
+            | Command::CreateView { .. } => Ok(Vec::new()),
         }
     }
 
+    /// Returns true if the query refers to columns of the enclosing queries in `outer` scope,
+    /// such subquery has to be executed for every row of the enclosing query
+    fn is_correlated(&self, db: &DB, outer: &Scope) -> Result<bool> {
+        Ok(self
+            .outer_depth(db, outer)?
+            .is_some_and(|depth| depth <= outer.depth()))
+    }
+
+    /// Returns the lowest nesting level of the columns the query refers to,
+    /// None if it refers only to its own columns
+    fn outer_depth(&self, db: &DB, outer: &Scope) -> Result<Option<usize>> {
+        let (from, where_cond, exprs) = match self {
+            Command::Select {
+                columns,
+                from,
+                where_cond,
+            } => {
+                let exprs = columns
+                    .iter()
+                    .filter_map(|c| match c {
+                        ResultColumn::Expr { expr, .. } => Some(expr),
+                        _ => None,
+                    })
+                    .collect();
+                (from, where_cond, exprs)
+            }
+            Command::Count {
+                from, where_cond, ..
+            }
+            | Command::MinMax {
+                from, where_cond, ..
+            } => (from, where_cond, Vec::new()),
+            Command::Compound { left, right, .. } => {
+                let left = left.outer_depth(db, outer)?;
+                let right = right.outer_depth(db, outer)?;
+                return Ok(left.into_iter().chain(right).min());
+            }
+            Command::Ordered { select, .. } | Command::ExplainQueryPlan(select) => {
+                return select.outer_depth(db, outer)
+            }
+            Command::With { ctes, select, .. } => {
+                return cte::inline(ctes, select).outer_depth(db, outer)
+            }
+            Command::CreateTable { .. }
+            | Command::CreateIndex { .. }
+            | Command::CreateView { .. } => return Ok(None),
+        };
+
+        let scope = join::scope(db, from.as_ref(), Some(outer))?;
+        let mut exprs: Vec<&Expr> = exprs;
+        exprs.extend(where_cond);
+        exprs.extend(from.iter().flat_map(|from| &from.joins).filter_map(
+            |j| match &j.constraint {
+                Some(JoinConstraint::On(expr)) => Some(expr),
+                _ => None,
+            },
+        ));
+
+        let mut depth = None;
+        for expr in exprs {
+            for column in eval::column_refs(expr) {
+                let column_depth = scope.column_depth(scope.resolve(column)?);
+                if column_depth < scope.depth() {
+                    depth = Some(depth.map_or(column_depth, |d: usize| d.min(column_depth)));
+                }
+            }
+            for select in eval::subqueries(expr) {
+                if let Some(select_depth) = select.outer_depth(db, &scope)? {
+                    if select_depth < scope.depth() {
+                        depth = Some(depth.map_or(select_depth, |d: usize| d.min(select_depth)));
+                    }
+                }
+            }
+        }
+
+        Ok(depth)
+    }
+
     fn select_columns(
         db: &mut DB,
-        column_names: &[String],
-        cond: &Option<Condition>,
-        table: &str,
-    ) -> Result<Vec<Vec<String>>> {
+        columns: &[ResultColumn],
+        from: Option<&FromClause>,
+        cond: &Option<Expr>,
+        order_by: &[(SortKey, bool)],
+        limit: Option<usize>,
+        outer: Option<(&Scope, &[ColumnContent])>,
+    ) -> Result<Box<dyn Operator>> {
         // SELECT name FROM apples"
         // SELECT id, name FROM apples"
         // SELECT * FROM apples"
+        // SELECT a.name, o.* FROM apples a JOIN oranges o ON o.id = a.id"
+        // SELECT name FROM apples WHERE id IN (SELECT id FROM oranges)"
 
-        let tbl_name = table;
-        let table = db
-            .table(tbl_name)
-            .with_context(|| format!("get schema columns for table {}", tbl_name))?;
-
-        let mut col_indices = Vec::new();
-
-        if column_names.len() == 1 && column_names.contains(&"*".to_string()) {
-            // SELECT * FROM ... (all columns)
-            col_indices = table
-                .columns
-                .iter()
-                .enumerate()
-                .map(|(i, _)| i as u16)
-                .collect();
-        } else {
-            // Select only specified columns
-            for col_name in column_names {
-                let Some(col_index) = table.columns.iter().position(|c| c == col_name) else {
-                    bail!("Error: column '{col_name}' is not in the table")
-                };
-                col_indices.push(col_index as u16);
+        // the rows are not sorted if they are read in the order of the ORDER BY terms
+        let order_exprs = Self::order_exprs(db, columns, from, order_by, outer.map(|(s, _)| s))?;
+        let used = Self::used_exprs(db, columns, from, outer.map(|(s, _)| s))?;
+        let (scope, rows, ordered) =
+            join::ordered_rows(db, from, cond.as_ref(), outer, &order_exprs, Some(&used))?;
+        let order_by = if ordered { &[] } else { order_by };
+
+        let mut outputs = Vec::new();
+        for column in columns {
+            match column {
+                // SELECT * FROM ... (all columns)
+                ResultColumn::All => outputs.extend(scope.all().into_iter().map(Output::Column)),
+                ResultColumn::AllFrom(table) => {
+                    outputs.extend(scope.all_from(table)?.into_iter().map(Output::Column))
+                }
+                ResultColumn::Expr { expr, .. } => {
+                    for column in eval::column_refs(expr) {
+                        scope.resolve(column)?;
+                    }
+                    outputs.push(Output::Expr(eval::prepare(db, expr, &scope)?))
+                }
             }
         }
 
-        // WHERE condition
-        let mut filter = None;
-        if let Some(cond) = cond {
-            if let Some(cond_col_i) = table.columns.iter().position(|c| *c == cond.column) {
-                let mut index_root_page = None;
+        // values of the sort keys follow the values of the result columns
+        let mut keys = Vec::new();
+        for (key, _) in order_by {
+            keys.push(match key {
+                SortKey::Column(i) => match &outputs[*i] {
+                    Output::Column(column) => Output::Column(*column),
+                    Output::Expr(expr) => Output::Expr(expr.clone()),
+                },
+                SortKey::Expr(expr) => {
+                    for column in eval::column_refs(expr) {
+                        scope.resolve(column)?;
+                    }
+                    Output::Expr(eval::prepare(db, expr, &scope)?)
+                }
+            });
+        }
+        outputs.extend(keys);
 
-                // get index schema if index exists
-                let index = db.index_on_column(tbl_name, &cond.column);
+        let rows = Box::new(Project::new(rows, outputs, scope));
+        if order_by.is_empty() {
+            return Ok(rows);
+        }
+        let descending = order_by.iter().map(|(_, descending)| *descending).collect();
+        Ok(Box::new(Sort::new(rows, descending, limit)))
+    }
 
-                if let Some(index_schema) = index {
-                    index_root_page = Some(index_schema.rootpage);
+    /// Returns the ORDER BY terms as expressions of the columns of the tables
+    fn order_exprs(
+        db: &DB,
+        columns: &[ResultColumn],
+        from: Option<&FromClause>,
+        order_by: &[(SortKey, bool)],
+        outer: Option<&Scope>,
+    ) -> Result<Vec<(Expr, bool)>> {
+        if order_by.is_empty() {
+            return Ok(Vec::new());
+        }
+        let scope = join::scope(db, from, outer)?;
+        let mut exprs = Vec::new();
+        for column in columns {
+            let indices = match column {
+                ResultColumn::All => scope.all(),
+                ResultColumn::AllFrom(table) => scope.all_from(table)?,
+                ResultColumn::Expr { expr, .. } => {
+                    exprs.push(expr.clone());
+                    continue;
                 }
+            };
+            exprs.extend(indices.into_iter().map(|i| {
+                let (table, column) = scope.name(i);
+                Expr::column(Some(table), column)
+            }));
+        }
 
-                filter = Some(pager::CellFilter::new(
-                    index_root_page,
-                    cond_col_i as u16,
-                    cond.value.to_lowercase(),
-                    table.primary_key_column_index,
-                ));
+        Ok(order_by
+            .iter()
+            .map(|(key, descending)| {
+                let expr = match key {
+                    SortKey::Column(i) => exprs[*i].clone(),
+                    SortKey::Expr(expr) => expr.clone(),
+                };
+                (expr, *descending)
+            })
+            .collect())
+    }
+
+    /// Returns the expressions of the result columns with `*` expanded to the columns
+    /// it selects, columns not used by the result may be left out
+    fn used_exprs(
+        db: &DB,
+        columns: &[ResultColumn],
+        from: Option<&FromClause>,
+        outer: Option<&Scope>,
+    ) -> Result<Vec<Expr>> {
+        let all = |column: &ResultColumn| !matches!(column, ResultColumn::Expr { .. });
+        let scope = match columns.iter().any(all) {
+            true => join::scope(db, from, outer)?,
+            false => Scope::default(),
+        };
+        let qualified = |indices: Vec<usize>| {
+            indices.into_iter().map(|i| {
+                let (table, name) = scope.name(i);
+                Expr::column(Some(table), name)
+            })
+        };
+
+        let mut exprs = Vec::new();
+        for column in columns {
+            match column {
+                ResultColumn::Expr { expr, .. } => exprs.push(expr.clone()),
+                ResultColumn::All => exprs.extend(qualified(scope.all())),
+                ResultColumn::AllFrom(table) => exprs.extend(qualified(scope.all_from(table)?)),
             }
         }
+        Ok(exprs)
+    }
 
-        let table_root_page = db.root_page_num(&table.name, SchemaType::Table)?;
+    /// COUNT is computed by the `Aggregate` operator from the rows of the query like
+    /// MIN and MAX, the commands only tell which aggregation it is. The rows of a table
+    /// without WHERE are counted from its b-tree pages without reading them.
+    fn count(
+        db: &mut DB,
+        column: &str,
+        from: Option<&FromClause>,
+        cond: &Option<Expr>,
+        outer: Option<(&Scope, &[ColumnContent])>,
+    ) -> Result<Box<dyn Operator>> {
+        // "SELECT COUNT(*) FROM apples"
 
-        let mut tree = Tree::new(&mut db.pager);
+        if column == "*" && cond.is_none() {
+            // rows of a table are counted without reading them
+            if let Some(count) = join::table_count(db, from, outer.map(|(scope, _)| scope))? {
+                let row = vec![ColumnContent::Int(count as i64)];
+                return Ok(Box::new(Values::new(vec![row])));
+            }
+        }
+
+        let used = match column {
+            "*" => Vec::new(),
+            column => vec![Expr::column(None, column)],
+        };
+        let (scope, rows) =
+            join::rows(db, from, cond.as_ref(), outer, Some(&used)).with_context(|| {
+                format!(
+                    "count rows of table {}",
+                    from.map_or("", |from| from.table.alias())
+                )
+            })?;
+
+        let aggregation = if column == "*" {
+            Aggregation::CountRows
+        } else {
+            // COUNT(column) counts only non NULL values
+            Aggregation::Count(scope.resolve(&ColumnRef {
+                table: None,
+                column: column.to_string(),
+            })?)
+        };
+
+        Ok(Box::new(Aggregate::new(rows, aggregation)))
+    }
 
-        let mut result = Vec::new();
-        for cell in tree.cells(table_root_page, filter)? {
-            let mut row = Vec::new();
-            for index in col_indices.iter() {
-                let s = cell.column(*index, table.primary_key_column_index)?;
-                row.push(s.to_string());
+    /// MIN and MAX are computed by the `Aggregate` operator from the rows of the query,
+    /// without WHERE the value of an indexed column is read from the edge of the index instead
+    fn min_max(
+        db: &mut DB,
+        max: bool,
+        column: &str,
+        from: Option<&FromClause>,
+        cond: &Option<Expr>,
+        outer: Option<(&Scope, &[ColumnContent])>,
+    ) -> Result<Box<dyn Operator>> {
+        // "SELECT MAX(id) FROM apples"
+
+        if cond.is_none() {
+            // the first or the last key of a b-tree ordered by the column
+            let scope = outer.map(|(scope, _)| scope);
+            if let Some(value) = join::table_extremum(db, from, scope, column, max)? {
+                return Ok(Box::new(Values::new(vec![vec![value]])));
             }
-            result.push(row);
         }
 
-        Ok(result)
+        let used = [Expr::column(None, column)];
+        let (scope, rows) = join::rows(db, from, cond.as_ref(), outer, Some(&used))?;
+        let column = scope.resolve(&ColumnRef {
+            table: None,
+            column: column.to_string(),
+        })?;
+
+        Ok(Box::new(Aggregate::new(
+            rows,
+            Aggregation::Extremum { column, max },
+        )))
     }
 
-    fn count(db: &mut DB, table: &str, cond: &Option<Condition>) -> Result<Vec<Vec<String>>> {
-        // "SELECT COUNT(*) FROM apples"
+    fn compound(
+        db: &mut DB,
+        op: CompoundOperator,
+        left: &Command,
+        right: &Command,
+        outer: Option<(&Scope, &[ColumnContent])>,
+    ) -> Result<Box<dyn Operator>> {
+        // SELECT name FROM apples UNION SELECT name FROM oranges
+
+        let outer_scope = outer.map(|(scope, _)| scope);
+        let left_names = left.column_names(db, outer_scope)?;
+        let right_names = right.column_names(db, outer_scope)?;
+        if left_names.len() != right_names.len() {
+            bail!(
+                "SELECTs to the left and right of {} do not have the same number of result columns",
+                op
+            );
+        }
+
+        let left = left.operator(db, outer)?;
+        let right = right.operator(db, outer)?;
+        if op == CompoundOperator::UnionAll {
+            return Ok(Box::new(Chain::new(vec![left, right])));
+        }
+
+        // all operators except UNION ALL return distinct rows in sorted order
+        let rows = operator::collect(db, left)?;
+        let right_rows = operator::collect(db, right)?;
+        let rows: BTreeSet<_> = match op {
+            CompoundOperator::UnionAll | CompoundOperator::Union => {
+                rows.into_iter().chain(right_rows).collect()
+            }
+            CompoundOperator::Intersect => {
+                let right: BTreeSet<_> = right_rows.into_iter().collect();
+                rows.into_iter().filter(|row| right.contains(row)).collect()
+            }
+            CompoundOperator::Except => {
+                let right: BTreeSet<_> = right_rows.into_iter().collect();
+                rows.into_iter()
+                    .filter(|row| !right.contains(row))
+                    .collect()
+            }
+        };
+
+        Ok(Box::new(Values::new(rows.into_iter().collect())))
+    }
+
+    fn ordered(
+        db: &mut DB,
+        select: &Command,
+        order_by: &[OrderingTerm],
+        limit: Option<&Limit>,
+        names: &[String],
+        outer: Option<(&Scope, &[ColumnContent])>,
+    ) -> Result<Box<dyn Operator>> {
+        // SELECT name FROM apples ORDER BY color DESC LIMIT 2
+        // SELECT name FROM apples UNION SELECT name FROM oranges ORDER BY 1 LIMIT 3 OFFSET 1
+
+        // only the rows up to the end of the limit are kept while sorting
+        let limit = limit
+            .map(|limit| order::limit_values(db, limit))
+            .transpose()?;
+        let sort_limit =
+            limit.and_then(|(count, offset)| count.map(|count| count.saturating_add(offset)));
+
+        let rows = match select {
+            // simple select can be sorted by any column of the tables in the FROM clause
+            Command::Select {
+                columns,
+                from,
+                where_cond,
+            } => {
+                let order_by = order::sort_keys(order_by, names, true)?;
+                Self::select_columns(
+                    db,
+                    columns,
+                    from.as_ref(),
+                    where_cond,
+                    &order_by,
+                    sort_limit,
+                    outer,
+                )?
+            }
+            select => {
+                let order_by = order::sort_keys(order_by, names, false)?;
+                let rows = select.operator(db, outer)?;
+                // values of the sort keys follow the values of the row
+                let mut outputs: Vec<_> = (0..names.len()).map(Output::Column).collect();
+                for (key, _) in &order_by {
+                    outputs.push(match key {
+                        SortKey::Column(i) => Output::Column(*i),
+                        SortKey::Expr(_) => unreachable!("only result columns are allowed"),
+                    });
+                }
+                let rows = Box::new(Project::new(rows, outputs, Rc::default()));
+                let descending = order_by.iter().map(|(_, descending)| *descending).collect();
+                Box::new(Sort::new(rows, descending, sort_limit))
+            }
+        };
 
-        let tbl_name = table;
-        let table = db
-            .table(tbl_name)
-            .with_context(|| format!("get schema columns for table {}", tbl_name))?;
+        // rows behind the limit are not read unless they are sorted
+        Ok(match limit {
+            Some((count, offset)) => Box::new(operator::Limit::new(rows, count, offset)),
+            None => rows,
+        })
+    }
 
-        // WHERE condition
-        let mut filter = None;
-        if let Some(cond) = cond {
-            if let Some(cond_col_i) = table.columns.iter().position(|c| *c == cond.column) {
-                let mut index_root_page = None;
+    /// Returns the plan of the query, how its tables are read, the query of EXPLAIN QUERY PLAN
+    /// is planned itself
+    pub(crate) fn query_plan(&self, db: &mut DB) -> Result<QueryPlan> {
+        let select = match self {
+            Command::ExplainQueryPlan(select) => select,
+            select => select,
+        };
+        Ok(QueryPlan {
+            steps: select.plan(db, None)?,
+        })
+    }
+
+    /// Returns the steps of the query execution
+    fn plan(&self, db: &mut DB, outer: Option<&Scope>) -> Result<Vec<PlanStep>> {
+        let steps = match self {
+            Command::Select {
+                columns,
+                from,
+                where_cond,
+            } => {
+                let used = Self::used_exprs(db, columns, from.as_ref(), outer)?;
+                let (steps, _) = join::explain(
+                    db,
+                    from.as_ref(),
+                    where_cond.as_ref(),
+                    outer,
+                    &[],
+                    Some(&used),
+                )?;
+                steps
+            }
+            Command::Count {
+                column,
+                from,
+                where_cond,
+            } => {
+                let counted = match (column.as_str(), where_cond) {
+                    ("*", None) => join::explain_count(db, from.as_ref(), outer)?,
+                    _ => None,
+                };
+                if let Some(step) = counted {
+                    return Ok(vec![step]);
+                }
+                let used = match column.as_str() {
+                    "*" => Vec::new(),
+                    column => vec![Expr::column(None, column)],
+                };
+                let where_cond = where_cond.as_ref();
+                join::explain(db, from.as_ref(), where_cond, outer, &[], Some(&used))?.0
+            }
+            Command::MinMax {
+                column,
+                from,
+                where_cond,
+                ..
+            } => {
+                if where_cond.is_none() {
+                    if let Some(step) = join::explain_extremum(db, from.as_ref(), outer, column)? {
+                        return Ok(vec![step]);
+                    }
+                }
+                let used = [Expr::column(None, column)];
+                let where_cond = where_cond.as_ref();
+                join::explain(db, from.as_ref(), where_cond, outer, &[], Some(&used))?.0
+            }
+            Command::Compound { .. } => {
+                // SELECTs of the compound query from the left-most one
+                let mut parts = Vec::new();
+                let mut select = self;
+                while let Command::Compound { op, left, right } = select {
+                    parts.push((Some(*op), right.as_ref()));
+                    select = left;
+                }
+                parts.push((None, select));
+
+                let mut steps = Vec::new();
+                for (op, select) in parts.into_iter().rev() {
+                    let detail = match op {
+                        None => "LEFT-MOST SUBQUERY".to_string(),
+                        Some(CompoundOperator::UnionAll) => "UNION ALL".to_string(),
+                        Some(op) => format!("{op} USING TEMP B-TREE"),
+                    };
+                    let select_steps = select.plan(db, outer)?;
+                    steps.push(PlanStep::Nested {
+                        detail,
+                        steps: select_steps,
+                    });
+                }
+                vec![PlanStep::Nested {
+                    detail: "COMPOUND QUERY".to_string(),
+                    steps,
+                }]
+            }
+            Command::Ordered {
+                select, order_by, ..
+            } => {
+                let (mut steps, ordered) = match select.as_ref() {
+                    Command::Select {
+                        columns,
+                        from,
+                        where_cond,
+                    } => {
+                        let names = select.column_names(db, outer)?;
+                        let order_by = order::sort_keys(order_by, &names, true)?;
+                        let order_by =
+                            Self::order_exprs(db, columns, from.as_ref(), &order_by, outer)?;
+                        let used = Self::used_exprs(db, columns, from.as_ref(), outer)?;
+                        join::explain(
+                            db,
+                            from.as_ref(),
+                            where_cond.as_ref(),
+                            outer,
+                            &order_by,
+                            Some(&used),
+                        )?
+                    }
+                    select => (select.plan(db, outer)?, order_by.is_empty()),
+                };
+                if !ordered {
+                    steps.push(PlanStep::TempBTree {
+                        clause: "ORDER BY".to_string(),
+                    });
+                }
+                steps
+            }
+            Command::With { ctes, select, .. } => {
+                let select = cte::expand(db, ctes, select)?;
+                select.plan(db, outer)?
+            }
+            Command::ExplainQueryPlan(_) => bail!("EXPLAIN QUERY PLAN of EXPLAIN is not supported"),
+            Command::CreateTable { .. }
+            | Command::CreateIndex { .. }
+            | Command::CreateView { .. } => Vec::new(),
+        };
+        Ok(steps)
+    }
 
-                // get index schema if index exists
-                let index = db.index_on_column(tbl_name, &cond.column);
+    /// Calls `f` for every table referenced in the query and its subqueries
+    fn visit_table_refs(&mut self, f: &mut dyn FnMut(&mut TableRef)) {
+        let (from, mut exprs) = match self {
+            Command::Select {
+                columns,
+                from,
+                where_cond,
+            } => {
+                let exprs: Vec<&mut Expr> = columns
+                    .iter_mut()
+                    .filter_map(|c| match c {
+                        ResultColumn::Expr { expr, .. } => Some(expr),
+                        _ => None,
+                    })
+                    .chain(where_cond)
+                    .collect();
+                (from, exprs)
+            }
+            Command::Count {
+                from, where_cond, ..
+            }
+            | Command::MinMax {
+                from, where_cond, ..
+            } => (from, where_cond.iter_mut().collect()),
+            Command::Compound { left, right, .. } => {
+                left.visit_table_refs(f);
+                right.visit_table_refs(f);
+                return;
+            }
+            Command::Ordered { select, .. } | Command::ExplainQueryPlan(select) => {
+                select.visit_table_refs(f);
+                return;
+            }
+            Command::With { ctes, select, .. } => {
+                for cte in ctes {
+                    cte.select.visit_table_refs(f);
+                }
+                select.visit_table_refs(f);
+                return;
+            }
+            Command::CreateTable { .. }
+            | Command::CreateIndex { .. }
+            | Command::CreateView { .. } => return,
+        };
 
-                if let Some(index_schema) = index {
-                    index_root_page = Some(index_schema.rootpage);
+        if let Some(from) = from {
+            for table in std::iter::once(&mut from.table).chain(from.joins.iter_mut().map(|j| {
+                if let Some(JoinConstraint::On(expr)) = &mut j.constraint {
+                    exprs.push(expr);
+                }
+                &mut j.table
+            })) {
+                // the table may be replaced, so its subqueries are visited first
+                if let TableRef::Subquery { select, .. } = table {
+                    select.visit_table_refs(f);
                 }
+                f(table);
+            }
+        }
 
-                filter = Some(pager::CellFilter::new(
-                    index_root_page,
-                    cond_col_i as u16,
-                    cond.value.to_lowercase(),
-                    table.primary_key_column_index,
-                ));
+        for expr in exprs {
+            for select in eval::subqueries_mut(expr) {
+                select.visit_table_refs(f);
             }
         }
+    }
 
-        let table_root_page = db.root_page_num(&table.name, SchemaType::Table)?;
-        let mut tree = Tree::new(&mut db.pager);
+    /// Calls `f` for every expression of the query and all its subexpressions, including
+    /// expressions of subqueries, in the order they appear in the SQL text
+    fn visit_exprs(&mut self, f: &mut dyn FnMut(&mut Expr)) {
+        let (columns, from, where_cond) = match self {
+            Command::Select {
+                columns,
+                from,
+                where_cond,
+            } => (columns.as_mut_slice(), from, where_cond),
+            Command::Count {
+                from, where_cond, ..
+            }
+            | Command::MinMax {
+                from, where_cond, ..
+            } => (&mut [][..], from, where_cond),
+            Command::Compound { left, right, .. } => {
+                left.visit_exprs(f);
+                right.visit_exprs(f);
+                return;
+            }
+            Command::Ordered {
+                select,
+                order_by,
+                limit,
+            } => {
+                select.visit_exprs(f);
+                for term in order_by {
+                    visit_expr(&mut term.expr, f);
+                }
+                if let Some(limit) = limit {
+                    visit_expr(&mut limit.limit, f);
+                    if let Some(offset) = &mut limit.offset {
+                        visit_expr(offset, f);
+                    }
+                }
+                return;
+            }
+            Command::With { ctes, select, .. } => {
+                for cte in ctes {
+                    cte.select.visit_exprs(f);
+                }
+                select.visit_exprs(f);
+                return;
+            }
+            Command::ExplainQueryPlan(select) => {
+                select.visit_exprs(f);
+                return;
+            }
+            Command::CreateTable { .. }
+            | Command::CreateIndex { .. }
+            | Command::CreateView { .. } => return,
+        };
 
-        Ok(vec![vec![tree
-            .cells(table_root_page, filter)?
-            .len()
-            .to_string()]])
+        for column in columns {
+            if let ResultColumn::Expr { expr, .. } = column {
+                visit_expr(expr, f);
+            }
+        }
+        if let Some(from) = from {
+            if let TableRef::Subquery { select, .. } = &mut from.table {
+                select.visit_exprs(f);
+            }
+            for join in &mut from.joins {
+                if let TableRef::Subquery { select, .. } = &mut join.table {
+                    select.visit_exprs(f);
+                }
+                if let Some(JoinConstraint::On(expr)) = &mut join.constraint {
+                    visit_expr(expr, f);
+                }
+            }
+        }
+        if let Some(expr) = where_cond {
+            visit_expr(expr, f);
+        }
+    }
+
+    /// Assigns indexes to the statement parameters like SQLite: `?NNN` has index NNN,
+    /// `?` and a new named parameter get the index following the largest one so far
+    /// and parameters with the same name share the index.
+    ///
+    /// Returns names of the parameters by their index - 1, None for `?`.
+    pub(crate) fn number_parameters(&mut self) -> Result<Vec<Option<String>>> {
+        let mut names: Vec<Option<String>> = Vec::new();
+        let mut invalid = false;
+
+        self.visit_exprs(&mut |expr| {
+            let Expr::Parameter { name, index } = expr else {
+                return;
+            };
+            let i = match name.strip_prefix('?') {
+                Some("") => names.len() + 1,
+                Some(number) => match number.parse() {
+                    Ok(i) if (1..=MAX_PARAMETER_NUMBER).contains(&i) => i,
+                    _ => {
+                        invalid = true;
+                        return;
+                    }
+                },
+                None => match names.iter().position(|n| n.as_ref() == Some(name)) {
+                    Some(i) => i + 1,
+                    None => names.len() + 1,
+                },
+            };
+
+            if names.len() < i {
+                names.resize(i, None);
+            }
+            if name != "?" && names[i - 1].is_none() {
+                names[i - 1] = Some(name.clone());
+            }
+            *index = Some(i);
+        });
+
+        if invalid {
+            bail!("variable number must be between ?1 and ?{MAX_PARAMETER_NUMBER}");
+        }
+        Ok(names)
+    }
+
+    /// Returns the query with the parameters replaced by their values, NULL if there is no value
+    pub(crate) fn bind_parameters(&self, values: &[ColumnContent]) -> Command {
+        let mut command = self.clone();
+        command.visit_exprs(&mut |expr| {
+            if let Expr::Parameter { index: Some(i), .. } = expr {
+                let value = values.get(*i - 1).cloned();
+                *expr = Expr::Literal(value.unwrap_or(ColumnContent::Null));
+            }
+        });
+        command
+    }
+}
+
+/// Calls `f` for the expression and all its subexpressions, including expressions of subqueries
+fn visit_expr(expr: &mut Expr, f: &mut dyn FnMut(&mut Expr)) {
+    f(expr);
+    match expr {
+        Expr::Column(_) | Expr::Literal(_) | Expr::Parameter { .. } => {}
+        Expr::Not(expr) | Expr::IsNull { expr, .. } => visit_expr(expr, f),
+        Expr::Binary { left, right, .. } => {
+            visit_expr(left, f);
+            visit_expr(right, f);
+        }
+        Expr::InList { expr, list, .. } => {
+            visit_expr(expr, f);
+            for expr in list {
+                visit_expr(expr, f);
+            }
+        }
+        Expr::InSelect { expr, select, .. } => {
+            visit_expr(expr, f);
+            select.visit_exprs(f);
+        }
+        Expr::Exists(select) | Expr::Subquery(select) => select.visit_exprs(f),
+        Expr::Function { args, .. } => {
+            for expr in args {
+                visit_expr(expr, f);
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::page::ColumnContent;
+
+    fn from(table: &str) -> Option<FromClause> {
+        Some(FromClause {
+            table: TableRef::Table {
+                name: table.to_string(),
+                alias: None,
+            },
+            joins: Vec::new(),
+        })
+    }
+
+    fn column(name: &str) -> ResultColumn {
+        ResultColumn::Expr {
+            expr: Expr::column(None, name),
+            alias: None,
+        }
+    }
 
     #[test]
     fn test_parse_count_uppercase() {
@@ -237,7 +1038,7 @@ mod tests {
             c,
             Command::Count {
                 column: "*".to_string(),
-                table: "oranges".to_string(),
+                from: from("oranges"),
                 where_cond: None,
             }
         );
@@ -252,11 +1053,12 @@ mod tests {
             c,
             Command::Count {
                 column: "*".to_string(),
-                table: "oranges".to_string(),
-                where_cond: Some(Condition {
-                    column: "color".to_string(),
-                    value: "Yellow".to_string(),
-                }),
+                from: from("oranges"),
+                where_cond: Some(Expr::binary(
+                    BinaryOperator::Eq,
+                    Expr::column(None, "color"),
+                    Expr::Literal(ColumnContent::Text("Yellow".to_string())),
+                )),
             }
         );
     }
@@ -270,7 +1072,7 @@ mod tests {
             c,
             Command::Count {
                 column: "name".to_string(),
-                table: "oranges".to_string(),
+                from: from("oranges"),
                 where_cond: None,
             }
         );
@@ -284,8 +1086,8 @@ mod tests {
         assert_eq!(
             c,
             Command::Select {
-                columns: vec!["name".to_string()],
-                table: "oranges".to_string(),
+                columns: vec![column("name")],
+                from: from("oranges"),
                 where_cond: None,
             }
         );
@@ -299,8 +1101,8 @@ mod tests {
         assert_eq!(
             c,
             Command::Select {
-                columns: vec!["name".to_string(), "color".to_string()],
-                table: "apples".to_string(),
+                columns: vec![column("name"), column("color")],
+                from: from("apples"),
                 where_cond: None,
             }
         );
@@ -314,8 +1116,8 @@ mod tests {
         assert_eq!(
             c,
             Command::Select {
-                columns: vec!["*".to_string()],
-                table: "apples".to_string(),
+                columns: vec![ResultColumn::All],
+                from: from("apples"),
                 where_cond: None,
             }
         );
@@ -329,15 +1131,1086 @@ mod tests {
         assert_eq!(
             c,
             Command::Select {
-                columns: vec!["name".to_string(), "color".to_string()],
-                table: "apples".to_string(),
-                where_cond: Some(Condition {
-                    column: "color".to_string(),
-                    value: "Yellow".to_string(),
-                }),
+                columns: vec![column("name"), column("color")],
+                from: from("apples"),
+                where_cond: Some(Expr::binary(
+                    BinaryOperator::Eq,
+                    Expr::column(None, "color"),
+                    Expr::Literal(ColumnContent::Text("Yellow".to_string())),
+                )),
             }
         );
     }
+
+    #[test]
+    fn test_execute_join() {
+        let mut db = DB::new("sample.db").unwrap();
+
+        let rows = db
+            .execute(
+                "SELECT a.name, o.name FROM apples a JOIN oranges o ON o.id = a.id WHERE a.id > 2",
+            )
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["Honeycrisp", "Tangerine"],
+                vec!["Golden Delicious", "Clementine"]
+            ]
+        );
+
+        let rows = db
+            .execute(
+                "SELECT id, apples.name FROM oranges LEFT JOIN apples USING (id) WHERE id >= 4",
+            )
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![vec!["4", "Golden Delicious"], vec!["5", ""], vec!["6", ""]]
+        );
+
+        let rows = db
+            .execute("SELECT COUNT(*) FROM apples, oranges WHERE apples.id < oranges.id")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["14"]]);
+    }
+
+    #[test]
+    fn test_execute_subqueries() {
+        let mut db = DB::new("sample.db").unwrap();
+
+        let rows = db
+            .execute("SELECT name FROM oranges WHERE id IN (SELECT id FROM apples WHERE id > 2)")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["Tangerine"], vec!["Clementine"]]);
+
+        let rows = db
+            .execute(
+                "SELECT o.name, (SELECT a.name FROM apples a WHERE a.id = o.id) FROM oranges o
+                WHERE NOT EXISTS (SELECT * FROM apples WHERE apples.id > o.id)",
+            )
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["Clementine", "Golden Delicious"],
+                vec!["Valencia Orange", ""],
+                vec!["Navel Orange", ""]
+            ]
+        );
+
+        let rows = db
+            .execute(
+                "SELECT t.n FROM (SELECT id, name AS n FROM apples WHERE id < 3) AS t
+                JOIN oranges USING (id)",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["Granny Smith"], vec!["Fuji"]]);
+    }
+
+    #[test]
+    fn test_execute_rowid() {
+        let mut db = DB::new("sample.db").unwrap();
+
+        let rows = db
+            .execute("SELECT rowid, oid, _rowid_, name FROM apples WHERE rowid > 1 AND oid <= 3")
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["2", "2", "2", "Fuji"],
+                vec!["3", "3", "3", "Honeycrisp"]
+            ]
+        );
+
+        let rows = db
+            .execute("SELECT o.name FROM apples a JOIN oranges o ON o.rowid = a.rowid + 4")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["Valencia Orange"], vec!["Navel Orange"]]);
+
+        // rowid is not a part of *
+        let rows = db
+            .execute("SELECT * FROM apples WHERE 4 <= _rowid_")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["4", "Golden Delicious", "Yellow"]]);
+
+        assert_eq!(
+            db.execute("SELECT rowid FROM apples, oranges")
+                .unwrap_err()
+                .to_string(),
+            "ambiguous column name: rowid"
+        );
+        assert_eq!(
+            db.execute("SELECT rowid FROM (SELECT name FROM apples)")
+                .unwrap_err()
+                .to_string(),
+            "no such column: rowid"
+        );
+    }
+
+    #[test]
+    fn test_execute_without_rowid() {
+        let mut db = DB::new("without_rowid.db").unwrap();
+
+        let rows = db.execute("SELECT count(*) FROM stock").unwrap();
+        assert_eq!(rows, vec![vec!["300"]]);
+
+        // seek on the composite primary key
+        let rows = db
+            .execute("SELECT item, quantity FROM stock WHERE warehouse = 'C' AND item = 299")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["299", "2990"]]);
+        // the key values are converted by the affinity of the primary key columns
+        let rows = db
+            .execute("SELECT quantity FROM stock WHERE warehouse = 'C' AND item = '299'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["2990"]]);
+
+        let rows = db
+            .execute("SELECT item FROM stock WHERE warehouse = 'B' AND item < 10")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"], vec!["4"], vec!["7"]]);
+
+        // columns are in the declared order, the record starts with the primary key
+        let rows = db
+            .execute("SELECT * FROM stock WHERE item = 2 AND warehouse = 'C'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["C", "2", "20", "note 2"]]);
+
+        // rows are in the primary key order, older rows have the default of the added column
+        let rows = db.execute("SELECT * FROM colors").unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["black", "#000", "0"],
+                vec!["blue", "#00f", "255"],
+                vec!["green", "#0f0", "255"],
+                vec!["red", "#f00", "255"],
+            ]
+        );
+
+        let rows = db
+            .execute(
+                "SELECT c.name, s.note FROM colors c
+                JOIN stock s ON s.warehouse = 'A' AND s.item = c.alpha / 85",
+            )
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["blue", "note 3"],
+                vec!["green", "note 3"],
+                vec!["red", "note 3"],
+            ]
+        );
+
+        assert_eq!(
+            db.execute("SELECT rowid FROM colors")
+                .unwrap_err()
+                .to_string(),
+            "no such column: rowid"
+        );
+    }
+
+    #[test]
+    fn test_execute_composite_index() {
+        let mut db = DB::new("events.db").unwrap();
+
+        let rows = db
+            .execute("SELECT id FROM events WHERE tenant_id = 2 AND created_at = '2024-03-03' AND id < 200")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["2"], vec!["86"], vec!["170"]]);
+
+        // range of the column following the equality prefix
+        let rows = db
+            .execute(
+                "SELECT count(*) FROM events
+                WHERE tenant_id = 5 AND created_at >= '2024-06-01' AND created_at < '2024-07-01'",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["50"]]);
+
+        let rows = db
+            .execute(
+                "SELECT count(*) FROM events WHERE created_at > '2024-11-20' AND tenant_id = 4",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["14"]]);
+
+        let rows = db
+            .execute(
+                "SELECT count(*) FROM events e
+                JOIN events f ON f.tenant_id = e.tenant_id AND f.created_at = e.created_at
+                WHERE e.id = 7",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["8"]]);
+    }
+
+    #[test]
+    fn test_execute_expression_index() {
+        let mut db = DB::new("events.db").unwrap();
+
+        let rows = db
+            .execute("SELECT count(*) FROM events WHERE upper(kind) = 'VIEW'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["200"]]);
+
+        // the partial index has only clicks
+        let rows = db
+            .execute("SELECT id FROM events WHERE created_at = '2024-04-04' AND kind = 'click'")
+            .unwrap();
+        assert_eq!(
+            rows,
+            [3, 87, 171, 255, 339, 423, 507, 591]
+                .map(|id| vec![id.to_string()])
+                .to_vec()
+        );
+        let rows = db
+            .execute("SELECT count(*) FROM events WHERE created_at = '2024-04-04'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["8"]]);
+    }
+
+    #[test]
+    fn test_execute_desc_index() {
+        let mut db = DB::new("events.db").unwrap();
+
+        // tenant_id and created_at are descending in idx_events_tenant_kind
+        let rows = db
+            .execute(
+                "SELECT count(*) FROM events
+                WHERE kind = 'view' AND tenant_id = 4 AND created_at > '2024-10-01'",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["50"]]);
+        let rows = db
+            .execute(
+                "SELECT count(*) FROM events WHERE kind = 'view' AND tenant_id > 1 AND tenant_id <= 4",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["100"]]);
+
+        // the index order satisfies ORDER BY
+        let rows = db
+            .execute(
+                "SELECT tenant_id, created_at FROM events
+                WHERE kind = 'view' AND tenant_id = 4 ORDER BY created_at DESC LIMIT 2",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["4", "2024-11-27"]; 2]);
+    }
+
+    #[test]
+    fn test_execute_auto_index() {
+        let mut db = DB::new("users.db").unwrap();
+
+        // email UNIQUE and UNIQUE (team, handle) have sqlite_autoindex_users_N indexes
+        let rows = db
+            .execute("SELECT id, name FROM users WHERE email = 'user123@example.com'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["123", "User 123"]]);
+        let rows = db
+            .execute("SELECT id FROM users WHERE email = 'nobody@example.com'")
+            .unwrap();
+        assert!(rows.is_empty());
+
+        let rows = db
+            .execute("SELECT id FROM users WHERE team = 'team3' AND handle = 'h229'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["17"]]);
+        let rows = db
+            .execute("SELECT count(*) FROM users WHERE team = 'team3' AND handle > 'h3'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["26"]]);
+    }
+
+    #[test]
+    fn test_execute_covering_index() {
+        let mut db = DB::new("events.db").unwrap();
+
+        // tenant_id, created_at and the rowid are in idx_events_tenant_created
+        let rows = db
+            .execute("SELECT count(*) FROM events WHERE tenant_id = 3")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["100"]]);
+        let rows = db
+            .execute(
+                "SELECT id, created_at FROM events
+                WHERE tenant_id = 3 AND created_at > '2024-10-20' ORDER BY created_at DESC, id LIMIT 3",
+            )
+            .unwrap();
+        assert_eq!(
+            rows,
+            [81, 165, 249].map(|id| vec![id.to_string(), "2024-10-26".to_string()])
+        );
+        // kind is read from the table
+        let rows = db
+            .execute("SELECT count(kind) FROM events WHERE tenant_id = 3")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["100"]]);
+
+        let mut db = DB::new("users.db").unwrap();
+
+        let rows = db
+            .execute("SELECT id, handle FROM users WHERE team = 'team5' AND handle < 'h105'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["173", "h1"]]);
+        let rows = db
+            .execute(
+                "SELECT u.id, (SELECT count(*) FROM users v WHERE v.email < u.email) FROM users u
+                WHERE u.email < 'user102'",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["100", "0"], vec!["101", "1"]]);
+
+        // `*` is expanded to the table columns, all of them are in the index
+        let mut db = DB::new("events.db").unwrap();
+        let sql = "SELECT * FROM events WHERE kind = 'click' AND tenant_id = 3";
+        assert_eq!(
+            db.query_plan(sql).unwrap().to_string(),
+            "QUERY PLAN
+`--SEARCH events USING COVERING INDEX idx_events_tenant_kind (kind=? AND tenant_id=?)
+"
+        );
+        let mut covered = db.execute(sql).unwrap();
+        let mut scanned = db
+            .execute("SELECT * FROM events WHERE kind || '' = 'click' AND tenant_id + 0 = 3")
+            .unwrap();
+        covered.sort();
+        scanned.sort();
+        assert_eq!(covered, scanned);
+        assert_eq!(covered.len(), 100);
+
+        // the records of an index of a WITHOUT ROWID table end with the primary key
+        let mut db = DB::new("without_rowid.db").unwrap();
+        let sql = "SELECT name, alpha FROM colors WHERE alpha < 255";
+        assert_eq!(
+            db.query_plan(sql).unwrap().to_string(),
+            "QUERY PLAN\n`--SEARCH colors USING COVERING INDEX idx_colors_alpha (alpha<?)\n"
+        );
+        assert_eq!(db.execute(sql).unwrap(), vec![vec!["black", "0"]]);
+        let rows = db
+            .execute("SELECT name FROM colors WHERE alpha = 255 OR name = 'black'")
+            .unwrap();
+        assert_eq!(rows.len(), 4);
+    }
+
+    #[test]
+    fn test_execute_index_range() {
+        let mut db = DB::new("users.db").unwrap();
+
+        let rows = db
+            .execute("SELECT count(*) FROM users WHERE email >= 'user2' AND email < 'user3'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["111"]]);
+        let rows = db
+            .execute("SELECT id FROM users WHERE email BETWEEN 'user398' AND 'user4' ORDER BY id")
+            .unwrap();
+        assert_eq!(rows, [3, 39, 398, 399].map(|id| vec![id.to_string()]));
+
+        let mut db = DB::new("events.db").unwrap();
+
+        let rows = db
+            .execute("SELECT count(*) FROM events WHERE tenant_id BETWEEN 2 AND 3")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["200"]]);
+        let rows = db
+            .execute("SELECT count(*) FROM events WHERE tenant_id NOT BETWEEN 2 AND 3")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["400"]]);
+
+        // rowid ranges are read from the table b-tree
+        let rows = db
+            .execute("SELECT count(*) FROM events WHERE id > 590")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["10"]]);
+        let rows = db
+            .execute("SELECT id FROM events WHERE id BETWEEN 10 AND 13")
+            .unwrap();
+        assert_eq!(rows, [10, 11, 12, 13].map(|id| vec![id.to_string()]));
+    }
+
+    #[test]
+    fn test_execute_count_all() {
+        // counted from idx_events_tenant_created, the cells of the leaves are not read
+        let mut db = DB::new("events.db").unwrap();
+        let sql = "SELECT count(*) FROM events";
+        assert_eq!(
+            db.query_plan(sql).unwrap().steps,
+            vec![PlanStep::Scan {
+                table: "events".to_string(),
+                index: Some(PlanIndex::Index {
+                    name: "idx_events_tenant_created".to_string(),
+                    covering: true,
+                }),
+            }]
+        );
+        let reads = db.pager.reads();
+        let rows = db.execute(sql).unwrap();
+        assert_eq!(rows, vec![vec!["600"]]);
+        assert_eq!(db.pager.reads(), reads);
+
+        let mut db = DB::new("without_rowid.db").unwrap();
+        let reads = db.pager.reads();
+        let rows = db.execute("SELECT count(*) FROM stock").unwrap();
+        assert_eq!(rows, vec![vec!["300"]]);
+        assert_eq!(db.pager.reads(), reads);
+
+        let mut db = DB::new("sample.db").unwrap();
+        let reads = db.pager.reads();
+        let rows = db.execute("SELECT count(*) FROM apples").unwrap();
+        assert_eq!(rows, vec![vec!["4"]]);
+        assert_eq!(db.pager.reads(), reads);
+        // rows of a common table expression are counted one by one
+        let rows = db
+            .execute("WITH a AS (SELECT * FROM apples WHERE id > 1) SELECT count(*) FROM a")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["3"]]);
+        assert!(db.pager.reads() > reads);
+    }
+
+    #[test]
+    fn test_execute_min_max() {
+        let mut db = DB::new("users.db").unwrap();
+
+        // points of idx_scores_points start with NULLs over several pages
+        let rows = db.execute("SELECT MIN(points) FROM scores").unwrap();
+        assert_eq!(rows, vec![vec!["1"]]);
+        let rows = db.execute("SELECT MAX(points) FROM scores").unwrap();
+        assert_eq!(rows, vec![vec!["998"]]);
+        let rows = db.execute("SELECT max(id) FROM scores").unwrap();
+        assert_eq!(rows, vec![vec!["2000"]]);
+        let rows = db.execute("SELECT min(email) FROM users").unwrap();
+        assert_eq!(rows, vec![vec!["user100@example.com"]]);
+
+        // without an index the rows are read
+        let rows = db.execute("SELECT min(user_id) FROM scores").unwrap();
+        assert_eq!(rows, vec![vec!["1"]]);
+        let rows = db
+            .execute("SELECT max(points) FROM scores WHERE id > 1800")
+            .unwrap();
+        assert_eq!(rows, vec![vec![""]]);
+
+        let rows = db.execute("SELECT min(v) FROM empty").unwrap();
+        assert_eq!(rows, vec![vec![""]]);
+        let rows = db.execute("SELECT max(id) FROM empty").unwrap();
+        assert_eq!(rows, vec![vec![""]]);
+
+        let mut db = DB::new("without_rowid.db").unwrap();
+        let rows = db.execute("SELECT max(warehouse) FROM stock").unwrap();
+        assert_eq!(rows, vec![vec!["C"]]);
+
+        // REAL values without a fraction are stored in the index as integers
+        let mut db = DB::new("readings.db").unwrap();
+        let rows = db.execute("SELECT max(level) FROM levels").unwrap();
+        assert_eq!(rows, vec![vec!["7.0"]]);
+        let rows = db.execute("SELECT min(level) FROM levels").unwrap();
+        assert_eq!(rows, vec![vec!["-1.0"]]);
+    }
+
+    #[test]
+    fn test_execute_order_backward() {
+        // the rows are read backward without a temporary b-tree for the ORDER BY, the limit
+        // stops the read on the right-most pages
+        fn execute_backward(db: &mut DB, sql: &str) -> Vec<Vec<String>> {
+            let plan = db.query_plan(sql).unwrap();
+            assert!(
+                !plan
+                    .steps
+                    .iter()
+                    .any(|step| matches!(step, PlanStep::TempBTree { .. })),
+                "{plan}"
+            );
+            let reads = db.pager.reads();
+            let rows = db.execute(sql).unwrap();
+            assert!(db.pager.reads() - reads <= 2, "{sql}");
+            rows
+        }
+
+        let mut db = DB::new("users.db").unwrap();
+
+        // the table b-tree is read from the right-most page
+        let rows = execute_backward(&mut db, "SELECT id FROM scores ORDER BY id DESC LIMIT 3");
+        assert_eq!(rows, vec![vec!["2000"], vec!["1999"], vec!["1998"]]);
+        let rows = execute_backward(
+            &mut db,
+            "SELECT id, email FROM users WHERE id > 390 ORDER BY id DESC LIMIT 2 OFFSET 1",
+        );
+        assert_eq!(
+            rows,
+            vec![
+                vec!["399", "user399@example.com"],
+                vec!["398", "user398@example.com"]
+            ]
+        );
+
+        // index range read backward, equal keys by the row_id descending
+        let rows = execute_backward(
+            &mut db,
+            "SELECT points, id FROM scores WHERE points > 990 ORDER BY points DESC LIMIT 3",
+        );
+        assert_eq!(
+            rows,
+            vec![vec!["998", "1642"], vec!["998", "642"], vec!["997", "963"]]
+        );
+
+        let mut db = DB::new("without_rowid.db").unwrap();
+        let rows = execute_backward(
+            &mut db,
+            "SELECT warehouse, item FROM stock ORDER BY warehouse DESC, item DESC LIMIT 2",
+        );
+        let expected = db
+            .execute("SELECT warehouse, item FROM stock ORDER BY warehouse, item")
+            .unwrap();
+        assert_eq!(rows, expected.into_iter().rev().take(2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_execute_multi_seek() {
+        let mut db = DB::new("users.db").unwrap();
+
+        let rows = db
+            .execute("SELECT id FROM users WHERE id IN (9, 1, 5, 1)")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["9"], vec!["1"], vec!["5"]]);
+        let rows = db.execute("SELECT id FROM users WHERE id IN ()").unwrap();
+        assert!(rows.is_empty());
+
+        // the same row found by the email index and by the rowid
+        let mut rows = db
+            .execute("SELECT id FROM users WHERE email = 'user3@example.com' OR id = 3 OR id = 8")
+            .unwrap();
+        rows.sort();
+        assert_eq!(rows, vec![vec!["3"], vec!["8"]]);
+
+        let mut rows = db
+            .execute(
+                "SELECT id FROM users
+                WHERE (team = 'team1' AND handle = 'h37') OR email IN ('user2@example.com', 'user5@example.com')",
+            )
+            .unwrap();
+        rows.sort();
+        assert_eq!(rows, vec![vec!["1"], vec!["2"], vec!["5"]]);
+
+        // one side of OR without an index is scanned
+        let rows = db
+            .execute("SELECT count(*) FROM scores WHERE points = 998 OR user_id = 3")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["7"]]);
+
+        let mut db = DB::new("events.db").unwrap();
+        let rows = db
+            .execute("SELECT count(*) FROM events WHERE tenant_id IN (1, 2) AND created_at = '2024-03-03'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["8"]]);
+    }
+
+    #[test]
+    fn test_execute_seek_affinity() {
+        let mut db = DB::new("users.db").unwrap();
+
+        // the value of an index key is converted by the affinity of the column
+        // like in the comparison of a scan, `OR 0` keeps the index from being used
+        let sql = "SELECT id FROM scores WHERE points = '3' ORDER BY id";
+        let plan = db.query_plan(sql).unwrap();
+        assert!(matches!(&plan.steps[0], PlanStep::Search { .. }), "{plan}");
+        let sought = db.execute(sql).unwrap();
+        let sql = "SELECT id FROM scores WHERE points = '3' OR 0 ORDER BY id";
+        let plan = db.query_plan(sql).unwrap();
+        assert!(matches!(&plan.steps[0], PlanStep::Scan { .. }), "{plan}");
+        let scanned = db.execute(sql).unwrap();
+        assert_eq!(sought, scanned);
+        assert_eq!(sought.len(), 2);
+
+        let sought = db
+            .execute("SELECT count(*) FROM scores WHERE points >= '3' AND points < '5'")
+            .unwrap();
+        let scanned = db
+            .execute("SELECT count(*) FROM scores WHERE (points >= '3' AND points < '5') OR 0")
+            .unwrap();
+        assert_eq!(sought, scanned);
+
+        let mut db = DB::new("events.db").unwrap();
+        let rows = db
+            .execute("SELECT count(*) FROM events WHERE tenant_id = '3'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["100"]]);
+    }
+
+    #[test]
+    fn test_execute_comparison_affinity() {
+        let mut db = DB::new("readings.db").unwrap();
+
+        // literals are compared as they are
+        assert_eq!(
+            db.execute("SELECT '1' = 1, 1 = 1.0").unwrap(),
+            vec![vec!["0", "1"]]
+        );
+
+        // a TEXT column makes the other side text, none of the columns is indexed
+        let rows = db
+            .execute("SELECT id FROM samples WHERE code >= 1 AND code <= 2")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"], vec!["2"], vec!["4"]]);
+        let rows = db
+            .execute("SELECT id FROM samples WHERE code BETWEEN 1 AND 2")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"], vec!["2"], vec!["4"]]);
+        let rows = db
+            .execute("SELECT id FROM samples WHERE code IN (1, 10)")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"], vec!["4"]]);
+
+        // a numeric column makes the other side numeric
+        let rows = db
+            .execute("SELECT id FROM samples WHERE amount > '5'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["3"], vec!["4"]]);
+        let rows = db
+            .execute("SELECT id FROM samples WHERE amount = note")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"]]);
+
+        // a column without type converts nothing
+        let rows = db
+            .execute("SELECT id FROM samples WHERE note = '1'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["2"]]);
+        let rows = db.execute("SELECT id FROM samples WHERE note = 2").unwrap();
+        assert_eq!(rows, vec![vec!["3"]]);
+        let rows = db
+            .execute("SELECT id FROM samples WHERE note IN (1, '2')")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"], vec!["4"]]);
+
+        // values of a subquery are converted once and looked up for each row
+        let rows = db
+            .execute("SELECT id FROM samples WHERE code IN (SELECT amount FROM samples)")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"], vec!["2"], vec!["4"]]);
+        let rows = db
+            .execute("SELECT id FROM samples WHERE amount IN (SELECT code FROM samples)")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"], vec!["2"], vec!["3"]]);
+        let rows = db
+            .execute("SELECT id FROM samples WHERE note IN (SELECT code FROM samples)")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["2"], vec!["4"]]);
+        let rows = db
+            .execute("SELECT id FROM samples WHERE amount NOT IN (SELECT code FROM samples WHERE id > 1)")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"], vec!["4"]]);
+    }
+
+    #[test]
+    fn test_execute_floats_and_blobs() {
+        let mut db = DB::new("readings.db").unwrap();
+
+        let rows = db
+            .execute("SELECT 1e3, -3.5, 0.1 + 0.2, 7 / 2.0, 1.5e-5, x'4142' || 'c'")
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![vec!["1000.0", "-3.5", "0.3", "3.5", "1.5e-05", "ABc"]]
+        );
+
+        // REAL values without a fraction are stored as integers
+        let rows = db
+            .execute("SELECT value, length(raw), value * 2 FROM readings")
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["3.5", "2", "7.0"],
+                vec!["1.0e+20", "2", "2.0e+20"],
+                vec!["-0.25", "", "-0.5"],
+                vec!["2.0", "0", "4.0"],
+            ]
+        );
+
+        let rows = db
+            .execute("SELECT id FROM readings WHERE value > 3.5 OR raw = x'4142'")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"], vec!["2"]]);
+        let rows = db
+            .execute("SELECT id FROM readings WHERE value BETWEEN -1 AND 2.5 ORDER BY value DESC")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["4"], vec!["3"]]);
+    }
+
+    #[test]
+    fn test_execute_generated_columns() {
+        let mut db = DB::new("generated.db").unwrap();
+
+        // the VIRTUAL columns before the ordinary columns are not stored in the records
+        let rows = db.execute("SELECT a, b, c, d FROM items").unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["1", "x", "y", "5"],
+                vec!["2", "z", "", "5"],
+                vec!["3", "q", "r", "5"],
+                vec!["4", "p", "o", "6"],
+            ]
+        );
+        let rows = db.execute("SELECT * FROM items WHERE a = 1").unwrap();
+        assert_eq!(rows, vec![vec!["1", "2", "x", "x!", "y", "3", "5"]]);
+
+        // the rows found by the index on a VIRTUAL column
+        let rows = db
+            .execute("SELECT a, v, w FROM items WHERE v > 4 ORDER BY v DESC")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["4", "8", "9"], vec!["3", "6", "7"]]);
+
+        let rows = db.execute("SELECT label, price FROM parts").unwrap();
+        assert_eq!(rows, vec![vec!["BOLT", "3"], vec!["NUT", "1"]]);
+    }
+
+    #[test]
+    fn test_execute_stats() {
+        let mut db = DB::new("stats.db").unwrap();
+        assert_eq!(db.table_rows("orders"), Some(3000));
+        assert_eq!(db.index_stat("idx_orders_status"), Some(&[3000, 1000][..]));
+        assert_eq!(db.index_stat("idx_orders_missing"), None);
+
+        // the customer index leaves less rows than the status index
+        let rows = db
+            .execute("SELECT id FROM orders WHERE status = 'done' AND customer = 42")
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["42"],
+                vec!["542"],
+                vec!["1042"],
+                vec!["1542"],
+                vec!["2042"],
+                vec!["2542"]
+            ]
+        );
+        let rows = db
+            .execute("SELECT count(*) FROM orders WHERE status = 'void' AND id < 500")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["10"]]);
+
+        let db = DB::new("users.db").unwrap();
+        assert_eq!(db.table_rows("users"), None);
+
+        // sqlite_stat1 without the stat column is ignored
+        let mut db = DB::new("broken_stats.db").unwrap();
+        assert_eq!(db.table_rows("items"), None);
+        let rows = db.execute("SELECT id FROM items WHERE name = 'b'").unwrap();
+        assert_eq!(rows, vec![vec!["2"]]);
+
+        // with statistics a scan is cheaper than looking up most rows by the index
+        let mut db = DB::new("stats.db").unwrap();
+        let plan = db
+            .query_plan("SELECT id FROM orders WHERE status = 'done' AND customer = 42")
+            .unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "QUERY PLAN\n`--SEARCH orders USING INDEX idx_orders_customer (customer=?)\n"
+        );
+        let plan = db
+            .query_plan("SELECT id, total FROM orders WHERE status = 'done'")
+            .unwrap();
+        assert_eq!(plan.to_string(), "QUERY PLAN\n`--SCAN orders\n");
+    }
+
+    #[test]
+    fn test_execute_explain_query_plan() {
+        let mut db = DB::new("users.db").unwrap();
+
+        let rows = db
+            .execute("EXPLAIN QUERY PLAN SELECT name FROM users WHERE email > 'x' ORDER BY name")
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    "1",
+                    "0",
+                    "0",
+                    "SEARCH users USING INDEX sqlite_autoindex_users_1 (email>?)"
+                ],
+                vec!["2", "0", "0", "USE TEMP B-TREE FOR ORDER BY"],
+            ]
+        );
+
+        let plan = db
+            .query_plan("SELECT u.id FROM users u JOIN scores s ON s.id = u.id ORDER BY u.id DESC")
+            .unwrap();
+        assert_eq!(
+            plan.steps,
+            vec![
+                PlanStep::Scan {
+                    table: "u".to_string(),
+                    index: None
+                },
+                PlanStep::Search {
+                    table: "s".to_string(),
+                    index: Some(PlanIndex::IntegerPrimaryKey),
+                    constraints: vec!["rowid=?".to_string()],
+                },
+            ]
+        );
+
+        let plan = db
+            .query_plan("SELECT id FROM users WHERE team = 'team1' AND handle > 'h'")
+            .unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "QUERY PLAN
+`--SEARCH users USING COVERING INDEX sqlite_autoindex_users_2 (team=? AND handle>?)
+"
+        );
+        let plan = db
+            .query_plan("SELECT * FROM users WHERE email = ? OR id = 3")
+            .unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "QUERY PLAN
+`--MULTI-INDEX OR
+   |--INDEX 1
+   |  `--SEARCH users USING INDEX sqlite_autoindex_users_1 (email=?)
+   `--INDEX 2
+      `--SEARCH users USING INTEGER PRIMARY KEY (rowid=?)
+"
+        );
+
+        let plan = db
+            .query_plan("SELECT count(*) FROM scores UNION ALL SELECT max(points) FROM scores")
+            .unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "QUERY PLAN
+`--COMPOUND QUERY
+   |--LEFT-MOST SUBQUERY
+   |  `--SCAN scores USING COVERING INDEX idx_scores_points
+   `--UNION ALL
+      `--SEARCH scores USING COVERING INDEX idx_scores_points
+"
+        );
+
+        let mut db = DB::new("without_rowid.db").unwrap();
+        let plan = db
+            .query_plan(
+                "EXPLAIN QUERY PLAN SELECT * FROM stock WHERE warehouse = 'A' ORDER BY item",
+            )
+            .unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "QUERY PLAN\n`--SEARCH stock USING PRIMARY KEY (warehouse=?)\n"
+        );
+    }
+
+    #[test]
+    fn test_execute_with() {
+        let mut db = DB::new("sample.db").unwrap();
+
+        let rows = db
+            .execute(
+                "WITH RECURSIVE cnt(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM cnt WHERE x < 4)
+                SELECT x, x * x FROM cnt",
+            )
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["1", "1"],
+                vec!["2", "4"],
+                vec!["3", "9"],
+                vec!["4", "16"]
+            ]
+        );
+
+        // walks apples with ids 1, 3 and 5 (there is no apple with id 5)
+        let rows = db
+            .execute(
+                "WITH RECURSIVE odd(id, path) AS (
+                    SELECT id, name FROM apples WHERE id = 1
+                    UNION
+                    SELECT a.id, odd.path || ', ' || a.name FROM odd JOIN apples a ON a.id = odd.id + 2
+                )
+                SELECT path FROM odd",
+            )
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![vec!["Granny Smith"], vec!["Granny Smith, Honeycrisp"]]
+        );
+
+        // referenced twice, computed once
+        let rows = db
+            .execute(
+                "WITH big AS (SELECT id FROM oranges WHERE id > 4)
+                SELECT COUNT(*) FROM big b1, big b2",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["4"]]);
+
+        let err = db
+            .execute("WITH RECURSIVE t(a) AS (SELECT a FROM t) SELECT * FROM t")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "circular reference: t");
+        let err = db
+            .execute("WITH t(a, b) AS (SELECT 1) SELECT * FROM t")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "table t has 1 values for 2 columns");
+
+        // LIMIT stops the recursion
+        let rows = db
+            .execute(
+                "WITH RECURSIVE cnt(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM cnt LIMIT 2 OFFSET 1)
+                SELECT x FROM cnt",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["2"], vec!["3"]]);
+
+        // so does the LIMIT of the query reading the rows
+        let rows = db
+            .execute(
+                "WITH RECURSIVE c(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM c)
+                SELECT n FROM c LIMIT 3",
+            )
+            .unwrap();
+        assert_eq!(rows, vec![vec!["1"], vec!["2"], vec!["3"]]);
+    }
+
+    #[test]
+    fn test_view_columns() {
+        let db = DB::new("sample.db").unwrap();
+        let view = |sql| match parse_command(sql).unwrap() {
+            Command::CreateView {
+                name,
+                columns,
+                select,
+            } => View {
+                name,
+                columns,
+                select: *select,
+            },
+            _ => unreachable!(),
+        };
+
+        let v = view("CREATE VIEW v AS SELECT name, id * 2 AS double, color || '!' FROM apples");
+        assert_eq!(
+            view_columns(&db, &v).unwrap(),
+            vec!["name", "double", "color || '!'"]
+        );
+        let v = view("CREATE VIEW v(a, b) AS SELECT * FROM (SELECT name, color FROM apples)");
+        assert_eq!(view_columns(&db, &v).unwrap(), vec!["a", "b"]);
+        let v = view("CREATE VIEW v(a, b) AS SELECT 1");
+        assert_eq!(
+            view_columns(&db, &v).unwrap_err().to_string(),
+            "expected 2 columns for 'v' but got 1"
+        );
+
+        // views are read from the schema, they cannot be created
+        let mut db = DB::new("sample.db").unwrap();
+        let err = db.execute("CREATE VIEW v AS SELECT 1").unwrap_err();
+        assert_eq!(err.to_string(), "CREATE VIEW is not supported");
+        assert!(db.query("CREATE VIEW v AS SELECT 1").is_err());
+    }
+
+    #[test]
+    fn test_execute_compound() {
+        let mut db = DB::new("sample.db").unwrap();
+
+        let rows = db
+            .execute("SELECT id FROM apples UNION SELECT id FROM oranges WHERE id > 3")
+            .unwrap();
+        assert_eq!(rows.len(), 6);
+        let rows = db
+            .execute("SELECT id FROM apples UNION ALL SELECT id FROM oranges WHERE id > 3")
+            .unwrap();
+        assert_eq!(rows.len(), 7);
+        let rows = db
+            .execute("SELECT id FROM oranges EXCEPT SELECT id FROM apples")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["5"], vec!["6"]]);
+        let rows = db
+            .execute("SELECT id FROM oranges INTERSECT SELECT id + 2 FROM apples")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["3"], vec!["4"], vec!["5"], vec!["6"]]);
+
+        let rows = db
+            .execute(
+                "SELECT name, id FROM apples UNION ALL SELECT name, id FROM oranges
+                ORDER BY 2 DESC, name LIMIT 3 OFFSET 1",
+            )
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["Valencia Orange", "5"],
+                vec!["Clementine", "4"],
+                vec!["Golden Delicious", "4"]
+            ]
+        );
+
+        // a simple select can be ordered by columns not in the result
+        let rows = db
+            .execute("SELECT name FROM apples ORDER BY color LIMIT 2")
+            .unwrap();
+        assert_eq!(rows, vec![vec!["Honeycrisp"], vec!["Granny Smith"]]);
+
+        let err = db
+            .execute("SELECT id FROM apples UNION SELECT id, name FROM oranges")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "SELECTs to the left and right of UNION do not have the same number of result columns"
+        );
+        let err = db
+            .execute("SELECT id FROM apples UNION SELECT id FROM oranges ORDER BY color")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "1st ORDER BY term does not match any column in the result set"
+        );
+        let err = db.execute("SELECT id FROM apples ORDER BY 2").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "1st ORDER BY term out of range - should be between 1 and 1"
+        );
+    }
+
+    #[test]
+    fn test_query_rows() {
+        let mut db = DB::new("sample.db").unwrap();
+
+        let mut rows = db
+            .query("SELECT a.name, o.name FROM apples a JOIN oranges o ON o.id = a.id + 2")
+            .unwrap();
+        assert_eq!(
+            rows.next().unwrap().unwrap(),
+            vec![
+                ColumnContent::Text("Granny Smith".to_string()),
+                ColumnContent::Text("Tangerine".to_string())
+            ]
+        );
+        assert_eq!(rows.count(), 3);
+
+        // rows behind the limit are not read, also when the filter skips some rows
+        let rows: Vec<_> = db
+            .query("SELECT id FROM oranges WHERE name > 'M' LIMIT 2 OFFSET 1")
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![vec![ColumnContent::Int(2)], vec![ColumnContent::Int(3)]]
+        );
+
+        let mut stmt = db
+            .prepare("SELECT count(*) FROM oranges WHERE id < ?")
+            .unwrap();
+        stmt.bind(1, 3).unwrap();
+        let rows: Vec<_> = stmt.query(&mut db).unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(rows, vec![vec![ColumnContent::Int(2)]]);
+    }
 }
 
 #[test]
@@ -345,11 +2218,9 @@ fn test_parse_create_index() {
     let sql = "CREATE INDEX idx_companies_country\n\ton companies (country)";
     let c = parse_command(sql);
     let c = c.unwrap();
-    assert_eq!(
-        c,
-        Command::CreateIndex {
-            columns: vec!["country".to_string()],
-            table: "companies".to_string()
-        }
-    );
+    let Command::CreateIndex(index) = c else {
+        panic!("not an index: {c:?}");
+    };
+    assert_eq!(index.table, "companies");
+    assert_eq!(index.columns[0].expr, Expr::column(None, "country"));
 }
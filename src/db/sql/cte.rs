@@ -0,0 +1,400 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, VecDeque};
+use std::rc::Rc;
+
+use anyhow::{bail, Result};
+
+use super::eval::Row;
+use super::operator::Operator;
+use super::order::{self, KeyedRow, SortKey};
+use super::parser::{CompoundOperator, Cte, TableRef};
+use super::Command;
+use crate::db::DB;
+
+/// Maximum number of rows of a recursive common table expression, protects against endless recursion
+const MAX_RECURSIVE_ROWS: usize = 1_000_000;
+
+/// Replaces references to the common table expressions in the select by their definitions.
+///
+/// CTE referenced once is inlined as a subquery, CTEs referenced more than once are
+/// computed in advance and recursive CTEs are computed while their rows are read.
+pub(super) fn expand(db: &mut DB, ctes: &[Cte], select: &Command) -> Result<Command> {
+    let mut ctes = ctes.to_vec();
+    let mut select = select.clone();
+
+    for i in 0..ctes.len() {
+        let cte = ctes[i].clone();
+
+        let table = if references(&cte.select, &cte.name) > 0 {
+            TableRef::Recursive {
+                alias: None,
+                recursion: Rc::new(recursion(db, &cte)?),
+            }
+        } else if ctes[i + 1..]
+            .iter()
+            .map(|c| references(&c.select, &cte.name))
+            .sum::<usize>()
+            + references(&select, &cte.name)
+            > 1
+        {
+            let (names, rows) = cte.select.query(db, None)?;
+            materialized(&cte, columns(&cte, names)?, rows)
+        } else {
+            subquery(&cte)
+        };
+
+        for c in &mut ctes[i + 1..] {
+            replace(&mut c.select, &cte.name, &table);
+        }
+        replace(&mut select, &cte.name, &table);
+    }
+
+    Ok(select)
+}
+
+/// Replaces references to the common table expressions by their definitions without executing anything
+pub(super) fn inline(ctes: &[Cte], select: &Command) -> Command {
+    let mut ctes = ctes.to_vec();
+    let mut select = select.clone();
+
+    for i in 0..ctes.len() {
+        let table = subquery(&ctes[i]);
+        let name = ctes[i].name.clone();
+        for c in &mut ctes[i + 1..] {
+            replace(&mut c.select, &name, &table);
+        }
+        replace(&mut select, &name, &table);
+    }
+
+    select
+}
+
+/// Recursive CTE `initial-select UNION [ALL] recursive-select`, its rows are computed
+/// while they are read, so a query reading only some of them stops the recursion.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Recursion {
+    pub(super) name: String,
+    pub(super) columns: Vec<String>,
+    initial: Command,
+    /// selects referring to the CTE with the operators on their left side
+    recursive: Vec<(CompoundOperator, Command)>,
+    /// UNION adds only rows that were not added before
+    distinct: bool,
+    /// result columns of the ORDER BY of the CTE, true for DESC
+    order_by: Vec<(usize, bool)>,
+    /// LIMIT and OFFSET of the CTE
+    count: Option<usize>,
+    offset: usize,
+}
+
+/// Splits the recursive CTE into the initial and recursive selects
+fn recursion(db: &mut DB, cte: &Cte) -> Result<Recursion> {
+    let (select, order_by, limit) = match &cte.select {
+        Command::Ordered {
+            select,
+            order_by,
+            limit,
+        } => (select.as_ref(), order_by.as_slice(), limit.as_ref()),
+        select => (select, &[][..], None),
+    };
+
+    let mut arms = Vec::new();
+    compound_arms(select, None, &mut arms);
+
+    let Some(split) = arms
+        .iter()
+        .position(|(_, select)| references(select, &cte.name) > 0)
+    else {
+        unreachable!("recursive CTE must refer to itself");
+    };
+    if split == 0 {
+        bail!("circular reference: {}", cte.name);
+    }
+    if arms[split..]
+        .iter()
+        .any(|(_, select)| references(select, &cte.name) > 1)
+    {
+        bail!("multiple references to recursive table: {}", cte.name);
+    }
+
+    let (initial, recursive) = arms.split_at(split);
+    let recursive: Vec<_> = recursive
+        .iter()
+        .map(|(op, select)| {
+            let Some(op) = op else {
+                unreachable!("only the first select has no operator");
+            };
+            (*op, select.clone())
+        })
+        .collect();
+    let initial = initial
+        .iter()
+        .skip(1)
+        .fold(initial[0].1.clone(), |left, (op, right)| {
+            Command::Compound {
+                op: op.unwrap_or(CompoundOperator::UnionAll),
+                left: Box::new(left),
+                right: Box::new(right.clone()),
+            }
+        });
+    let columns = columns(cte, initial.column_names(db, None)?)?;
+
+    let order_by = order::sort_keys(order_by, &columns, false)?
+        .into_iter()
+        .map(|(key, descending)| match key {
+            SortKey::Column(i) => (i, descending),
+            SortKey::Expr(_) => unreachable!("only result columns are allowed"),
+        })
+        .collect();
+    let (count, offset) = match limit {
+        Some(limit) => order::limit_values(db, limit)?,
+        None => (None, 0),
+    };
+
+    Ok(Recursion {
+        name: cte.name.clone(),
+        columns,
+        initial,
+        distinct: recursive[0].0 == CompoundOperator::Union,
+        recursive,
+        order_by,
+        count,
+        offset,
+    })
+}
+
+/// Rows of a recursive CTE waiting for their turn
+enum Queue {
+    /// Rows in the order they were added
+    Fifo(VecDeque<Row>),
+    /// Rows ordered by the ORDER BY of the CTE, the lowest key is taken first
+    Sorted {
+        heap: BinaryHeap<Reverse<KeyedRow>>,
+        descending: Rc<[bool]>,
+    },
+}
+
+/// Rows of a recursive CTE.
+///
+/// Rows are taken one by one from a queue and the recursive select is run with the CTE
+/// containing just the current row, its result is added to the end of the queue.
+/// The recursive select of a row runs when the next row is read, so the recursion stops
+/// when the rows are no longer read. ORDER BY changes the queue to a priority queue,
+/// LIMIT stops the recursion.
+pub(super) struct RecursiveRows {
+    recursion: Rc<Recursion>,
+    started: bool,
+    queue: Queue,
+    /// rows added so far, used by UNION
+    seen: BTreeSet<Row>,
+    /// number of rows added to the queue
+    added: usize,
+    /// number of rows taken from the queue, including the rows skipped by OFFSET
+    taken: usize,
+    /// the last row taken from the queue, its recursive select didn't run yet
+    current: Option<Row>,
+}
+
+impl RecursiveRows {
+    pub(super) fn new(recursion: Rc<Recursion>) -> Self {
+        let queue = match recursion.order_by.is_empty() {
+            true => Queue::Fifo(VecDeque::new()),
+            false => Queue::Sorted {
+                heap: BinaryHeap::new(),
+                descending: recursion.order_by.iter().map(|&(_, d)| d).collect(),
+            },
+        };
+        Self {
+            recursion,
+            started: false,
+            queue,
+            seen: BTreeSet::new(),
+            added: 0,
+            taken: 0,
+            current: None,
+        }
+    }
+
+    fn add(&mut self, row: Row) -> Result<()> {
+        if self.recursion.distinct && !self.seen.insert(row.clone()) {
+            return Ok(());
+        }
+        if self.added >= MAX_RECURSIVE_ROWS {
+            bail!(
+                "recursive common table expression {} returned more than {} rows",
+                self.recursion.name,
+                MAX_RECURSIVE_ROWS
+            );
+        }
+        self.added += 1;
+        match &mut self.queue {
+            Queue::Fifo(queue) => queue.push_back(row),
+            Queue::Sorted { heap, descending } => heap.push(Reverse(KeyedRow {
+                key: self
+                    .recursion
+                    .order_by
+                    .iter()
+                    .map(|&(i, _)| row[i].clone())
+                    .collect(),
+                sequence: self.added,
+                descending: Rc::clone(descending),
+                row,
+            })),
+        }
+        Ok(())
+    }
+
+    /// Runs the recursive selects with the row as the content of the CTE
+    fn recurse(&mut self, db: &mut DB, row: Row) -> Result<()> {
+        let recursion = Rc::clone(&self.recursion);
+        let table = TableRef::Materialized {
+            name: recursion.name.clone(),
+            alias: None,
+            columns: recursion.columns.clone(),
+            rows: Rc::new(vec![row]),
+        };
+
+        for (op, select) in &recursion.recursive {
+            let mut select = select.clone();
+            replace(&mut select, &recursion.name, &table);
+
+            let (names, rows) = select.query(db, None)?;
+            if names.len() != recursion.columns.len() {
+                bail!(
+                    "SELECTs to the left and right of {} do not have the same number of result columns",
+                    op
+                );
+            }
+            for row in rows {
+                self.add(row)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Operator for RecursiveRows {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        if !self.started {
+            self.started = true;
+            let (_, rows) = self.recursion.initial.query(db, None)?;
+            for row in rows {
+                self.add(row)?;
+            }
+        }
+
+        loop {
+            if let Some(row) = self.current.take() {
+                self.recurse(db, row)?;
+            }
+
+            let recursion = &self.recursion;
+            if recursion
+                .count
+                .is_some_and(|count| self.taken >= recursion.offset + count)
+            {
+                return Ok(None);
+            }
+
+            let next = match &mut self.queue {
+                Queue::Fifo(queue) => queue.pop_front(),
+                Queue::Sorted { heap, .. } => heap.pop().map(|Reverse(keyed)| keyed.row),
+            };
+            let Some(row) = next else {
+                return Ok(None);
+            };
+            self.taken += 1;
+            self.current = Some(row.clone());
+
+            // skipped rows are still used by the recursive select
+            if self.taken > recursion.offset {
+                return Ok(Some(row));
+            }
+        }
+    }
+}
+
+/// Splits `SELECT ... UNION SELECT ...` into selects with the operator on their left side
+fn compound_arms(
+    select: &Command,
+    op: Option<CompoundOperator>,
+    arms: &mut Vec<(Option<CompoundOperator>, Command)>,
+) {
+    match select {
+        Command::Compound {
+            op: right_op,
+            left,
+            right,
+        } => {
+            compound_arms(left, op, arms);
+            compound_arms(right, Some(*right_op), arms);
+        }
+        select => arms.push((op, select.clone())),
+    }
+}
+
+/// Returns names of the CTE columns, either listed in the CTE definition or taken from the select
+fn columns(cte: &Cte, names: Vec<String>) -> Result<Vec<String>> {
+    if cte.columns.is_empty() {
+        return Ok(names);
+    }
+    if cte.columns.len() != names.len() {
+        bail!(
+            "table {} has {} values for {} columns",
+            cte.name,
+            names.len(),
+            cte.columns.len()
+        );
+    }
+    Ok(cte.columns.clone())
+}
+
+fn materialized(cte: &Cte, columns: Vec<String>, rows: Vec<Row>) -> TableRef {
+    TableRef::Materialized {
+        name: cte.name.clone(),
+        alias: None,
+        columns,
+        rows: Rc::new(rows),
+    }
+}
+
+fn subquery(cte: &Cte) -> TableRef {
+    TableRef::Subquery {
+        select: Box::new(cte.select.clone()),
+        alias: Some(cte.name.clone()),
+        columns: cte.columns.clone(),
+    }
+}
+
+/// Returns number of references to the table in the query
+fn references(select: &Command, name: &str) -> usize {
+    let mut count = 0;
+    select.clone().visit_table_refs(&mut |table| {
+        if matches!(table, TableRef::Table { name: n, .. } if n.eq_ignore_ascii_case(name)) {
+            count += 1;
+        }
+    });
+    count
+}
+
+/// Replaces references to the table `name` in the query by `table`, keeping their aliases
+fn replace(select: &mut Command, name: &str, table: &TableRef) {
+    select.visit_table_refs(&mut |table_ref| {
+        let TableRef::Table { name: n, alias } = table_ref else {
+            return;
+        };
+        if !n.eq_ignore_ascii_case(name) {
+            return;
+        }
+
+        let alias = alias.clone().unwrap_or_else(|| n.clone());
+        let mut table = table.clone();
+        match &mut table {
+            TableRef::Subquery { alias: a, .. }
+            | TableRef::Materialized { alias: a, .. }
+            | TableRef::Recursive { alias: a, .. } => *a = Some(alias),
+            TableRef::Table { .. } => unreachable!("tables are replaced by subqueries"),
+        }
+        *table_ref = table;
+    });
+}
@@ -0,0 +1,1039 @@
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, bail, Result};
+
+use super::parser::{Affinity, BinaryOperator, ColumnRef, Expr};
+use super::Command;
+use crate::db::{page::ColumnContent, DB};
+
+/// Values of the columns of all tables in the FROM clause
+pub(super) type Row = Vec<ColumnContent>;
+
+/// Columns of the tables in the FROM clause, in the same order as values in a `Row`.
+///
+/// Scope of a subquery starts with the columns of the enclosing queries,
+/// so correlated subqueries can refer to them.
+#[derive(Debug, Default, Clone)]
+pub(super) struct Scope {
+    columns: Vec<ScopeColumn>,
+    /// Number of columns of the enclosing queries at the beginning of the scope
+    outer: usize,
+    /// Nesting level of the query, columns of inner queries hide columns of outer queries
+    depth: usize,
+}
+
+#[derive(Debug, Clone)]
+struct ScopeColumn {
+    /// Position of the table in the FROM clause
+    source: usize,
+    /// Nesting level of the query the column belongs to
+    depth: usize,
+    /// Table name or alias
+    table: String,
+    name: String,
+    /// Column was merged into the column of the left table by USING or NATURAL join,
+    /// it is not a part of `*` and can only be referenced with a table name
+    merged: bool,
+    /// Hidden rowid of the table, it is not a part of `*` and can be referenced
+    /// as `rowid`, `oid` or `_rowid_` unless the table has a column of that name
+    rowid: bool,
+    /// Affinity of a table column, None for columns of derived tables
+    affinity: Option<Affinity>,
+}
+
+/// Names of the rowid of a table
+const ROWID_NAMES: [&str; 3] = ["rowid", "oid", "_rowid_"];
+
+/// Returns true if the name is one of the names of the rowid
+pub(super) fn is_rowid_name(name: &str) -> bool {
+    ROWID_NAMES
+        .iter()
+        .any(|rowid| rowid.eq_ignore_ascii_case(name))
+}
+
+impl Scope {
+    /// Returns an empty scope of a query nested in the query with the `outer` scope
+    pub(super) fn nested(outer: &Scope) -> Self {
+        Self {
+            columns: outer.columns.clone(),
+            outer: outer.columns.len(),
+            depth: outer.depth + 1,
+        }
+    }
+
+    pub(super) fn push(
+        &mut self,
+        source: usize,
+        table: &str,
+        name: &str,
+        affinity: Option<Affinity>,
+    ) {
+        self.columns.push(ScopeColumn {
+            source,
+            depth: self.depth,
+            table: table.to_string(),
+            name: name.to_string(),
+            merged: false,
+            rowid: false,
+            affinity,
+        });
+    }
+
+    /// Adds the hidden rowid column of the table
+    pub(super) fn push_rowid(&mut self, source: usize, table: &str) {
+        self.columns.push(ScopeColumn {
+            source,
+            depth: self.depth,
+            table: table.to_string(),
+            name: ROWID_NAMES[0].to_string(),
+            merged: false,
+            rowid: true,
+            affinity: Some(Affinity::Integer),
+        });
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub(super) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub(super) fn merge(&mut self, index: usize) {
+        self.columns[index].merged = true;
+    }
+
+    /// Returns position of the table in the FROM clause the column belongs to,
+    /// None for columns of the enclosing queries
+    pub(super) fn source(&self, index: usize) -> Option<usize> {
+        (index >= self.outer).then(|| self.columns[index].source)
+    }
+
+    /// Returns nesting level of the query the column belongs to
+    pub(super) fn column_depth(&self, index: usize) -> usize {
+        self.columns[index].depth
+    }
+
+    /// Returns table name or alias and name of the column
+    pub(super) fn name(&self, index: usize) -> (&str, &str) {
+        let column = &self.columns[index];
+        (&column.table, &column.name)
+    }
+
+    /// Returns indices of the columns selected by `*`
+    pub(super) fn all(&self) -> Vec<usize> {
+        (self.outer..self.columns.len())
+            .filter(|&i| !self.columns[i].merged && !self.columns[i].rowid)
+            .collect()
+    }
+
+    /// Returns indices of the columns selected by `table.*`
+    pub(super) fn all_from(&self, table: &str) -> Result<Vec<usize>> {
+        let indices: Vec<_> = (self.outer..self.columns.len())
+            .filter(|&i| {
+                self.columns[i].table.eq_ignore_ascii_case(table) && !self.columns[i].rowid
+            })
+            .collect();
+
+        if indices.is_empty() {
+            bail!("no such table: {}", table);
+        }
+        Ok(indices)
+    }
+
+    /// Returns index of the column in the row
+    pub(super) fn resolve(&self, column: &ColumnRef) -> Result<usize> {
+        // search the innermost query first
+        for depth in (0..=self.depth).rev() {
+            let mut found = self.columns.iter().enumerate().filter(|(_, c)| {
+                let name_matches = match c.rowid {
+                    true => {
+                        is_rowid_name(&column.column)
+                            && !self.columns.iter().any(|real| {
+                                !real.rowid
+                                    && real.depth == c.depth
+                                    && real.source == c.source
+                                    && real.name.eq_ignore_ascii_case(&column.column)
+                            })
+                    }
+                    false => c.name.eq_ignore_ascii_case(&column.column),
+                };
+                c.depth == depth
+                    && name_matches
+                    && match &column.table {
+                        Some(table) => c.table.eq_ignore_ascii_case(table),
+                        None => !c.merged,
+                    }
+            });
+
+            match (found.next(), found.next()) {
+                (Some((i, _)), None) => return Ok(i),
+                (Some(_), Some(_)) => bail!("ambiguous column name: {}", column),
+                (None, _) => {}
+            }
+        }
+
+        bail!("no such column: {}", column)
+    }
+}
+
+/// Evaluates the expression for the row
+pub(super) fn eval(
+    db: &mut DB,
+    expr: &Expr,
+    row: &[ColumnContent],
+    scope: &Scope,
+) -> Result<ColumnContent> {
+    let value = match expr {
+        Expr::Column(column) => {
+            let i = scope.resolve(column)?;
+            row.get(i)
+                .cloned()
+                .ok_or_else(|| anyhow!("column {} can't be used here", column))?
+        }
+        Expr::Literal(value) => value.clone(),
+        // parameters without a bound value are NULL
+        Expr::Parameter { .. } => ColumnContent::Null,
+        Expr::Not(expr) => match truth(&eval(db, expr, row, scope)?) {
+            Some(b) => bool_value(!b),
+            None => ColumnContent::Null,
+        },
+        Expr::IsNull { expr, negated } => {
+            let is_null = eval(db, expr, row, scope)? == ColumnContent::Null;
+            bool_value(is_null != *negated)
+        }
+        Expr::Binary { op, left, right } => {
+            let (left, left_affinity) = operand(db, left, row, scope)?;
+
+            // AND and OR use three-valued logic
+            match (op, truth(&left)) {
+                (BinaryOperator::And, Some(false)) => return Ok(bool_value(false)),
+                (BinaryOperator::Or, Some(true)) => return Ok(bool_value(true)),
+                _ => {}
+            }
+
+            let (right, right_affinity) = operand(db, right, row, scope)?;
+
+            match op {
+                BinaryOperator::And | BinaryOperator::Or => match (truth(&left), truth(&right)) {
+                    (_, Some(b)) if b == (*op == BinaryOperator::Or) => bool_value(b),
+                    (Some(_), Some(b)) => bool_value(b),
+                    _ => ColumnContent::Null,
+                },
+                BinaryOperator::Concat => match (left, right) {
+                    (ColumnContent::Null, _) | (_, ColumnContent::Null) => ColumnContent::Null,
+                    (left, right) => ColumnContent::Text(format!("{left}{right}")),
+                },
+                BinaryOperator::Add
+                | BinaryOperator::Sub
+                | BinaryOperator::Mul
+                | BinaryOperator::Div
+                | BinaryOperator::Rem => arithmetic(*op, &left, &right),
+                op => match compare_with(
+                    comparison_affinity(left_affinity, right_affinity),
+                    left,
+                    right,
+                ) {
+                    None => ColumnContent::Null,
+                    Some(ord) => bool_value(match op {
+                        BinaryOperator::Eq => ord == Ordering::Equal,
+                        BinaryOperator::NotEq => ord != Ordering::Equal,
+                        BinaryOperator::Lt => ord == Ordering::Less,
+                        BinaryOperator::LtEq => ord != Ordering::Greater,
+                        BinaryOperator::Gt => ord == Ordering::Greater,
+                        BinaryOperator::GtEq => ord != Ordering::Less,
+                        _ => unreachable!(),
+                    }),
+                },
+            }
+        }
+        Expr::InList {
+            expr,
+            list,
+            negated,
+            sorted: true,
+        } => {
+            let (value, affinity) = operand(db, expr, row, scope)?;
+            in_sorted(value, affinity, list, *negated)
+        }
+        Expr::InList {
+            expr,
+            list,
+            negated,
+            sorted: false,
+        } => {
+            let (value, affinity) = operand(db, expr, row, scope)?;
+            let mut values = Vec::new();
+            for item in list {
+                values.push(eval(db, item, row, scope)?);
+            }
+            in_values(value, affinity, values, *negated)
+        }
+        Expr::InSelect {
+            expr,
+            select,
+            negated,
+        } => {
+            let (value, affinity) = operand(db, expr, row, scope)?;
+            let rows = subquery(db, select, Some((scope, row)))?;
+            let values = rows.into_iter().map(|mut r| r.swap_remove(0));
+            in_values(value, affinity, values, *negated)
+        }
+        Expr::Exists(select) => {
+            let (_, rows) = select.query(db, Some((scope, row)))?;
+            bool_value(!rows.is_empty())
+        }
+        Expr::Subquery(select) => {
+            let rows = subquery(db, select, Some((scope, row)))?;
+            rows.into_iter()
+                .next()
+                .map_or(ColumnContent::Null, |mut r| r.swap_remove(0))
+        }
+        Expr::Function { name, args } => {
+            let mut values = Vec::new();
+            for arg in args {
+                values.push(eval(db, arg, row, scope)?);
+            }
+            function(name, values)?
+        }
+    };
+
+    Ok(value)
+}
+
+/// Returns the affinity of the column if the expression is a column,
+/// other expressions have no affinity
+fn expr_affinity(expr: &Expr, scope: &Scope) -> Option<Affinity> {
+    match expr {
+        Expr::Column(column) => scope
+            .resolve(column)
+            .ok()
+            .and_then(|i| scope.columns[i].affinity),
+        _ => None,
+    }
+}
+
+/// Evaluates an operand of a comparison, returns its value and the affinity of the column
+/// if the operand is a column. Other expressions have no affinity.
+fn operand(
+    db: &mut DB,
+    expr: &Expr,
+    row: &[ColumnContent],
+    scope: &Scope,
+) -> Result<(ColumnContent, Option<Affinity>)> {
+    match expr {
+        Expr::Column(column) => {
+            let i = scope.resolve(column)?;
+            let value = row
+                .get(i)
+                .cloned()
+                .ok_or_else(|| anyhow!("column {} can't be used here", column))?;
+            Ok((value, scope.columns[i].affinity))
+        }
+        expr => Ok((eval(db, expr, row, scope)?, None)),
+    }
+}
+
+/// Replaces subqueries that don't refer to the enclosing queries with their results,
+/// so they are executed only once and not for every row
+pub(super) fn prepare(db: &mut DB, expr: &Expr, scope: &Scope) -> Result<Expr> {
+    let prepare_box =
+        |db: &mut DB, expr: &Expr| -> Result<Box<Expr>> { Ok(Box::new(prepare(db, expr, scope)?)) };
+
+    let expr = match expr {
+        Expr::Column(_) | Expr::Literal(_) | Expr::Parameter { .. } => expr.clone(),
+        Expr::Binary { op, left, right } => Expr::Binary {
+            op: *op,
+            left: prepare_box(db, left)?,
+            right: prepare_box(db, right)?,
+        },
+        Expr::Not(expr) => Expr::Not(prepare_box(db, expr)?),
+        Expr::IsNull { expr, negated } => Expr::IsNull {
+            expr: prepare_box(db, expr)?,
+            negated: *negated,
+        },
+        Expr::InList {
+            expr,
+            list,
+            negated,
+            sorted,
+        } => Expr::InList {
+            expr: prepare_box(db, expr)?,
+            list: list
+                .iter()
+                .map(|e| prepare(db, e, scope))
+                .collect::<Result<_>>()?,
+            negated: *negated,
+            sorted: *sorted,
+        },
+        Expr::InSelect {
+            expr,
+            select,
+            negated,
+        } if !select.is_correlated(db, scope)? => {
+            let values = subquery(db, select, None)?
+                .into_iter()
+                .map(|mut r| r.swap_remove(0))
+                .collect();
+            sorted_list(prepare_box(db, expr)?, values, *negated, scope)
+        }
+        Expr::Exists(select) if !select.is_correlated(db, scope)? => {
+            let (_, rows) = select.query(db, None)?;
+            Expr::Literal(bool_value(!rows.is_empty()))
+        }
+        Expr::Subquery(select) if !select.is_correlated(db, scope)? => {
+            let rows = subquery(db, select, None)?;
+            Expr::Literal(
+                rows.into_iter()
+                    .next()
+                    .map_or(ColumnContent::Null, |mut r| r.swap_remove(0)),
+            )
+        }
+        Expr::InSelect { .. } | Expr::Exists(_) | Expr::Subquery(_) => expr.clone(),
+        Expr::Function { name, args } => {
+            check_arguments(name, args.len())?;
+            Expr::Function {
+                name: name.clone(),
+                args: args
+                    .iter()
+                    .map(|e| prepare(db, e, scope))
+                    .collect::<Result<_>>()?,
+            }
+        }
+    };
+
+    Ok(expr)
+}
+
+/// Returns `expr IN (values)` with the values converted like in the comparisons with `expr`
+/// and sorted, so they are looked up by binary search for each row
+fn sorted_list(expr: Box<Expr>, values: Vec<ColumnContent>, negated: bool, scope: &Scope) -> Expr {
+    let affinity = comparison_affinity(expr_affinity(&expr, scope), None);
+    let mut values: Vec<ColumnContent> = values
+        .into_iter()
+        .map(|value| match affinity {
+            Some(affinity) => apply_affinity(value, affinity),
+            None => value,
+        })
+        .collect();
+    values.sort();
+    values.dedup();
+
+    Expr::InList {
+        expr,
+        list: values.into_iter().map(Expr::Literal).collect(),
+        negated,
+        sorted: true,
+    }
+}
+
+/// Result of `value IN (list)` for a sorted list, see `sorted_list`
+fn in_sorted(
+    value: ColumnContent,
+    affinity: Option<Affinity>,
+    list: &[Expr],
+    negated: bool,
+) -> ColumnContent {
+    if list.is_empty() {
+        return bool_value(negated);
+    }
+    let value = match comparison_affinity(affinity, None) {
+        Some(affinity) => apply_affinity(value, affinity),
+        None => value,
+    };
+    if value == ColumnContent::Null {
+        return ColumnContent::Null;
+    }
+
+    let found = list
+        .binary_search_by(|item| match item {
+            Expr::Literal(item) => item.cmp(&value),
+            _ => Ordering::Less,
+        })
+        .is_ok();
+    match (found, &list[0]) {
+        (true, _) => bool_value(!negated),
+        // NULL is the first value, unknown unless the value is found
+        (false, Expr::Literal(ColumnContent::Null)) => ColumnContent::Null,
+        (false, _) => bool_value(negated),
+    }
+}
+
+/// Checks that the scalar function exists and accepts the number of arguments
+fn check_arguments(name: &str, count: usize) -> Result<()> {
+    let valid = match name {
+        "lower" | "upper" | "length" | "abs" => count == 1,
+        "ifnull" => count == 2,
+        "coalesce" => count >= 2,
+        _ => bail!("no such function: {name}"),
+    };
+    if !valid {
+        bail!("wrong number of arguments to function {name}()");
+    }
+    Ok(())
+}
+
+/// Calls the scalar function, NULL arguments give NULL except for `coalesce` and `ifnull`
+fn function(name: &str, args: Vec<ColumnContent>) -> Result<ColumnContent> {
+    check_arguments(name, args.len())?;
+
+    let value = match (name, args.first()) {
+        ("coalesce" | "ifnull", _) => args
+            .into_iter()
+            .find(|value| *value != ColumnContent::Null)
+            .unwrap_or(ColumnContent::Null),
+        (_, Some(ColumnContent::Null)) => ColumnContent::Null,
+        // like in SQLite only ASCII letters are converted
+        ("lower", Some(value)) => ColumnContent::Text(value.to_string().to_ascii_lowercase()),
+        ("upper", Some(value)) => ColumnContent::Text(value.to_string().to_ascii_uppercase()),
+        // the length of a blob is the number of bytes
+        ("length", Some(ColumnContent::Blob(b))) => ColumnContent::Int(b.len() as i64),
+        ("length", Some(value)) => ColumnContent::Int(value.to_string().chars().count() as i64),
+        ("abs", Some(value)) => match number(value) {
+            Some(ColumnContent::Int(i)) => ColumnContent::Int(i.wrapping_abs()),
+            Some(ColumnContent::Float(x)) => ColumnContent::Float(x.abs()),
+            _ => ColumnContent::Int(0),
+        },
+        _ => unreachable!("arguments are checked"),
+    };
+
+    Ok(value)
+}
+
+/// Executes subquery that must return a single column
+fn subquery(
+    db: &mut DB,
+    select: &Command,
+    outer: Option<(&Scope, &[ColumnContent])>,
+) -> Result<Vec<Row>> {
+    let (columns, rows) = select.query(db, outer)?;
+    if columns.len() != 1 {
+        bail!("sub-select returns {} columns - expected 1", columns.len());
+    }
+    Ok(rows)
+}
+
+/// Result of `value IN (values)`, values are compared like with `=`
+fn in_values(
+    value: ColumnContent,
+    affinity: Option<Affinity>,
+    values: impl IntoIterator<Item = ColumnContent>,
+    negated: bool,
+) -> ColumnContent {
+    let affinity = comparison_affinity(affinity, None);
+    let mut result = Some(false);
+    for v in values {
+        match compare_with(affinity, value.clone(), v) {
+            Some(Ordering::Equal) => {
+                result = Some(true);
+                break;
+            }
+            None => result = None, // unknown, unless we find a match
+            _ => {}
+        }
+    }
+
+    match result {
+        Some(b) => bool_value(b != negated),
+        None => ColumnContent::Null,
+    }
+}
+
+/// Integer arithmetic, or float arithmetic if any operand is a float. Text is converted
+/// to a number (0 if it doesn't look like one).
+/// The result is NULL if any operand is NULL or when dividing by zero.
+fn arithmetic(op: BinaryOperator, left: &ColumnContent, right: &ColumnContent) -> ColumnContent {
+    let (Some(left), Some(right)) = (number(left), number(right)) else {
+        return ColumnContent::Null;
+    };
+
+    let (left, right) = match (left, right) {
+        (ColumnContent::Int(left), ColumnContent::Int(right)) => {
+            let result = match op {
+                BinaryOperator::Div | BinaryOperator::Rem if right == 0 => {
+                    return ColumnContent::Null
+                }
+                BinaryOperator::Add => left.checked_add(right),
+                BinaryOperator::Sub => left.checked_sub(right),
+                BinaryOperator::Mul => left.checked_mul(right),
+                BinaryOperator::Div => left.checked_div(right),
+                BinaryOperator::Rem => Some(left.wrapping_rem(right)),
+                _ => unreachable!(),
+            };
+            match result {
+                Some(result) => return ColumnContent::Int(result),
+                // like in SQLite the result out of the range of integers is a float
+                None => (left as f64, right as f64),
+            }
+        }
+        (left, right) => (float(&left), float(&right)),
+    };
+
+    let result = match op {
+        BinaryOperator::Add => Some(left + right),
+        BinaryOperator::Sub => Some(left - right),
+        BinaryOperator::Mul => Some(left * right),
+        BinaryOperator::Div => (right != 0.0).then(|| left / right),
+        // like in SQLite the remainder is computed for the integer parts
+        BinaryOperator::Rem => (left as i64)
+            .checked_rem(right as i64)
+            .map(|remainder| remainder as f64),
+        _ => unreachable!(),
+    };
+    result.map_or(ColumnContent::Null, ColumnContent::Float)
+}
+
+/// Returns the value as an integer or a float, None for NULL
+fn number(value: &ColumnContent) -> Option<ColumnContent> {
+    match value {
+        ColumnContent::Int(_) | ColumnContent::Float(_) => Some(value.clone()),
+        ColumnContent::Text(_) | ColumnContent::Blob(_) => match numeric(&value.to_string()) {
+            number @ (ColumnContent::Int(_) | ColumnContent::Float(_)) => Some(number),
+            _ => Some(ColumnContent::Int(0)),
+        },
+        ColumnContent::Null => None,
+    }
+}
+
+/// Returns the numeric value as a float
+fn float(value: &ColumnContent) -> f64 {
+    match value {
+        ColumnContent::Int(i) => *i as f64,
+        ColumnContent::Float(x) => *x,
+        _ => 0.0,
+    }
+}
+
+/// Returns true if all terms of the condition are true
+pub(super) fn satisfies(
+    db: &mut DB,
+    terms: &[Expr],
+    row: &[ColumnContent],
+    scope: &Scope,
+) -> Result<bool> {
+    for term in terms {
+        if !is_true(&eval(db, term, row, scope)?) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns true if the value of the condition is true
+pub(super) fn is_true(value: &ColumnContent) -> bool {
+    truth(value) == Some(true)
+}
+
+/// Returns the boolean value of the value, None for NULL
+fn truth(value: &ColumnContent) -> Option<bool> {
+    match value {
+        ColumnContent::Null => None,
+        value => Some(number(value).is_some_and(|number| float(&number) != 0.0)),
+    }
+}
+
+fn bool_value(b: bool) -> ColumnContent {
+    ColumnContent::Int(b as i64)
+}
+
+/// Returns the affinity applied to both operands of a comparison: a numeric column makes
+/// the other operand numeric, a TEXT column makes an operand without affinity TEXT.
+/// Values of other operands are compared as they are.
+fn comparison_affinity(left: Option<Affinity>, right: Option<Affinity>) -> Option<Affinity> {
+    let numeric = |affinity| {
+        matches!(
+            affinity,
+            Some(Affinity::Integer | Affinity::Real | Affinity::Numeric)
+        )
+    };
+    match (left, right) {
+        _ if numeric(left) || numeric(right) => Some(Affinity::Numeric),
+        (Some(Affinity::Text), None) | (None, Some(Affinity::Text)) => Some(Affinity::Text),
+        _ => None,
+    }
+}
+
+/// Compares two values after converting them by the affinity, returns None if any of them
+/// is NULL
+fn compare_with(
+    affinity: Option<Affinity>,
+    left: ColumnContent,
+    right: ColumnContent,
+) -> Option<Ordering> {
+    match affinity {
+        Some(affinity) => compare(
+            &apply_affinity(left, affinity),
+            &apply_affinity(right, affinity),
+        ),
+        None => compare(&left, &right),
+    }
+}
+
+/// Compares two values by their storage class and value, returns None if any of them is NULL
+fn compare(left: &ColumnContent, right: &ColumnContent) -> Option<Ordering> {
+    match (left, right) {
+        (ColumnContent::Null, _) | (_, ColumnContent::Null) => None,
+        _ => Some(left.cmp(right)),
+    }
+}
+
+/// Converts text to a number if possible
+pub(super) fn numeric(s: &str) -> ColumnContent {
+    let trimmed = s.trim();
+    if let Ok(i) = trimmed.parse() {
+        return ColumnContent::Int(i);
+    }
+
+    // Rust also parses words like "inf" or "NaN" which SQLite doesn't
+    let is_float = trimmed.chars().any(|c| c.is_ascii_digit())
+        && trimmed
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+    match trimmed.parse() {
+        Ok(x) if is_float => ColumnContent::Float(x),
+        _ => ColumnContent::Text(s.to_string()),
+    }
+}
+
+/// Converts the value to the storage class of the affinity if it can be done without loss,
+/// like a value compared with a column or stored in it
+pub(super) fn apply_affinity(value: ColumnContent, affinity: Affinity) -> ColumnContent {
+    match (affinity, value) {
+        (Affinity::Integer | Affinity::Numeric | Affinity::Real, ColumnContent::Text(s)) => {
+            numeric(&s)
+        }
+        (Affinity::Text, value @ (ColumnContent::Int(_) | ColumnContent::Float(_))) => {
+            ColumnContent::Text(value.to_string())
+        }
+        (_, value) => value,
+    }
+}
+
+/// Splits the expression into terms connected by AND
+pub(super) fn conjuncts(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Binary {
+            op: BinaryOperator::And,
+            left,
+            right,
+        } => {
+            let mut terms = conjuncts(left);
+            terms.extend(conjuncts(right));
+            terms
+        }
+        expr => vec![expr],
+    }
+}
+
+/// Splits the expression into terms connected by OR
+pub(super) fn disjuncts(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Binary {
+            op: BinaryOperator::Or,
+            left,
+            right,
+        } => {
+            let mut terms = disjuncts(left);
+            terms.extend(disjuncts(right));
+            terms
+        }
+        expr => vec![expr],
+    }
+}
+
+/// Returns all columns the expression refers to, except those inside subqueries
+pub(super) fn column_refs(expr: &Expr) -> Vec<&ColumnRef> {
+    match expr {
+        Expr::Column(column) => vec![column],
+        Expr::Literal(_) | Expr::Parameter { .. } | Expr::Exists(_) | Expr::Subquery(_) => {
+            Vec::new()
+        }
+        Expr::Not(expr) | Expr::IsNull { expr, .. } | Expr::InSelect { expr, .. } => {
+            column_refs(expr)
+        }
+        Expr::Binary { left, right, .. } => {
+            let mut columns = column_refs(left);
+            columns.extend(column_refs(right));
+            columns
+        }
+        Expr::InList { expr, list, .. } => {
+            let mut columns = column_refs(expr);
+            columns.extend(list.iter().flat_map(column_refs));
+            columns
+        }
+        Expr::Function { args, .. } => args.iter().flat_map(column_refs).collect(),
+    }
+}
+
+/// Returns all subqueries in the expression (not nested in other subqueries)
+pub(super) fn subqueries(expr: &Expr) -> Vec<&Command> {
+    match expr {
+        Expr::Column(_) | Expr::Literal(_) | Expr::Parameter { .. } => Vec::new(),
+        Expr::Exists(select) | Expr::Subquery(select) => vec![select],
+        Expr::Not(expr) | Expr::IsNull { expr, .. } => subqueries(expr),
+        Expr::InSelect { expr, select, .. } => {
+            let mut selects = subqueries(expr);
+            selects.push(select);
+            selects
+        }
+        Expr::Binary { left, right, .. } => {
+            let mut selects = subqueries(left);
+            selects.extend(subqueries(right));
+            selects
+        }
+        Expr::InList { expr, list, .. } => {
+            let mut selects = subqueries(expr);
+            selects.extend(list.iter().flat_map(subqueries));
+            selects
+        }
+        Expr::Function { args, .. } => args.iter().flat_map(subqueries).collect(),
+    }
+}
+
+/// Returns all subqueries in the expression (not nested in other subqueries) for modification
+pub(super) fn subqueries_mut(expr: &mut Expr) -> Vec<&mut Command> {
+    match expr {
+        Expr::Column(_) | Expr::Literal(_) | Expr::Parameter { .. } => Vec::new(),
+        Expr::Exists(select) | Expr::Subquery(select) => vec![select],
+        Expr::Not(expr) | Expr::IsNull { expr, .. } => subqueries_mut(expr),
+        Expr::InSelect { expr, select, .. } => {
+            let mut selects = subqueries_mut(expr);
+            selects.push(select);
+            selects
+        }
+        Expr::Binary { left, right, .. } => {
+            let mut selects = subqueries_mut(left);
+            selects.extend(subqueries_mut(right));
+            selects
+        }
+        Expr::InList { expr, list, .. } => {
+            let mut selects = subqueries_mut(expr);
+            selects.extend(list.iter_mut().flat_map(subqueries_mut));
+            selects
+        }
+        Expr::Function { args, .. } => args.iter_mut().flat_map(subqueries_mut).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::sql::parser::{parse_expr, parse_sql};
+
+    fn eval_sql(sql: &str, row: &[ColumnContent], scope: &Scope) -> ColumnContent {
+        let mut db = DB::new("sample.db").unwrap();
+        let expr = parse_sql(sql, parse_expr).unwrap();
+        eval(&mut db, &expr, row, scope).unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut scope = Scope::default();
+        scope.push(0, "a", "id", None);
+        scope.push(0, "a", "name", None);
+        scope.push(1, "b", "id", None);
+        scope.push(1, "b", "color", None);
+
+        let column = |table: Option<&str>, column: &str| ColumnRef {
+            table: table.map(|t| t.to_string()),
+            column: column.to_string(),
+        };
+
+        assert_eq!(scope.resolve(&column(None, "name")).unwrap(), 1);
+        assert_eq!(scope.resolve(&column(Some("b"), "ID")).unwrap(), 2);
+        assert_eq!(
+            scope.resolve(&column(None, "id")).unwrap_err().to_string(),
+            "ambiguous column name: id"
+        );
+        assert_eq!(
+            scope
+                .resolve(&column(Some("a"), "color"))
+                .unwrap_err()
+                .to_string(),
+            "no such column: a.color"
+        );
+
+        // USING (id)
+        scope.merge(2);
+        assert_eq!(scope.resolve(&column(None, "id")).unwrap(), 0);
+        assert_eq!(scope.resolve(&column(Some("b"), "id")).unwrap(), 2);
+        assert_eq!(scope.all(), vec![0, 1, 3]);
+        assert_eq!(scope.all_from("b").unwrap(), vec![2, 3]);
+
+        // columns of the subquery hide columns of the enclosing query
+        let mut inner = Scope::nested(&scope);
+        inner.push(0, "c", "name", None);
+        assert_eq!(inner.resolve(&column(None, "name")).unwrap(), 4);
+        assert_eq!(inner.resolve(&column(None, "color")).unwrap(), 3);
+        assert_eq!(inner.source(3), None);
+        assert_eq!(inner.source(4), Some(0));
+        assert_eq!(inner.all(), vec![4]);
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut scope = Scope::default();
+        scope.push(0, "t", "id", Some(Affinity::Integer));
+        scope.push(0, "t", "name", Some(Affinity::Text));
+        scope.push(0, "t", "color", None);
+        let row = vec![
+            ColumnContent::Int(3),
+            ColumnContent::Text("Fuji".to_string()),
+            ColumnContent::Null,
+        ];
+
+        let t = ColumnContent::Int(1);
+        let f = ColumnContent::Int(0);
+        assert_eq!(eval_sql("name = 'Fuji'", &row, &scope), t);
+        assert_eq!(eval_sql("name = 'fuji'", &row, &scope), f);
+        assert_eq!(eval_sql("id = '3'", &row, &scope), t);
+        assert_eq!(eval_sql("'3' = id", &row, &scope), t);
+        assert_eq!(eval_sql("'3' = 3", &row, &scope), f);
+        assert_eq!(eval_sql("id IN ('3')", &row, &scope), t);
+        assert_eq!(eval_sql("id > 2 AND id <= 3", &row, &scope), t);
+        assert_eq!(eval_sql("color = 'Red'", &row, &scope), ColumnContent::Null);
+        assert_eq!(eval_sql("color = 'Red' OR id = 3", &row, &scope), t);
+        assert_eq!(eval_sql("color = 'Red' AND id = 4", &row, &scope), f);
+        assert_eq!(eval_sql("NOT color IS NULL", &row, &scope), f);
+        assert_eq!(
+            eval_sql("color IS NOT NULL OR name <> 'Fuji'", &row, &scope),
+            f
+        );
+        assert_eq!(eval_sql("id IN (1, 2, 3)", &row, &scope), t);
+        assert_eq!(eval_sql("id NOT IN (1, 2)", &row, &scope), t);
+        assert_eq!(
+            eval_sql("id IN (1, NULL)", &row, &scope),
+            ColumnContent::Null
+        );
+        assert_eq!(eval_sql("color IN ()", &row, &scope), f);
+    }
+
+    #[test]
+    fn test_eval_function() {
+        let mut scope = Scope::default();
+        scope.push(0, "t", "id", None);
+        scope.push(0, "t", "name", None);
+        scope.push(0, "t", "color", None);
+        let row = vec![
+            ColumnContent::Int(-3),
+            ColumnContent::Text("Fuji".to_string()),
+            ColumnContent::Null,
+        ];
+
+        let text = |s: &str| ColumnContent::Text(s.to_string());
+        assert_eq!(
+            eval_sql("lower(name) || upper(name)", &row, &scope),
+            text("fujiFUJI")
+        );
+        assert_eq!(
+            eval_sql("length(name) + length(id)", &row, &scope),
+            6.into()
+        );
+        assert_eq!(eval_sql("abs(id)", &row, &scope), 3.into());
+        assert_eq!(eval_sql("upper(color)", &row, &scope), ColumnContent::Null);
+        assert_eq!(
+            eval_sql("coalesce(color, NULL, id)", &row, &scope),
+            (-3).into()
+        );
+        assert_eq!(
+            eval_sql("ifnull(color, 'none')", &row, &scope),
+            text("none")
+        );
+
+        let mut db = DB::new("sample.db").unwrap();
+        let expr = parse_sql("lower(name, 1)", parse_expr).unwrap();
+        assert_eq!(
+            prepare(&mut db, &expr, &scope).unwrap_err().to_string(),
+            "wrong number of arguments to function lower()"
+        );
+        let expr = parse_sql("soundex(name)", parse_expr).unwrap();
+        assert_eq!(
+            eval(&mut db, &expr, &row, &scope).unwrap_err().to_string(),
+            "no such function: soundex"
+        );
+    }
+
+    #[test]
+    fn test_eval_float() {
+        let scope = Scope::default();
+        let float = |sql: &str| eval_sql(sql, &[], &scope).to_string();
+        assert_eq!(float("1.5 + 2"), "3.5");
+        assert_eq!(float("3 * 1.0"), "3.0");
+        assert_eq!(float("1 / 4.0"), "0.25");
+        assert_eq!(float("5.5 % 2"), "1.0");
+        assert_eq!(float("'2.5' * 2"), "5.0");
+        assert_eq!(float("abs(-2.5)"), "2.5");
+        assert_eq!(float("100.0 / 3"), "33.3333333333333");
+        assert_eq!(float("1e15"), "1.0e+15");
+        assert_eq!(eval_sql("1 / 0.0", &[], &scope), ColumnContent::Null);
+        assert_eq!(eval_sql("3 = 3.0", &[], &scope), 1.into());
+        assert_eq!(eval_sql("'3.5' > 3", &[], &scope), 1.into());
+        assert_eq!(eval_sql("x'00' > 'z'", &[], &scope), 1.into());
+        assert_eq!(eval_sql("length(x'CAFE')", &[], &scope), 2.into());
+
+        // only the negative one of the largest integers fits in 64 bits
+        assert_eq!(
+            eval_sql("-9223372036854775808", &[], &scope),
+            ColumnContent::Int(i64::MIN)
+        );
+        assert_eq!(float("9223372036854775808"), "9.22337203685478e+18");
+        assert_eq!(float("-9223372036854775809"), "-9.22337203685478e+18");
+        assert_eq!(float("-9223372036854775808 - 1"), "-9.22337203685478e+18");
+        assert_eq!(float("9223372036854775807 * 2"), "1.84467440737096e+19");
+    }
+
+    #[test]
+    fn test_eval_subquery() {
+        let mut scope = Scope::default();
+        scope.push(0, "t", "id", None);
+        let row = vec![ColumnContent::Int(3)];
+
+        let t = ColumnContent::Int(1);
+        let f = ColumnContent::Int(0);
+        assert_eq!(eval_sql("id IN (SELECT id FROM apples)", &row, &scope), t);
+        assert_eq!(
+            eval_sql(
+                "EXISTS (SELECT * FROM apples WHERE id > t.id)",
+                &row,
+                &scope
+            ),
+            t
+        );
+        assert_eq!(
+            eval_sql(
+                "NOT EXISTS (SELECT * FROM oranges o WHERE o.id = t.id)",
+                &row,
+                &scope
+            ),
+            f
+        );
+        assert_eq!(
+            eval_sql(
+                "(SELECT name FROM apples WHERE apples.id = t.id)",
+                &row,
+                &scope
+            ),
+            ColumnContent::Text("Honeycrisp".to_string())
+        );
+        assert_eq!(
+            eval_sql(
+                "(SELECT COUNT(*) FROM apples WHERE id >= t.id)",
+                &row,
+                &scope
+            ),
+            ColumnContent::Int(2)
+        );
+        assert_eq!(
+            eval_sql("(SELECT name FROM apples WHERE id > 10)", &row, &scope),
+            ColumnContent::Null
+        );
+
+        let mut db = DB::new("sample.db").unwrap();
+        let expr = parse_sql("id IN (SELECT id, name FROM apples)", parse_expr).unwrap();
+        assert_eq!(
+            eval(&mut db, &expr, &row, &scope).unwrap_err().to_string(),
+            "sub-select returns 2 columns - expected 1"
+        );
+    }
+}
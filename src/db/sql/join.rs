@@ -0,0 +1,1766 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::ops::Bound;
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+
+use super::cte::{Recursion, RecursiveRows};
+use super::eval::{self, Row, Scope};
+use super::operator::{self, Filter, Operator, Values};
+use super::parser::{
+    parse_default_value, parse_expr, parse_sql, Affinity, BinaryOperator, ColumnRef, Expr,
+    FromClause, IndexDef, IndexKey, JoinConstraint, JoinOperator, TableRef,
+};
+use super::plan::{PlanIndex, PlanStep};
+use super::{visit_expr, Command};
+use crate::db::{
+    page::ColumnContent,
+    pager::{CellFilter, Cursor, Tree},
+    schema::SchemaType,
+    Index, Table, DB,
+};
+
+/// Table in the FROM clause
+struct Source {
+    kind: SourceKind,
+    /// alias of the table or its name
+    name: String,
+    columns: Vec<String>,
+    /// Index of the first column of the table in the row
+    first_column: usize,
+    /// How the table is joined to the tables on its left side (`Inner` for the first table)
+    operator: JoinOperator,
+    /// Join condition from ON, USING or NATURAL, split into terms connected by AND
+    constraint: Vec<Expr>,
+    access: Access,
+    /// Rows are read in the reverse order of the access
+    backward: bool,
+    /// VIRTUAL columns of the table, computed for each row read
+    virtual_columns: Option<VirtualColumns>,
+}
+
+enum SourceKind {
+    Table {
+        table: Table,
+        root_page: u64,
+    },
+    /// Derived table `(SELECT ...) AS alias`, its rows are computed once and scanned
+    Derived(Command),
+    /// Rows of a common table expression computed in advance
+    Materialized(Rc<Vec<Row>>),
+    /// Recursive common table expression, its rows are computed while they are scanned
+    Recursive(Rc<Recursion>),
+}
+
+/// How rows of a table are looked up for each row of the tables on its left side
+#[derive(Debug)]
+enum Access {
+    /// Full table scan, done once and reused
+    Scan,
+    /// Seek by row_id equal to the expression value
+    RowId(Expr),
+    /// Seek by row_id between the values of the expressions
+    RowIdRange {
+        lower: Bound<Expr>,
+        upper: Bound<Expr>,
+    },
+    /// Seek in the primary key b-tree of a WITHOUT ROWID table for keys starting with
+    /// the values of the expressions
+    PrimaryKey(Vec<Expr>),
+    /// Seek in the index for keys starting with the values of the expressions,
+    /// the next column of the key between `lower` and `upper` in the order of the index
+    Index {
+        name: String,
+        root_page: u64,
+        /// key expressions of the index with true for DESC keys
+        columns: Vec<(Expr, bool)>,
+        keys: Vec<Expr>,
+        lower: Bound<Expr>,
+        upper: Bound<Expr>,
+        /// columns of the row with the fields of the index record they are read from,
+        /// if the index has all columns of the table used by the query
+        covered: Option<Vec<(usize, usize)>>,
+    },
+    /// Seeks for each value of `IN (values)` or each side of OR, rows found by more of them
+    /// are returned once
+    Union(Vec<Access>),
+}
+
+/// Returns rows of all tables in the FROM clause joined together (nested loop join)
+/// and filtered by the WHERE condition.
+///
+/// For subqueries `outer` is the scope and the current row of the enclosing query,
+/// the returned rows start with the values of the outer row.
+///
+/// `used` are the expressions computed from the rows, None if all columns are used.
+/// Columns not used by them or the WHERE condition may be NULL in the rows.
+pub(super) fn rows(
+    db: &mut DB,
+    from: Option<&FromClause>,
+    where_cond: Option<&Expr>,
+    outer: Option<(&Scope, &[ColumnContent])>,
+    used: Option<&[Expr]>,
+) -> Result<(Rc<Scope>, Box<dyn Operator>)> {
+    let (scope, rows, _) = ordered_rows(db, from, where_cond, outer, &[], used)?;
+    Ok((scope, rows))
+}
+
+/// Returns rows like `rows` and true if they are already sorted by the ORDER BY terms,
+/// expressions of the columns of the tables with true for DESC, because the first table
+/// is read in the order of the terms (or in the reverse order).
+pub(super) fn ordered_rows(
+    db: &mut DB,
+    from: Option<&FromClause>,
+    where_cond: Option<&Expr>,
+    outer: Option<(&Scope, &[ColumnContent])>,
+    order_by: &[(Expr, bool)],
+    used: Option<&[Expr]>,
+) -> Result<(Rc<Scope>, Box<dyn Operator>, bool)> {
+    let outer_scope = outer.map(|(scope, _)| scope);
+    let Plan {
+        scope,
+        sources,
+        where_terms,
+        filters,
+        ordered,
+    } = plan(db, from, where_cond, outer_scope, order_by, used)?;
+    let scope = Rc::new(scope);
+
+    let first_row = outer.map(|(_, row)| row.to_vec()).unwrap_or_default();
+    let mut rows: Box<dyn Operator> = Box::new(Values::new(vec![first_row]));
+    if sources.is_empty() {
+        // SELECT without FROM has a single row
+        rows = Box::new(Filter::new(rows, where_terms, scope.clone()));
+        return Ok((scope, rows, true));
+    }
+
+    for (i, (source, filter)) in sources.into_iter().zip(filters).enumerate() {
+        rows = Box::new(Join {
+            left: rows,
+            source: Rc::new(source),
+            scope: scope.clone(),
+            scanned: None,
+            reuse_scan: i > 0,
+            current: None,
+        });
+        if !filter.is_empty() {
+            rows = Box::new(Filter::new(rows, filter, scope.clone()));
+        }
+    }
+
+    Ok((scope, rows, ordered))
+}
+
+/// Rows of the tables on the left side joined with the rows of the table looked up
+/// for each of them, LEFT JOIN adds NULL values for the rows without any
+struct Join {
+    left: Box<dyn Operator>,
+    source: Rc<Source>,
+    scope: Rc<Scope>,
+    /// rows of the full scan of the table, read once for all rows of the left side
+    scanned: Option<Rc<Vec<Row>>>,
+    /// rows of the scan are kept for the next rows of the left side, false for the first table
+    /// which is scanned once and streamed
+    reuse_scan: bool,
+    /// current row of the left side, the rows of the table looked up for it and true
+    /// once one of them satisfied the join constraint
+    current: Option<(Row, Box<dyn Operator>, bool)>,
+}
+
+impl Operator for Join {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        loop {
+            if self.current.is_none() {
+                let Some(row) = self.left.next(db)? else {
+                    return Ok(None);
+                };
+                let inner_rows = self.inner_rows(db, &row)?;
+                self.current = Some((row, inner_rows, false));
+            }
+            let Some((row, inner_rows, matched)) = &mut self.current else {
+                continue;
+            };
+
+            match inner_rows.next(db)? {
+                Some(inner_row) => {
+                    let mut joined_row = row.clone();
+                    joined_row.extend(inner_row);
+                    if eval::satisfies(db, &self.source.constraint, &joined_row, &self.scope)? {
+                        *matched = true;
+                        return Ok(Some(joined_row));
+                    }
+                }
+                None => {
+                    // the next row of the left side is read
+                    let Some((mut row, _, matched)) = self.current.take() else {
+                        continue;
+                    };
+                    if !matched && self.source.operator == JoinOperator::Left {
+                        row.resize(
+                            self.source.first_column + self.source.width(),
+                            ColumnContent::Null,
+                        );
+                        return Ok(Some(row));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Join {
+    fn inner_rows(&mut self, db: &mut DB, row: &[ColumnContent]) -> Result<Box<dyn Operator>> {
+        let access = &self.source.access;
+        if !matches!(access, Access::Scan) || !self.reuse_scan {
+            return lookup(db, &self.source, access, row, &self.scope);
+        }
+        let scanned = match &self.scanned {
+            Some(scanned) => scanned.clone(),
+            None => {
+                let rows = lookup(db, &self.source, access, row, &self.scope)?;
+                let scanned = Rc::new(operator::collect(db, rows)?);
+                self.scanned.insert(scanned).clone()
+            }
+        };
+        Ok(Box::new(Values::shared(scanned)))
+    }
+}
+
+/// Tables of the FROM clause with the way their rows are looked up
+struct Plan {
+    scope: Scope,
+    sources: Vec<Source>,
+    where_terms: Vec<Expr>,
+    /// WHERE terms checked after joining each table
+    filters: Vec<Vec<Expr>>,
+    /// rows are read in the order of the ORDER BY terms
+    ordered: bool,
+}
+
+/// Chooses how rows of the tables are looked up for `ordered_rows`
+fn plan(
+    db: &mut DB,
+    from: Option<&FromClause>,
+    where_cond: Option<&Expr>,
+    outer: Option<&Scope>,
+    order_by: &[(Expr, bool)],
+    used: Option<&[Expr]>,
+) -> Result<Plan> {
+    let (scope, mut sources) = sources(db, from, outer)?;
+
+    let mut where_terms = Vec::new();
+    for term in where_cond.map(eval::conjuncts).unwrap_or_default() {
+        where_terms.push(eval::prepare(db, term, &scope)?);
+    }
+    if sources.is_empty() {
+        return Ok(Plan {
+            scope,
+            sources,
+            where_terms,
+            filters: Vec::new(),
+            ordered: true,
+        });
+    }
+
+    // WHERE terms are checked as soon as all tables they refer to are joined
+    let mut filters = vec![Vec::new(); sources.len()];
+    for term in &where_terms {
+        let mut level = 0;
+        for column in eval::column_refs(term) {
+            let source = scope.source(scope.resolve(column)?);
+            level = level.max(source.unwrap_or_default());
+        }
+        if !eval::subqueries(term).is_empty() {
+            // correlated subqueries may refer to any table
+            level = sources.len() - 1;
+        }
+        filters[level].push(term.clone());
+    }
+    for source in sources.iter_mut() {
+        for column in source.constraint.iter().flat_map(eval::column_refs) {
+            scope.resolve(column)?;
+        }
+        for term in source.constraint.iter_mut() {
+            *term = eval::prepare(db, term, &scope)?;
+        }
+    }
+
+    // columns the query refers to, an index having all of them is read without the table
+    let mut used_columns = used.map(|_| Vec::new());
+    let exprs = used.into_iter().flatten().chain(&where_terms);
+    let exprs = exprs.chain(order_by.iter().map(|(expr, _)| expr));
+    for expr in exprs.chain(sources.iter().flat_map(|source| &source.constraint)) {
+        // correlated subqueries may refer to any column
+        let columns: Option<Vec<usize>> = match eval::subqueries(expr).is_empty() {
+            true => eval::column_refs(expr)
+                .into_iter()
+                .map(|column| scope.resolve(column).ok())
+                .collect(),
+            false => None,
+        };
+        used_columns = used_columns.zip(columns).map(|(mut used, columns)| {
+            used.extend(columns);
+            used
+        });
+    }
+
+    for (i, source) in sources.iter_mut().enumerate() {
+        // WHERE condition can be used to look up rows unless it would skip NULL rows of a LEFT JOIN
+        let mut terms: Vec<&Expr> = source.constraint.iter().collect();
+        if source.operator == JoinOperator::Inner {
+            terms.extend(&where_terms);
+        }
+        let used: Option<Vec<usize>> = used_columns.as_ref().map(|used| {
+            let mut used: Vec<usize> = used
+                .iter()
+                .filter(|&&column| scope.source(column) == Some(i))
+                .map(|column| column - source.first_column)
+                .collect();
+            used.sort();
+            used.dedup();
+            used
+        });
+        source.access = access(db, &scope, i, source, &terms, used.as_deref());
+    }
+    // the order of the rows of the first table is kept by the nested loop join
+    let backward = read_backward(db, &scope, &sources[0], order_by);
+    let ordered = backward.is_some();
+    sources[0].backward = backward.unwrap_or_default();
+
+    Ok(Plan {
+        scope,
+        sources,
+        where_terms,
+        filters,
+        ordered,
+    })
+}
+
+/// Returns the steps of reading the rows like `ordered_rows` does and true if they are
+/// sorted by the ORDER BY terms
+pub(super) fn explain(
+    db: &mut DB,
+    from: Option<&FromClause>,
+    where_cond: Option<&Expr>,
+    outer: Option<&Scope>,
+    order_by: &[(Expr, bool)],
+    used: Option<&[Expr]>,
+) -> Result<(Vec<PlanStep>, bool)> {
+    let plan = plan(db, from, where_cond, outer, order_by, used)?;
+    if plan.sources.is_empty() {
+        return Ok((vec![PlanStep::ConstantRow], true));
+    }
+
+    let mut steps = Vec::new();
+    for source in &plan.sources {
+        steps.extend(explain_source(db, source, &source.access)?);
+    }
+    Ok((steps, plan.ordered))
+}
+
+/// Describes how rows of the table are read by the access
+fn explain_source(db: &mut DB, source: &Source, access: &Access) -> Result<Vec<PlanStep>> {
+    let table = source.name.clone();
+    let table_def = match &source.kind {
+        SourceKind::Table { table, .. } => table,
+        SourceKind::Derived(select) => {
+            let steps = select.plan(db, None)?;
+            return Ok(vec![
+                PlanStep::Nested {
+                    detail: format!("MATERIALIZE {table}"),
+                    steps,
+                },
+                PlanStep::Scan { table, index: None },
+            ]);
+        }
+        SourceKind::Materialized(_) | SourceKind::Recursive(_) => {
+            return Ok(vec![PlanStep::Scan { table, index: None }])
+        }
+    };
+    // constraints of the keys like `a=?` or `b>?`
+    let range = |key: &str, lower: &Bound<Expr>, upper: &Bound<Expr>| {
+        let lower = match lower {
+            Bound::Included(_) => Some(format!("{key}>=?")),
+            Bound::Excluded(_) => Some(format!("{key}>?")),
+            Bound::Unbounded => None,
+        };
+        let upper = match upper {
+            Bound::Included(_) => Some(format!("{key}<=?")),
+            Bound::Excluded(_) => Some(format!("{key}<?")),
+            Bound::Unbounded => None,
+        };
+        lower.into_iter().chain(upper).collect::<Vec<_>>()
+    };
+
+    let step = match access {
+        Access::Scan => PlanStep::Scan { table, index: None },
+        Access::RowId(_) => PlanStep::Search {
+            table,
+            index: Some(PlanIndex::IntegerPrimaryKey),
+            constraints: vec![format!("{ROWID}=?")],
+        },
+        Access::RowIdRange { lower, upper } => PlanStep::Search {
+            table,
+            index: Some(PlanIndex::IntegerPrimaryKey),
+            constraints: range(ROWID, lower, upper),
+        },
+        Access::PrimaryKey(keys) => PlanStep::Search {
+            table,
+            index: Some(PlanIndex::PrimaryKey),
+            constraints: table_def.primary_key[..keys.len()]
+                .iter()
+                .map(|&(c, _)| format!("{}=?", table_def.columns[c]))
+                .collect(),
+        },
+        Access::Index {
+            name,
+            columns,
+            keys,
+            lower,
+            upper,
+            covered,
+            ..
+        } => {
+            let mut constraints: Vec<String> = columns[..keys.len()]
+                .iter()
+                .map(|(key, _)| format!("{key}=?"))
+                .collect();
+            if let Some((key, descending)) = columns.get(keys.len()) {
+                // the bounds are in the order of the index, greater values first in a DESC key
+                constraints.extend(match descending {
+                    true => range(&key.to_string(), upper, lower),
+                    false => range(&key.to_string(), lower, upper),
+                });
+            }
+            PlanStep::Search {
+                table,
+                index: Some(PlanIndex::Index {
+                    name: name.clone(),
+                    covering: covered.is_some(),
+                }),
+                constraints,
+            }
+        }
+        Access::Union(accesses) => {
+            let mut searches = Vec::new();
+            for access in accesses {
+                searches.extend(explain_source(db, source, access)?);
+            }
+            PlanStep::MultiIndexOr(searches)
+        }
+    };
+    Ok(vec![step])
+}
+
+/// Returns the number of rows of the only table in the FROM clause counted from the smallest
+/// b-tree having all its rows, None if the FROM clause has other sources or joins
+pub(super) fn table_count(
+    db: &mut DB,
+    from: Option<&FromClause>,
+    outer: Option<&Scope>,
+) -> Result<Option<u64>> {
+    let (_, sources) = sources(db, from, outer)?;
+    let [source] = sources.as_slice() else {
+        return Ok(None);
+    };
+    let SourceKind::Table { table, root_page } = &source.kind else {
+        return Ok(None);
+    };
+    let root_page = match smallest_index(db, table) {
+        Some(index) => index.root_page,
+        None => *root_page,
+    };
+
+    let count = Tree::new(&mut db.pager)
+        .count(root_page)
+        .with_context(|| format!("count rows of table {}", table.name))?;
+    Ok(Some(count))
+}
+
+/// Returns the step of counting the rows like `table_count`
+pub(super) fn explain_count(
+    db: &DB,
+    from: Option<&FromClause>,
+    outer: Option<&Scope>,
+) -> Result<Option<PlanStep>> {
+    let (_, sources) = sources(db, from, outer)?;
+    let [source] = sources.as_slice() else {
+        return Ok(None);
+    };
+    let SourceKind::Table { table, .. } = &source.kind else {
+        return Ok(None);
+    };
+    let index = smallest_index(db, table).map(|index| PlanIndex::Index {
+        name: index.definition.name,
+        covering: true,
+    });
+    Ok(Some(PlanStep::Scan {
+        table: source.name.clone(),
+        index,
+    }))
+}
+
+/// Returns the index with a record for every row of the table, shorter than the rows
+fn smallest_index(db: &DB, table: &Table) -> Option<Index> {
+    // an index without WHERE has a record for every row, with less keys than the table
+    // has columns its records are shorter than the rows
+    db.table_indexes(&table.name)
+        .into_iter()
+        .filter(|index| index.definition.where_cond.is_none())
+        .min_by_key(|index| index.definition.columns.len())
+        .filter(|index| index.definition.columns.len() < table.columns.len())
+}
+
+/// B-tree ordered by a column of the table
+enum OrderedTree {
+    /// the table b-tree ordered by the rowid
+    Table,
+    /// the b-tree of a WITHOUT ROWID table starting with the primary key
+    PrimaryKey,
+    Index(Index),
+}
+
+/// Returns the least value of the column of the only table in the FROM clause, the greatest
+/// if `max`, read from the edge of a b-tree ordered by the column. None if there is no such
+/// b-tree or the FROM clause has other sources or joins.
+pub(super) fn table_extremum(
+    db: &mut DB,
+    from: Option<&FromClause>,
+    outer: Option<&Scope>,
+    column: &str,
+    max: bool,
+) -> Result<Option<ColumnContent>> {
+    let (_, sources) = sources(db, from, outer)?;
+    let [source] = sources.as_slice() else {
+        return Ok(None);
+    };
+    let SourceKind::Table { table, root_page } = &source.kind else {
+        return Ok(None);
+    };
+
+    let root_page = match ordered_tree(db, table, column) {
+        Some(OrderedTree::Table) => {
+            let row_id = Tree::new(&mut db.pager).edge_row_id(*root_page, max)?;
+            return Ok(Some(row_id.map_or(ColumnContent::Null, ColumnContent::Int)));
+        }
+        Some(OrderedTree::PrimaryKey) => *root_page,
+        Some(OrderedTree::Index(index)) => index.root_page,
+        None => return Ok(None),
+    };
+
+    // REAL values without a fraction are stored as integers
+    let affinity = table_column_index(table, column)
+        .and_then(|i| table.affinities.get(i).copied())
+        .unwrap_or_default();
+    let record = Tree::new(&mut db.pager).edge_record(root_page, max)?;
+    Ok(Some(record.map_or(ColumnContent::Null, |mut record| {
+        stored_value(record.swap_remove(0), affinity)
+    })))
+}
+
+/// Returns the step of reading the least or the greatest value like `table_extremum`
+pub(super) fn explain_extremum(
+    db: &DB,
+    from: Option<&FromClause>,
+    outer: Option<&Scope>,
+    column: &str,
+) -> Result<Option<PlanStep>> {
+    let (_, sources) = sources(db, from, outer)?;
+    let [source] = sources.as_slice() else {
+        return Ok(None);
+    };
+    let SourceKind::Table { table, .. } = &source.kind else {
+        return Ok(None);
+    };
+
+    let index = match ordered_tree(db, table, column) {
+        Some(OrderedTree::Table) => None,
+        Some(OrderedTree::PrimaryKey) => Some(PlanIndex::PrimaryKey),
+        Some(OrderedTree::Index(index)) => Some(PlanIndex::Index {
+            name: index.definition.name,
+            covering: true,
+        }),
+        None => return Ok(None),
+    };
+    Ok(Some(PlanStep::Search {
+        table: source.name.clone(),
+        index,
+        constraints: Vec::new(),
+    }))
+}
+
+/// Returns the b-tree ordered by the column, with NULLs first
+fn ordered_tree(db: &DB, table: &Table, column: &str) -> Option<OrderedTree> {
+    let column = table_column_index(table, column)?;
+
+    if !table.without_rowid
+        && (column == table.columns.len() || table.rowid_column == Some(column as u16))
+    {
+        return Some(OrderedTree::Table);
+    }
+
+    // the b-tree of a WITHOUT ROWID table starts with the primary key, indexes with their keys,
+    // only keys in the ascending binary order are used
+    let ascending = |descending: bool| !descending || !descending_keys(db);
+    match table.primary_key.first() {
+        Some(&(key, descending)) if table.without_rowid && key == column => {
+            ascending(descending).then_some(OrderedTree::PrimaryKey)
+        }
+        _ => db
+            .table_indexes(&table.name)
+            .into_iter()
+            .find(|index| {
+                let first = index.definition.columns.first();
+                index.definition.where_cond.is_none()
+                    && first.is_some_and(|key| {
+                        is_binary(key)
+                            && ascending(key.descending)
+                            && matches!(&key.expr, Expr::Column(c)
+                                if table_column_index(table, &c.column) == Some(column))
+                    })
+            })
+            .map(OrderedTree::Index),
+    }
+}
+
+/// Loads schemas of all tables in the FROM clause and resolves USING and NATURAL join constraints
+fn sources(
+    db: &DB,
+    from: Option<&FromClause>,
+    outer: Option<&Scope>,
+) -> Result<(Scope, Vec<Source>)> {
+    let mut scope = outer.map(Scope::nested).unwrap_or_default();
+    let mut sources = Vec::new();
+
+    for (i, table_ref) in from.iter().flat_map(|from| from.tables()).enumerate() {
+        let (kind, columns) = match table_ref {
+            TableRef::Table { name, .. } if db.schema(name, SchemaType::View).is_some() => {
+                let view = db.view(name)?;
+                let columns = super::view_columns(db, &view)?;
+                (SourceKind::Derived(view.select), columns)
+            }
+            TableRef::Table { name, .. } => {
+                let table = db
+                    .table(name)
+                    .with_context(|| format!("get schema columns for table {}", name))?;
+                let root_page = db.root_page_num(&table.name, SchemaType::Table)?;
+                let columns = table.columns.clone();
+                (SourceKind::Table { table, root_page }, columns)
+            }
+            TableRef::Subquery {
+                select,
+                columns,
+                alias,
+            } => {
+                let names = select.column_names(db, None)?;
+                let columns = match columns.is_empty() {
+                    true => names,
+                    false if columns.len() == names.len() => columns.clone(),
+                    false => bail!(
+                        "table {} has {} values for {} columns",
+                        alias.as_deref().unwrap_or_default(),
+                        names.len(),
+                        columns.len()
+                    ),
+                };
+                (SourceKind::Derived(select.as_ref().clone()), columns)
+            }
+            TableRef::Materialized { columns, rows, .. } => {
+                (SourceKind::Materialized(Rc::clone(rows)), columns.clone())
+            }
+            TableRef::Recursive { recursion, .. } => (
+                SourceKind::Recursive(Rc::clone(recursion)),
+                recursion.columns.clone(),
+            ),
+        };
+        let alias = table_ref.alias();
+
+        let mut operator = JoinOperator::Inner;
+        let mut constraint = Vec::new();
+        let mut using = Vec::new();
+
+        if let Some(join) = i
+            .checked_sub(1)
+            .and_then(|j| from.map(|from| &from.joins[j]))
+        {
+            operator = join.operator;
+
+            if join.natural {
+                // columns with the same name in both tables
+                using.extend(columns.iter().filter(|&c| {
+                    scope
+                        .resolve(&ColumnRef {
+                            table: None,
+                            column: c.clone(),
+                        })
+                        .is_ok_and(|c| scope.source(c).is_some())
+                }));
+            }
+
+            match &join.constraint {
+                Some(_) if join.natural => {
+                    bail!("a NATURAL join may not have an ON or USING clause")
+                }
+                Some(JoinConstraint::On(expr)) => {
+                    constraint.extend(eval::conjuncts(expr).into_iter().cloned());
+                }
+                Some(JoinConstraint::Using(columns)) => using.extend(columns),
+                None => {}
+            }
+        }
+
+        // USING (column) is a shortcut for left.column = right.column
+        let mut merged = Vec::new();
+        for column in using {
+            let left = scope
+                .resolve(&ColumnRef {
+                    table: None,
+                    column: column.clone(),
+                })
+                .with_context(|| {
+                    format!("cannot join using column {column} - column not present in both tables")
+                })?;
+            if scope.source(left).is_none() {
+                bail!("cannot join using column {column} - column not present in both tables");
+            }
+            let Some(right) = columns.iter().position(|c| c.eq_ignore_ascii_case(column)) else {
+                bail!("cannot join using column {column} - column not present in both tables");
+            };
+
+            let (left_table, left_column) = scope.name(left);
+            constraint.push(Expr::binary(
+                BinaryOperator::Eq,
+                Expr::column(Some(left_table), left_column),
+                Expr::column(Some(alias), &columns[right]),
+            ));
+            merged.push(scope.len() + right);
+        }
+
+        let first_column = scope.len();
+        for (j, column) in columns.iter().enumerate() {
+            let affinity = match &kind {
+                SourceKind::Table { table, .. } => Some(table.affinities[j]),
+                _ => None,
+            };
+            scope.push(i, alias, column, affinity);
+        }
+        if matches!(&kind, SourceKind::Table { table, .. } if !table.without_rowid) {
+            scope.push_rowid(i, alias);
+        }
+        for column in merged {
+            scope.merge(column);
+        }
+
+        let virtual_columns = match &kind {
+            SourceKind::Table { table, .. } => VirtualColumns::new(table)?,
+            _ => None,
+        };
+        sources.push(Source {
+            kind,
+            name: alias.to_string(),
+            columns,
+            first_column,
+            operator,
+            constraint,
+            access: Access::Scan,
+            backward: false,
+            virtual_columns,
+        });
+    }
+
+    Ok((scope, sources))
+}
+
+/// Returns scope of the columns of all tables in the FROM clause
+pub(super) fn scope(db: &DB, from: Option<&FromClause>, outer: Option<&Scope>) -> Result<Scope> {
+    Ok(sources(db, from, outer)?.0)
+}
+
+impl Source {
+    /// Number of values of the table in the row, tables have the hidden rowid after their columns
+    /// unless they are WITHOUT ROWID
+    fn width(&self) -> usize {
+        match &self.kind {
+            SourceKind::Table { table, .. } if table.without_rowid => self.columns.len(),
+            SourceKind::Table { .. } => self.columns.len() + 1,
+            SourceKind::Derived(_) | SourceKind::Materialized(_) | SourceKind::Recursive(_) => {
+                self.columns.len()
+            }
+        }
+    }
+
+    fn rowid_column(&self) -> Option<u16> {
+        match &self.kind {
+            SourceKind::Table { table, .. } => table.rowid_column,
+            SourceKind::Derived(_) | SourceKind::Materialized(_) | SourceKind::Recursive(_) => None,
+        }
+    }
+}
+
+/// Finds the fastest way to look up rows of the table. If the table would be scanned, a term
+/// `expression IN (values)` or `term OR term` is looked up by a seek for each value or each
+/// side of OR, if all of them can be sought.
+fn access(
+    db: &DB,
+    scope: &Scope,
+    i: usize,
+    source: &Source,
+    terms: &[&Expr],
+    used: Option<&[usize]>,
+) -> Access {
+    let access = single_access(db, scope, i, source, terms, used);
+    let SourceKind::Table { table, .. } = &source.kind else {
+        return access;
+    };
+    if !matches!(access, Access::Scan) {
+        return access;
+    }
+    // rows of the seeks are told apart by the rowid or the primary key of a WITHOUT ROWID
+    // table, an index covering the query has to have it
+    let used: Option<Vec<usize>> = used.map(|used| {
+        let mut used = used.to_vec();
+        match table.without_rowid {
+            true => used.extend(table.primary_key.iter().map(|&(column, _)| column)),
+            false => used.push(table.columns.len()),
+        }
+        used
+    });
+
+    for (t, term) in terms.iter().enumerate() {
+        let alternatives: Vec<Vec<Expr>> = match term {
+            Expr::InList {
+                expr,
+                list,
+                negated: false,
+                ..
+            } => list
+                .iter()
+                .map(|value| {
+                    let expr = expr.as_ref().clone();
+                    vec![Expr::binary(BinaryOperator::Eq, expr, value.clone())]
+                })
+                .collect(),
+            Expr::Binary {
+                op: BinaryOperator::Or,
+                ..
+            } => eval::disjuncts(term)
+                .into_iter()
+                .map(|term| eval::conjuncts(term).into_iter().cloned().collect())
+                .collect(),
+            _ => continue,
+        };
+
+        let mut accesses = Vec::new();
+        for alternative in &alternatives {
+            let mut terms: Vec<&Expr> = terms[..t].iter().chain(&terms[t + 1..]).copied().collect();
+            terms.extend(alternative);
+            match single_access(db, scope, i, source, &terms, used.as_deref()) {
+                Access::Scan => break,
+                access => accesses.push(access),
+            }
+        }
+        if accesses.len() == alternatives.len() {
+            return Access::Union(accesses);
+        }
+    }
+    Access::Scan
+}
+
+/// Finds the fastest way to look up rows of the table, using terms in the form
+/// `column = expression of the columns of the tables on the left side`
+/// or `column > expression` (also `<`, `<=` and `>=`). Indexes are matched by their key
+/// expressions, a partial index is used only if the terms imply its WHERE condition.
+fn single_access(
+    db: &DB,
+    scope: &Scope,
+    i: usize,
+    source: &Source,
+    terms: &[&Expr],
+    used: Option<&[usize]>,
+) -> Access {
+    let SourceKind::Table { table, .. } = &source.kind else {
+        return Access::Scan;
+    };
+    // columns of this table referred to in the query
+    let column_of = |column: &ColumnRef| {
+        let column = scope.resolve(column).ok()?;
+        (scope.source(column) == Some(i)).then(|| column - source.first_column)
+    };
+
+    // comparisons of an expression of the table columns with a known value
+    let mut comparisons = Vec::new();
+    for term in terms {
+        let Expr::Binary { op, left, right } = term else {
+            continue;
+        };
+        // operator of the term with swapped sides, `1 < id` is `id > 1`
+        let swapped = match op {
+            BinaryOperator::Eq => BinaryOperator::Eq,
+            BinaryOperator::Lt => BinaryOperator::Gt,
+            BinaryOperator::LtEq => BinaryOperator::GtEq,
+            BinaryOperator::Gt => BinaryOperator::Lt,
+            BinaryOperator::GtEq => BinaryOperator::LtEq,
+            _ => continue,
+        };
+
+        for (expr, key, op) in [(left, right, *op), (right, left, swapped)] {
+            let Some(expr) = table_expr(table, expr, column_of) else {
+                continue;
+            };
+
+            // columns of the enclosing query are known too
+            let key_is_known = eval::subqueries(key).is_empty()
+                && eval::column_refs(key)
+                    .iter()
+                    .all(|c| scope.resolve(c).is_ok_and(|c| scope.source(c) < Some(i)));
+            if key_is_known {
+                comparisons.push((expr, op, key.as_ref().clone()));
+            }
+        }
+    }
+
+    // the first comparison of the expression by one of the operators
+    let compared = |expr: &Expr, ops: &[BinaryOperator]| {
+        comparisons
+            .iter()
+            .find(|(e, op, _)| e == expr && ops.contains(op))
+            .map(|(_, op, key)| (*op, key.clone()))
+    };
+    let equal = |expr: &Expr| compared(expr, &[BinaryOperator::Eq]).map(|(_, key)| key);
+    let bounds = |expr: &Expr| {
+        let lower = match compared(expr, &[BinaryOperator::Gt, BinaryOperator::GtEq]) {
+            Some((BinaryOperator::Gt, key)) => Bound::Excluded(key),
+            Some((_, key)) => Bound::Included(key),
+            None => Bound::Unbounded,
+        };
+        let upper = match compared(expr, &[BinaryOperator::Lt, BinaryOperator::LtEq]) {
+            Some((BinaryOperator::Lt, key)) => Bound::Excluded(key),
+            Some((_, key)) => Bound::Included(key),
+            None => Bound::Unbounded,
+        };
+        (lower, upper)
+    };
+    let column = |column: usize| table_column(table, column);
+    let rowid = Expr::column(None, ROWID);
+
+    // row_id seek is the best we can do
+    if let Some(key) = equal(&rowid) {
+        return Access::RowId(key);
+    }
+
+    if table.without_rowid {
+        // the primary key of a WITHOUT ROWID table can be sought by its leading columns
+        let prefix: Vec<Expr> = table
+            .primary_key
+            .iter()
+            .map_while(|&(c, _)| equal(&column(c)))
+            .collect();
+        if !prefix.is_empty() {
+            return Access::PrimaryKey(prefix);
+        }
+    }
+
+    // indexes with leading keys compared for equality or a range of the first key
+    let mut candidates = Vec::new();
+    for index in db.table_indexes(&table.name) {
+        let (root_page, index) = (index.root_page, index.definition);
+        let by_name = |c: &ColumnRef| table_column_index(table, &c.column);
+
+        // only keys in the binary order can be sought
+        let columns: Vec<(Expr, bool)> = index
+            .columns
+            .iter()
+            .map_while(|key| {
+                let expr = is_binary(key).then(|| table_expr(table, &key.expr, by_name))??;
+                Some((expr, key.descending && descending_keys(db)))
+            })
+            .collect();
+        let keys: Vec<Expr> = columns.iter().map_while(|(e, _)| equal(e)).collect();
+        let (lower, upper) = match columns.get(keys.len()) {
+            // greater values are first in a DESC key
+            Some((next, true)) => {
+                let (lower, upper) = bounds(next);
+                (upper, lower)
+            }
+            Some((next, false)) => bounds(next),
+            None => (Bound::Unbounded, Bound::Unbounded),
+        };
+        let rank = (
+            keys.len(),
+            lower != Bound::Unbounded || upper != Bound::Unbounded,
+        );
+        if rank == (0, false) {
+            continue;
+        }
+
+        // every condition of a partial index has to be a term of the query
+        let implied = index
+            .where_cond
+            .iter()
+            .flat_map(eval::conjuncts)
+            .all(|cond| {
+                table_expr(table, cond, by_name).is_some_and(|cond| {
+                    terms
+                        .iter()
+                        .any(|term| table_expr(table, term, column_of).as_ref() == Some(&cond))
+                        || is_not_null_of_compared(&cond, &comparisons)
+                })
+            });
+        if !implied {
+            continue;
+        }
+
+        let covered = used.and_then(|used| covered_columns(table, &index, used));
+        // indexes of a WITHOUT ROWID table refer to rows by the primary key instead of
+        // the rowid, only the indexes covering the query are read
+        if table.without_rowid && covered.is_none() {
+            continue;
+        }
+        let access = Access::Index {
+            name: index.name.clone(),
+            root_page,
+            columns,
+            keys,
+            lower,
+            upper,
+            covered,
+        };
+        candidates.push((rank, index.name, access));
+    }
+
+    let rowid_range = bounds(&rowid);
+    if let Some(rows) = db.table_rows(&table.name) {
+        let candidates = candidates
+            .into_iter()
+            .map(|(_, name, access)| (name, access));
+        return cheapest(db, rows, candidates, rowid_range);
+    }
+
+    // without statistics, the index with the most leading keys compared for equality,
+    // then one with a range of the next key, the first one of equal indexes
+    let best = candidates.into_iter().rev().max_by_key(|(rank, ..)| *rank);
+    // a range of the rowid is read from the table b-tree directly, without index lookups
+    match (best, rowid_range) {
+        (Some(((keys, _), _, access)), _) if keys > 0 => access,
+        (best, (Bound::Unbounded, Bound::Unbounded)) => best.map_or(Access::Scan, |(.., a)| a),
+        (_, (lower, upper)) => Access::RowIdRange { lower, upper },
+    }
+}
+
+/// Returns the access reading the least rows of a table with `rows` rows estimated by ANALYZE:
+/// a full scan, the rowid range or one of the index seeks
+fn cheapest(
+    db: &DB,
+    rows: u64,
+    indexes: impl Iterator<Item = (String, Access)>,
+    (lower, upper): (Bound<Expr>, Bound<Expr>),
+) -> Access {
+    let rows = rows as f64;
+    // each bound of a range is assumed to leave a quarter of the rows, like in SQLite
+    let range = |lower: &Bound<Expr>, upper: &Bound<Expr>| {
+        let bounded = [lower, upper]
+            .into_iter()
+            .filter(|b| **b != Bound::Unbounded);
+        0.25f64.powi(bounded.count() as i32)
+    };
+    // a row found in an index is looked up in the table b-tree
+    let lookup = rows.log2().max(1.0);
+
+    let mut cheapest = (rows, Access::Scan);
+    if lower != Bound::Unbounded || upper != Bound::Unbounded {
+        cheapest = (
+            rows * range(&lower, &upper),
+            Access::RowIdRange { lower, upper },
+        );
+    }
+    for (name, access) in indexes {
+        let Access::Index {
+            keys,
+            lower,
+            upper,
+            covered,
+            ..
+        } = &access
+        else {
+            continue;
+        };
+        let entries = match db.index_stat(&name) {
+            Some(stat) => stat.get(keys.len()).or(stat.last()).copied().unwrap_or(1) as f64,
+            // an index created after ANALYZE, each key is assumed to leave a tenth of the rows
+            None => rows * 0.1f64.powi(keys.len() as i32),
+        };
+        let mut cost = entries * range(lower, upper);
+        if covered.is_none() {
+            cost *= lookup;
+        }
+        if cost < cheapest.0 {
+            cheapest = (cost, access);
+        }
+    }
+    cheapest.1
+}
+
+/// Returns true if the key of the index is in the binary order
+fn is_binary(key: &IndexKey) -> bool {
+    key.collation
+        .as_ref()
+        .map_or(true, |c| c.eq_ignore_ascii_case("BINARY"))
+}
+
+/// Returns the used columns of the table with the fields of the index records holding them,
+/// None if some column is not in the records. The keys of the index are followed by the rowid,
+/// or by the primary key columns which are not keys of the index for a WITHOUT ROWID table.
+fn covered_columns(table: &Table, index: &IndexDef, used: &[usize]) -> Option<Vec<(usize, usize)>> {
+    // the value of the INTEGER PRIMARY KEY is the rowid after the columns
+    let stored = |column: usize| match table.rowid_column == Some(column as u16) {
+        true => table.columns.len(),
+        false => column,
+    };
+
+    // columns in the fields of the records, None for keys which are not columns
+    let mut fields: Vec<Option<usize>> = index
+        .columns
+        .iter()
+        .map(|key| match &key.expr {
+            Expr::Column(c) => table_column_index(table, &c.column).map(stored),
+            _ => None,
+        })
+        .collect();
+    match table.without_rowid {
+        true => {
+            for &(column, _) in &table.primary_key {
+                if !fields.contains(&Some(column)) {
+                    fields.push(Some(column));
+                }
+            }
+        }
+        false => fields.push(Some(table.columns.len())),
+    }
+
+    used.iter()
+        .map(|&column| {
+            let field = fields.iter().position(|&f| f == Some(stored(column)))?;
+            Some((column, field))
+        })
+        .collect()
+}
+
+/// Returns Some if rows of the table are read in the order of the ORDER BY terms,
+/// true if read backward in the reverse order of the access, None if not in the order
+fn read_backward(
+    db: &DB,
+    scope: &Scope,
+    source: &Source,
+    order_by: &[(Expr, bool)],
+) -> Option<bool> {
+    let SourceKind::Table { table, .. } = &source.kind else {
+        return order_by.is_empty().then_some(false);
+    };
+
+    // expressions the rows are sorted by and expressions equal in all rows
+    let (order, equal) = match (&source.access, table.without_rowid) {
+        // at most one row
+        (Access::RowId(_), _) => return Some(false),
+        // rows of the seeks one after another
+        (Access::Union(_), _) => return order_by.is_empty().then_some(false),
+        (Access::Index { columns, keys, .. }, _) => {
+            let (equal, order) = columns.split_at(keys.len());
+            (order.to_vec(), equal.to_vec())
+        }
+        (_, false) => (vec![(Expr::column(None, ROWID), false)], Vec::new()),
+        (access, true) => {
+            let primary_key: Vec<(Expr, bool)> = table
+                .primary_key
+                .iter()
+                .map(|&(c, descending)| (table_column(table, c), descending && descending_keys(db)))
+                .collect();
+            let sought = match access {
+                Access::PrimaryKey(keys) => keys.len(),
+                _ => 0,
+            };
+            let (equal, order) = primary_key.split_at(sought);
+            (order.to_vec(), equal.to_vec())
+        }
+    };
+
+    let column_of = |column: &ColumnRef| {
+        let column = scope.resolve(column).ok()?;
+        (scope.source(column) == Some(0)).then(|| column - source.first_column)
+    };
+    let mut order = order.into_iter();
+    let mut next = order.next();
+    let mut backward = None;
+    for (term, descending) in order_by {
+        let term = table_expr(table, term, column_of)?;
+        if equal.iter().any(|(e, _)| *e == term) {
+            continue;
+        }
+        match &next {
+            // all terms in the order of the access or all in the reverse order
+            Some((e, d))
+                if *e == term && *backward.get_or_insert(d != descending) == (d != descending) =>
+            {
+                next = order.next()
+            }
+            _ => return None,
+        }
+    }
+    Some(backward.unwrap_or_default())
+}
+
+/// DESC keys of indexes are stored in the descending order since the schema format 4,
+/// in older formats DESC is ignored
+fn descending_keys(db: &DB) -> bool {
+    db.schema_format() >= 4
+}
+
+/// Name of the rowid in the expressions of the table columns
+const ROWID: &str = "rowid";
+
+/// The expression with references to the table columns replaced by their names, the rowid and
+/// its alias by `rowid`, so that expressions from the query and from the schema can be compared.
+/// None if it refers to a column which is not of the table or it contains subqueries.
+fn table_expr(
+    table: &Table,
+    expr: &Expr,
+    column_of: impl Fn(&ColumnRef) -> Option<usize>,
+) -> Option<Expr> {
+    if !eval::subqueries(expr).is_empty() {
+        return None;
+    }
+
+    let mut expr = expr.clone();
+    let mut known = true;
+    visit_expr(&mut expr, &mut |e| {
+        if let Expr::Column(column) = e {
+            match column_of(column) {
+                Some(column) => *e = table_column(table, column),
+                None => known = false,
+            }
+        }
+    });
+    known.then_some(expr)
+}
+
+/// Column of the table by its index, the rowid is the column after the declared columns
+fn table_column(table: &Table, column: usize) -> Expr {
+    match column == table.columns.len() || table.rowid_column == Some(column as u16) {
+        true => Expr::column(None, ROWID),
+        false => Expr::column(None, &table.columns[column].to_lowercase()),
+    }
+}
+
+/// Index of the column of the table by its name, a name of the rowid is the column after
+/// the declared columns
+fn table_column_index(table: &Table, name: &str) -> Option<usize> {
+    match table
+        .columns
+        .iter()
+        .position(|c| c.eq_ignore_ascii_case(name))
+    {
+        Some(column) => Some(column),
+        None if !table.without_rowid && eval::is_rowid_name(name) => Some(table.columns.len()),
+        None => None,
+    }
+}
+
+/// Returns true for `expr IS NOT NULL` where the expression is compared, as a comparison
+/// with NULL is never true
+fn is_not_null_of_compared(cond: &Expr, comparisons: &[(Expr, BinaryOperator, Expr)]) -> bool {
+    match cond {
+        Expr::IsNull {
+            expr,
+            negated: true,
+        } => comparisons.iter().any(|(e, _, _)| e == expr.as_ref()),
+        _ => false,
+    }
+}
+
+/// Evaluates the row_id the table is searched for, text is converted to a number like
+/// for a column with INTEGER affinity
+fn rowid_value(
+    db: &mut DB,
+    key: &Expr,
+    row: &[ColumnContent],
+    scope: &Scope,
+) -> Result<ColumnContent> {
+    key_value(db, key, Affinity::Integer, row, scope)
+}
+
+/// Evaluates the value a key of a b-tree is searched for, converted by the affinity
+/// of the key column like in the comparison of the column with the value
+fn key_value(
+    db: &mut DB,
+    key: &Expr,
+    affinity: Affinity,
+    row: &[ColumnContent],
+    scope: &Scope,
+) -> Result<ColumnContent> {
+    let value = eval::eval(db, key, row, scope)?;
+    Ok(eval::apply_affinity(value, affinity))
+}
+
+/// Affinity of the key of an index, expressions other than columns have none
+fn key_affinity(table: &Table, key: &Expr) -> Affinity {
+    let Expr::Column(column) = key else {
+        return Affinity::Blob;
+    };
+    match table_column_index(table, &column.column) {
+        Some(i) if i == table.columns.len() || table.rowid_column == Some(i as u16) => {
+            Affinity::Integer
+        }
+        Some(i) => table.affinities[i],
+        None => Affinity::Blob,
+    }
+}
+
+/// Looks up rows of the table by the access for the current row of the tables on its left side
+fn lookup(
+    db: &mut DB,
+    source: &Rc<Source>,
+    access: &Access,
+    row: &[ColumnContent],
+    scope: &Scope,
+) -> Result<Box<dyn Operator>> {
+    match access {
+        Access::Scan => load(db, source, None),
+        Access::RowId(key) => {
+            let key = rowid_value(db, key, row, scope)?;
+            let rowid = source.rowid_column();
+            seek(db, source, CellFilter::new(None, None, key, rowid))
+        }
+        Access::RowIdRange { lower, upper } => {
+            let bound = |db: &mut DB, bound: &Bound<Expr>| -> Result<_> {
+                Ok(match bound {
+                    Bound::Included(key) => Bound::Included(rowid_value(db, key, row, scope)?),
+                    Bound::Excluded(key) => Bound::Excluded(rowid_value(db, key, row, scope)?),
+                    Bound::Unbounded => Bound::Unbounded,
+                })
+            };
+            let filter = CellFilter::range(
+                None,
+                None,
+                bound(db, lower)?,
+                bound(db, upper)?,
+                source.rowid_column(),
+            );
+            seek(db, source, filter)
+        }
+        Access::PrimaryKey(keys) => {
+            let SourceKind::Table { table, .. } = &source.kind else {
+                bail!("{} is not a table", source.name);
+            };
+            let prefix = keys
+                .iter()
+                .zip(&table.primary_key)
+                .map(|(key, &(column, _))| key_value(db, key, table.affinities[column], row, scope))
+                .collect::<Result<Vec<_>>>()?;
+            match prefix.contains(&ColumnContent::Null) {
+                // NULL is not equal to anything
+                true => Ok(Box::new(Values::new(Vec::new()))),
+                false => load_records(db, source, &prefix),
+            }
+        }
+        Access::Index {
+            root_page,
+            columns,
+            keys,
+            lower,
+            upper,
+            covered,
+            ..
+        } => {
+            let SourceKind::Table { table, .. } = &source.kind else {
+                bail!("{} is not a table", source.name);
+            };
+            let affinities: Vec<Affinity> = columns
+                .iter()
+                .map(|(key, _)| key_affinity(table, key))
+                .collect();
+            let prefix = keys
+                .iter()
+                .zip(&affinities)
+                .map(|(key, &affinity)| key_value(db, key, affinity, row, scope))
+                .collect::<Result<Vec<_>>>()?;
+            // the bound of the next column extends the prefix
+            let affinity = affinities.get(keys.len()).copied().unwrap_or_default();
+            let bound = |db: &mut DB, bound: &Bound<Expr>| -> Result<_> {
+                let mut key = prefix.clone();
+                Ok(match bound {
+                    Bound::Included(value) => {
+                        key.push(key_value(db, value, affinity, row, scope)?);
+                        Bound::Included(key)
+                    }
+                    Bound::Excluded(value) => {
+                        key.push(key_value(db, value, affinity, row, scope)?);
+                        Bound::Excluded(key)
+                    }
+                    Bound::Unbounded => Bound::Included(key),
+                })
+            };
+            let descending = columns.iter().map(|(_, descending)| *descending).collect();
+            let filter =
+                CellFilter::index(*root_page, bound(db, lower)?, bound(db, upper)?, descending);
+            match covered {
+                Some(covered) => seek_covering(source, filter, covered),
+                None => seek(db, source, filter),
+            }
+        }
+        Access::Union(accesses) => {
+            let mut seeks = VecDeque::new();
+            for access in accesses {
+                seeks.push_back(lookup(db, source, access, row, scope)?);
+            }
+            // rows found by several seeks are told apart by the rowid
+            // or by the primary key values of a WITHOUT ROWID table
+            let key = match &source.kind {
+                SourceKind::Table { table, .. } if table.without_rowid => table
+                    .primary_key
+                    .iter()
+                    .map(|&(column, _)| column)
+                    .collect(),
+                _ => vec![source.columns.len()],
+            };
+            Ok(Box::new(Union {
+                seeks,
+                found: BTreeSet::new(),
+                key,
+            }))
+        }
+    }
+}
+
+/// Rows found by several seeks, the rows found by more of them are returned once
+struct Union {
+    seeks: VecDeque<Box<dyn Operator>>,
+    /// keys of the rows returned so far
+    found: BTreeSet<Row>,
+    /// positions of the values in the row the rows are told apart by
+    key: Vec<usize>,
+}
+
+impl Operator for Union {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        while let Some(seek) = self.seeks.front_mut() {
+            match seek.next(db)? {
+                Some(row) => {
+                    let key = self.key.iter().map(|&i| row[i].clone()).collect();
+                    if self.found.insert(key) {
+                        return Ok(Some(row));
+                    }
+                }
+                None => {
+                    self.seeks.pop_front();
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Looks up rows of the table matching the filter
+fn seek(db: &mut DB, source: &Rc<Source>, filter: CellFilter) -> Result<Box<dyn Operator>> {
+    if filter.has_null_bound() {
+        // NULL is not equal, lower or greater than anything
+        return Ok(Box::new(Values::new(Vec::new())));
+    }
+    load(db, source, Some(filter))
+}
+
+/// Reads rows of the table from the records of the index covering the query,
+/// the columns not in the index are NULL
+fn seek_covering(
+    source: &Rc<Source>,
+    filter: CellFilter,
+    covered: &[(usize, usize)],
+) -> Result<Box<dyn Operator>> {
+    if filter.has_null_bound() {
+        return Ok(Box::new(Values::new(Vec::new())));
+    }
+    Ok(Box::new(CoveringRows {
+        source: source.clone(),
+        cursor: Cursor::entries(filter)?.backward(source.backward),
+        width: source.width(),
+        covered: covered.to_vec(),
+    }))
+}
+
+/// Rows of a table made of the records of an index
+struct CoveringRows {
+    source: Rc<Source>,
+    cursor: Cursor,
+    width: usize,
+    /// columns of the row with the fields of the index record they are read from
+    covered: Vec<(usize, usize)>,
+}
+
+impl Operator for CoveringRows {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        let SourceKind::Table { table, .. } = &self.source.kind else {
+            bail!("{} is not a table", self.source.name);
+        };
+        let Some(entry) = self.cursor.next_record(&mut db.pager)? else {
+            return Ok(None);
+        };
+        let mut row = vec![ColumnContent::Null; self.width];
+        for &(column, field) in &self.covered {
+            // the rowid after the table columns has no affinity
+            let affinity = table.affinities.get(column).copied().unwrap_or_default();
+            row[column] = stored_value(entry[field].clone(), affinity);
+        }
+        Ok(Some(row))
+    }
+}
+
+/// Reads rows of the table
+fn load(db: &mut DB, source: &Rc<Source>, filter: Option<CellFilter>) -> Result<Box<dyn Operator>> {
+    let (table, root_page) = match &source.kind {
+        SourceKind::Table { table, root_page } => (table, *root_page),
+        SourceKind::Derived(select) => return select.operator(db, None),
+        SourceKind::Materialized(rows) => return Ok(Box::new(Values::shared(rows.clone()))),
+        SourceKind::Recursive(recursion) => {
+            return Ok(Box::new(RecursiveRows::new(Rc::clone(recursion))))
+        }
+    };
+    if table.without_rowid {
+        return load_records(db, source, &[]);
+    }
+
+    // records have the columns in the declared order except the VIRTUAL columns
+    let order = stored_columns(table).collect();
+    Ok(Box::new(TableRows {
+        source: source.clone(),
+        cursor: Cursor::table(root_page, filter).backward(source.backward),
+        order,
+        defaults: None,
+    }))
+}
+
+/// Rows of a table read from the table b-tree
+struct TableRows {
+    source: Rc<Source>,
+    cursor: Cursor,
+    /// columns of the row in the order of the fields of the records
+    order: Vec<usize>,
+    /// values of the columns added by ALTER TABLE after some rows were written,
+    /// starting at the first column missing in a row read so far
+    defaults: Option<(usize, Row)>,
+}
+
+impl Operator for TableRows {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        let SourceKind::Table { table, .. } = &self.source.kind else {
+            bail!("{} is not a table", self.source.name);
+        };
+        let Some(cell) = self.cursor.next_row(&mut db.pager)? else {
+            return Ok(None);
+        };
+
+        // columns added by ALTER TABLE after the row was written have the default value
+        let stored = cell.column_count();
+        if let Some(&first) = self
+            .order
+            .get(stored..)
+            .and_then(|missing| missing.iter().min())
+        {
+            if !matches!(&self.defaults, Some((cached, _)) if *cached <= first) {
+                self.defaults = Some((first, default_values(db, table, first)?));
+            }
+        }
+
+        let rowid_field = table
+            .rowid_column
+            .and_then(|rowid| self.order.iter().position(|&c| c == rowid as usize));
+        let mut row = vec![ColumnContent::Null; table.columns.len()];
+        for (field, &column) in self.order.iter().enumerate() {
+            row[column] = match (field < stored, &self.defaults) {
+                (true, _) => stored_value(
+                    cell.column(field as u16, rowid_field.map(|f| f as u16))?,
+                    table.affinities[column],
+                ),
+                (false, Some((_, defaults))) => defaults[column].clone(),
+                (false, None) => ColumnContent::Null,
+            };
+        }
+        if let Some(virtual_columns) = &self.source.virtual_columns {
+            virtual_columns.compute(db, &mut row)?;
+        }
+        row.push(ColumnContent::Int(cell.row_id() as i64));
+        Ok(Some(row))
+    }
+}
+
+/// Reads rows of a WITHOUT ROWID table with the primary key starting with the `prefix` values
+fn load_records(
+    db: &mut DB,
+    source: &Rc<Source>,
+    prefix: &[ColumnContent],
+) -> Result<Box<dyn Operator>> {
+    let SourceKind::Table { table, root_page } = &source.kind else {
+        bail!("{} is not a table", source.columns.join(", "));
+    };
+    let descending: Vec<bool> = table
+        .primary_key
+        .iter()
+        .map(|&(_, descending)| descending && descending_keys(db))
+        .collect();
+
+    // records have the primary key columns first, then the other columns in the declared order
+    // except the VIRTUAL columns
+    let primary_key: Vec<usize> = table.primary_key.iter().map(|&(c, _)| c).collect();
+    let order: Vec<usize> = primary_key
+        .iter()
+        .copied()
+        .chain(stored_columns(table).filter(|i| !primary_key.contains(i)))
+        .collect();
+
+    Ok(Box::new(RecordRows {
+        source: source.clone(),
+        cursor: Cursor::records(*root_page, prefix, &descending).backward(source.backward),
+        order,
+        defaults: None,
+    }))
+}
+
+/// Rows of a WITHOUT ROWID table read from its primary key b-tree
+struct RecordRows {
+    source: Rc<Source>,
+    cursor: Cursor,
+    /// columns of the row in the order of the fields of the records
+    order: Vec<usize>,
+    /// values of the columns added by ALTER TABLE after some rows were written,
+    /// starting at the first column missing in a record read so far
+    defaults: Option<(usize, Row)>,
+}
+
+impl Operator for RecordRows {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        let SourceKind::Table { table, .. } = &self.source.kind else {
+            bail!("{} is not a table", self.source.name);
+        };
+        let Some(record) = self.cursor.next_record(&mut db.pager)? else {
+            return Ok(None);
+        };
+
+        // columns added by ALTER TABLE after the row was written have the default value
+        if let Some(&first) = self
+            .order
+            .get(record.len()..)
+            .and_then(|missing| missing.iter().min())
+        {
+            if !matches!(&self.defaults, Some((cached, _)) if *cached <= first) {
+                self.defaults = Some((first, default_values(db, table, first)?));
+            }
+        }
+
+        let mut row = vec![ColumnContent::Null; table.columns.len()];
+        for (position, &column) in self.order.iter().enumerate() {
+            row[column] = match (record.get(position), &self.defaults) {
+                (Some(value), _) => stored_value(value.clone(), table.affinities[column]),
+                (None, Some((_, defaults))) => defaults[column].clone(),
+                (None, None) => ColumnContent::Null,
+            };
+        }
+        if let Some(virtual_columns) = &self.source.virtual_columns {
+            virtual_columns.compute(db, &mut row)?;
+        }
+        Ok(Some(row))
+    }
+}
+
+/// Returns the columns of the table stored in its records, all except the VIRTUAL columns
+fn stored_columns(table: &Table) -> impl Iterator<Item = usize> + '_ {
+    (0..table.columns.len())
+        .filter(|&i| !matches!(&table.generated[i], Some(generated) if !generated.stored))
+}
+
+/// VIRTUAL generated columns of a table, computed from the other columns of the rows
+struct VirtualColumns {
+    /// columns with their expressions, ordered so that the columns an expression
+    /// refers to are computed before it
+    columns: Vec<(usize, Expr)>,
+    affinities: Vec<Affinity>,
+    /// the columns of the table
+    scope: Scope,
+}
+
+impl VirtualColumns {
+    /// Returns None if the table has no VIRTUAL columns
+    fn new(table: &Table) -> Result<Option<Self>> {
+        let mut pending = Vec::new();
+        for (column, generated) in table.generated.iter().enumerate() {
+            let Some(generated) = generated.as_ref().filter(|g| !g.stored) else {
+                continue;
+            };
+            let mut expr = parse_sql(&generated.expr, parse_expr)
+                .with_context(|| format!("generated column {}", table.columns[column]))?;
+            let mut used = Vec::new();
+            visit_expr(&mut expr, &mut |expr| {
+                if let Expr::Column(c) = expr {
+                    used.extend(table_column_index(table, &c.column));
+                }
+            });
+            pending.push((column, expr, used));
+        }
+        if pending.is_empty() {
+            return Ok(None);
+        }
+
+        let mut columns = Vec::new();
+        while !pending.is_empty() {
+            let Some(next) = pending.iter().position(|(_, _, used)| {
+                used.iter()
+                    .all(|c| pending.iter().all(|(column, ..)| column != c))
+            }) else {
+                bail!("generated column loop in table {}", table.name);
+            };
+            let (column, expr, _) = pending.remove(next);
+            columns.push((column, expr));
+        }
+
+        let mut scope = Scope::default();
+        for (column, &affinity) in table.columns.iter().zip(&table.affinities) {
+            scope.push(0, &table.name, column, Some(affinity));
+        }
+        Ok(Some(Self {
+            columns,
+            affinities: table.affinities.clone(),
+            scope,
+        }))
+    }
+
+    /// Sets the values of the VIRTUAL columns of the row, converted by the affinity of the columns
+    fn compute(&self, db: &mut DB, row: &mut Row) -> Result<()> {
+        for (column, expr) in &self.columns {
+            let value = eval::eval(db, expr, row, &self.scope)?;
+            row[*column] = eval::apply_affinity(value, self.affinities[*column]);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the value read from a record, like SQLite floats without a fraction are stored
+/// as integers in REAL columns
+fn stored_value(value: ColumnContent, affinity: Affinity) -> ColumnContent {
+    match (affinity, value) {
+        (Affinity::Real, ColumnContent::Int(i)) => ColumnContent::Float(i as f64),
+        (_, value) => value,
+    }
+}
+
+/// Returns values of the DEFAULT clauses of the table columns starting at `first`, NULL if there is none.
+/// Columns before `first` are NULL.
+fn default_values(db: &mut DB, table: &Table, first: usize) -> Result<Row> {
+    let mut values = vec![ColumnContent::Null; first];
+    for default in &table.defaults[first..] {
+        values.push(match default {
+            Some(sql) => {
+                let expr = parse_sql(sql, parse_default_value)?;
+                eval::eval(db, &expr, &[], &Scope::default())
+                    .with_context(|| format!("default value {sql}"))?
+            }
+            None => ColumnContent::Null,
+        });
+    }
+    Ok(values)
+}
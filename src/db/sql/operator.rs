@@ -0,0 +1,397 @@
+use std::collections::{BinaryHeap, VecDeque};
+use std::rc::Rc;
+
+use anyhow::Result;
+
+use super::eval::{self, Row, Scope};
+use super::order::{self, KeyedRow};
+use super::parser::Expr;
+use crate::db::{page::ColumnContent, DB};
+
+/// Step of the execution of a query, it returns its rows one at a time pulling the rows
+/// it needs from its inputs. Rows are kept only by the operators which need all of them
+/// before returning the first one, like sorting or aggregating.
+pub(super) trait Operator {
+    /// Returns the next row, None after the last one
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>>;
+}
+
+/// Returns all rows of the operator
+pub(super) fn collect(db: &mut DB, mut operator: Box<dyn Operator>) -> Result<Vec<Row>> {
+    let mut rows = Vec::new();
+    while let Some(row) = operator.next(db)? {
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Rows computed in advance, they can be shared by several operators
+pub(super) struct Values {
+    rows: Rc<Vec<Row>>,
+    position: usize,
+}
+
+impl Values {
+    pub(super) fn new(rows: Vec<Row>) -> Self {
+        Self::shared(Rc::new(rows))
+    }
+
+    pub(super) fn shared(rows: Rc<Vec<Row>>) -> Self {
+        Self { rows, position: 0 }
+    }
+}
+
+impl Operator for Values {
+    fn next(&mut self, _db: &mut DB) -> Result<Option<Row>> {
+        let row = self.rows.get(self.position).cloned();
+        self.position += 1;
+        Ok(row)
+    }
+}
+
+/// Rows of the input for which all terms are true
+pub(super) struct Filter {
+    input: Box<dyn Operator>,
+    terms: Vec<Expr>,
+    scope: Rc<Scope>,
+}
+
+impl Filter {
+    pub(super) fn new(input: Box<dyn Operator>, terms: Vec<Expr>, scope: Rc<Scope>) -> Self {
+        Self {
+            input,
+            terms,
+            scope,
+        }
+    }
+}
+
+impl Operator for Filter {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        while let Some(row) = self.input.next(db)? {
+            if eval::satisfies(db, &self.terms, &row, &self.scope)? {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Value computed by `Project` from a row
+pub(super) enum Output {
+    Column(usize),
+    Expr(Expr),
+}
+
+/// Rows of the values computed from the rows of the input
+pub(super) struct Project {
+    input: Box<dyn Operator>,
+    outputs: Vec<Output>,
+    scope: Rc<Scope>,
+}
+
+impl Project {
+    pub(super) fn new(input: Box<dyn Operator>, outputs: Vec<Output>, scope: Rc<Scope>) -> Self {
+        Self {
+            input,
+            outputs,
+            scope,
+        }
+    }
+}
+
+impl Operator for Project {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        let Some(row) = self.input.next(db)? else {
+            return Ok(None);
+        };
+        let mut values = Vec::with_capacity(self.outputs.len());
+        for output in &self.outputs {
+            values.push(match output {
+                Output::Column(i) => row[*i].clone(),
+                Output::Expr(expr) => eval::eval(db, expr, &row, &self.scope)?,
+            });
+        }
+        Ok(Some(values))
+    }
+}
+
+/// Rows of the input sorted by the sort keys, the values at the end of the rows
+/// (one for each of `descending`), which are removed from the returned rows.
+/// With a `limit` only that many first rows are kept while the input is read.
+pub(super) struct Sort {
+    /// input not read yet
+    input: Option<Box<dyn Operator>>,
+    descending: Vec<bool>,
+    limit: Option<usize>,
+    rows: std::vec::IntoIter<Row>,
+}
+
+impl Sort {
+    pub(super) fn new(
+        input: Box<dyn Operator>,
+        descending: Vec<bool>,
+        limit: Option<usize>,
+    ) -> Self {
+        Self {
+            input: Some(input),
+            descending,
+            limit,
+            rows: Vec::new().into_iter(),
+        }
+    }
+
+    /// Returns the first `limit` rows of the input in order, the heap has the last
+    /// of the rows kept so far on the top
+    fn first_rows(&self, db: &mut DB, input: &mut dyn Operator, limit: usize) -> Result<Vec<Row>> {
+        let descending: Rc<[bool]> = self.descending.clone().into();
+        let mut heap = BinaryHeap::new();
+        let mut sequence = 0;
+        while let Some(mut row) = input.next(db)? {
+            let key = row.split_off(row.len() - self.descending.len());
+            heap.push(KeyedRow {
+                key,
+                sequence,
+                descending: descending.clone(),
+                row,
+            });
+            sequence += 1;
+            if heap.len() > limit {
+                heap.pop();
+            }
+        }
+        Ok(heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|keyed| keyed.row)
+            .collect())
+    }
+}
+
+impl Operator for Sort {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        if let Some(mut input) = self.input.take() {
+            // all rows are read before the first one is returned
+            let rows = match self.limit {
+                Some(limit) => self.first_rows(db, input.as_mut(), limit)?,
+                None => {
+                    let mut rows = Vec::new();
+                    while let Some(mut row) = input.next(db)? {
+                        let keys = row.split_off(row.len() - self.descending.len());
+                        rows.push((keys, row));
+                    }
+                    order::sort(rows, &self.descending)
+                }
+            };
+            self.rows = rows.into_iter();
+        }
+        Ok(self.rows.next())
+    }
+}
+
+/// At most `count` rows of the input (all if None) after skipping `offset` rows,
+/// the input is not read behind them
+pub(super) struct Limit {
+    input: Box<dyn Operator>,
+    count: Option<usize>,
+    offset: usize,
+}
+
+impl Limit {
+    pub(super) fn new(input: Box<dyn Operator>, count: Option<usize>, offset: usize) -> Self {
+        Self {
+            input,
+            count,
+            offset,
+        }
+    }
+}
+
+impl Operator for Limit {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        while self.offset > 0 {
+            self.offset -= 1;
+            if self.input.next(db)?.is_none() {
+                return Ok(None);
+            }
+        }
+        match &mut self.count {
+            Some(0) => Ok(None),
+            Some(count) => {
+                *count -= 1;
+                self.input.next(db)
+            }
+            None => self.input.next(db),
+        }
+    }
+}
+
+/// Function computing a single value from all rows
+pub(super) enum Aggregation {
+    /// COUNT(*)
+    CountRows,
+    /// COUNT(column), NULL values are not counted
+    Count(usize),
+    /// MIN(column) or MAX(column) if true, NULL values are skipped
+    Extremum { column: usize, max: bool },
+}
+
+/// Single row with the value of the aggregation of all rows of the input
+pub(super) struct Aggregate {
+    /// input not read yet
+    input: Option<Box<dyn Operator>>,
+    aggregation: Aggregation,
+}
+
+impl Aggregate {
+    pub(super) fn new(input: Box<dyn Operator>, aggregation: Aggregation) -> Self {
+        Self {
+            input: Some(input),
+            aggregation,
+        }
+    }
+}
+
+impl Operator for Aggregate {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        let Some(mut input) = self.input.take() else {
+            return Ok(None);
+        };
+
+        let mut count = 0;
+        let mut extremum: Option<ColumnContent> = None;
+        while let Some(row) = input.next(db)? {
+            match self.aggregation {
+                Aggregation::CountRows => count += 1,
+                Aggregation::Count(i) => {
+                    if row[i] != ColumnContent::Null {
+                        count += 1
+                    }
+                }
+                Aggregation::Extremum { column, max } => {
+                    let value = &row[column];
+                    let replaced = match &extremum {
+                        _ if *value == ColumnContent::Null => false,
+                        Some(extremum) if max => value > extremum,
+                        Some(extremum) => value < extremum,
+                        None => true,
+                    };
+                    if replaced {
+                        extremum = Some(value.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(Some(vec![match self.aggregation {
+            Aggregation::CountRows | Aggregation::Count(_) => ColumnContent::Int(count),
+            // NULL if there are no other values
+            Aggregation::Extremum { .. } => extremum.unwrap_or(ColumnContent::Null),
+        }]))
+    }
+}
+
+/// Rows of the inputs one after another
+pub(super) struct Chain {
+    inputs: VecDeque<Box<dyn Operator>>,
+}
+
+impl Chain {
+    pub(super) fn new(inputs: Vec<Box<dyn Operator>>) -> Self {
+        Self {
+            inputs: inputs.into(),
+        }
+    }
+}
+
+impl Operator for Chain {
+    fn next(&mut self, db: &mut DB) -> Result<Option<Row>> {
+        while let Some(input) = self.inputs.front_mut() {
+            match input.next(db)? {
+                Some(row) => return Ok(Some(row)),
+                None => {
+                    self.inputs.pop_front();
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(rows: &[&[i64]]) -> Box<dyn Operator> {
+        Box::new(Values::new(
+            rows.iter()
+                .map(|row| row.iter().map(|&i| ColumnContent::Int(i)).collect())
+                .collect(),
+        ))
+    }
+
+    fn ints(rows: Vec<Row>) -> Vec<Vec<i64>> {
+        rows.into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|value| match value {
+                        ColumnContent::Int(i) => i,
+                        value => panic!("not an integer: {value:?}"),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_operators() {
+        let mut db = DB::new("sample.db").unwrap();
+        let rows = &[&[1, 30][..], &[2, 10], &[3, 20], &[4, 10]];
+
+        // the sort key is the second value, removed from the sorted rows
+        let sort = Sort::new(values(rows), vec![false], None);
+        let limit = Limit::new(Box::new(sort), Some(2), 1);
+        assert_eq!(
+            ints(collect(&mut db, Box::new(limit)).unwrap()),
+            vec![vec![4], vec![3]]
+        );
+
+        // only the rows up to the limit are kept, equal keys in the input order
+        let sort = Sort::new(values(rows), vec![false], Some(3));
+        assert_eq!(
+            ints(collect(&mut db, Box::new(sort)).unwrap()),
+            vec![vec![2], vec![4], vec![3]]
+        );
+        let sort = Sort::new(values(rows), vec![true], Some(0));
+        assert!(collect(&mut db, Box::new(sort)).unwrap().is_empty());
+
+        let project = Project::new(
+            values(rows),
+            vec![Output::Column(1), Output::Column(0)],
+            Rc::default(),
+        );
+        let chain = Chain::new(vec![Box::new(project), values(&[&[5, 6]])]);
+        let max = Aggregate::new(
+            Box::new(chain),
+            Aggregation::Extremum {
+                column: 0,
+                max: true,
+            },
+        );
+        assert_eq!(
+            ints(collect(&mut db, Box::new(max)).unwrap()),
+            vec![vec![30]]
+        );
+
+        let count = Aggregate::new(values(&[]), Aggregation::CountRows);
+        assert_eq!(
+            ints(collect(&mut db, Box::new(count)).unwrap()),
+            vec![vec![0]]
+        );
+        let limit = Limit::new(values(rows), Some(0), 0);
+        assert_eq!(
+            collect(&mut db, Box::new(limit)).unwrap(),
+            Vec::<Row>::new()
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use anyhow::{bail, Result};
+
+use super::eval::{self, Row, Scope};
+use super::parser::{Expr, Limit, OrderingTerm};
+use crate::db::{page::ColumnContent, DB};
+
+/// Value the rows are sorted by
+#[derive(Debug)]
+pub(super) enum SortKey {
+    /// Result column
+    Column(usize),
+    /// Expression of the columns of the tables in the FROM clause
+    Expr(Expr),
+}
+
+/// Resolves ORDER BY terms referring to the result columns by number (`ORDER BY 2`)
+/// or by name (`ORDER BY name`). Other terms are expressions, if `expressions` are allowed
+/// (simple select), otherwise it is an error (compound select).
+pub(super) fn sort_keys(
+    order_by: &[OrderingTerm],
+    names: &[String],
+    expressions: bool,
+) -> Result<Vec<(SortKey, bool)>> {
+    let mut keys = Vec::new();
+
+    for (i, term) in order_by.iter().enumerate() {
+        let column = match &term.expr {
+            Expr::Literal(ColumnContent::Int(n)) => {
+                if *n < 1 || *n as usize > names.len() {
+                    bail!(
+                        "{} ORDER BY term out of range - should be between 1 and {}",
+                        ordinal(i + 1),
+                        names.len()
+                    );
+                }
+                Some(*n as usize - 1)
+            }
+            Expr::Column(column) if column.table.is_none() => names
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(&column.column)),
+            expr => {
+                let expr = expr.to_string();
+                names.iter().position(|name| *name == expr)
+            }
+        };
+
+        let key = match column {
+            Some(column) => SortKey::Column(column),
+            None if expressions => SortKey::Expr(term.expr.clone()),
+            None => bail!(
+                "{} ORDER BY term does not match any column in the result set",
+                ordinal(i + 1)
+            ),
+        };
+        keys.push((key, term.descending));
+    }
+
+    Ok(keys)
+}
+
+/// Sorts rows by their keys, keeping the original order of rows with equal keys
+pub(super) fn sort(mut rows: Vec<(Row, Row)>, descending: &[bool]) -> Vec<Row> {
+    rows.sort_by(|(a, _), (b, _)| compare(a, b, descending));
+    rows.into_iter().map(|(_, row)| row).collect()
+}
+
+/// Row with its sort key, ordered by the key and rows with equal keys by their sequence
+/// number, so equal keys keep the order the rows were added in
+pub(super) struct KeyedRow {
+    pub(super) key: Row,
+    pub(super) sequence: usize,
+    pub(super) descending: Rc<[bool]>,
+    pub(super) row: Row,
+}
+
+impl Ord for KeyedRow {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(&self.key, &other.key, &self.descending).then(self.sequence.cmp(&other.sequence))
+    }
+}
+
+impl PartialOrd for KeyedRow {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for KeyedRow {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for KeyedRow {}
+
+/// Compares sort keys of two rows
+pub(super) fn compare(a: &[ColumnContent], b: &[ColumnContent], descending: &[bool]) -> Ordering {
+    for ((a, b), descending) in a.iter().zip(b).zip(descending) {
+        let ord = match descending {
+            true => b.cmp(a),
+            false => a.cmp(b),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Returns the number of rows (None if unlimited) and the number of skipped rows,
+/// negative limit means no limit
+pub(super) fn limit_values(db: &mut DB, limit: &Limit) -> Result<(Option<usize>, usize)> {
+    let count = integer(db, &limit.limit)?;
+    let offset = match &limit.offset {
+        Some(offset) => integer(db, offset)?,
+        None => 0,
+    };
+
+    Ok((
+        (count >= 0).then_some(count as usize),
+        offset.max(0) as usize,
+    ))
+}
+
+fn integer(db: &mut DB, expr: &Expr) -> Result<i64> {
+    match eval::eval(db, expr, &[], &Scope::default())? {
+        ColumnContent::Int(i) => Ok(i),
+        ColumnContent::Float(x) if x.fract() == 0.0 => Ok(x as i64),
+        ColumnContent::Text(s) => match eval::numeric(&s) {
+            ColumnContent::Int(i) => Ok(i),
+            _ => bail!("datatype mismatch"),
+        },
+        _ => bail!("datatype mismatch"),
+    }
+}
+
+/// 1st, 2nd, 3rd, 4th, ...
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
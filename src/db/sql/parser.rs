@@ -1,240 +1,1835 @@
+mod error;
+mod lexer;
+
+use anyhow::Result;
 use nom::{
     branch::alt,
-    bytes::complete::{tag, tag_no_case, take_while1},
-    character::complete::{char, multispace0, multispace1, space0, space1},
-    combinator::{map, opt},
-    multi::{many1, separated_list1},
-    sequence::{delimited, preceded, separated_pair, terminated, tuple},
+    combinator::{map, opt, value},
+    error::{Error, ErrorKind},
+    multi::{many0, many1, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, terminated, tuple},
     IResult,
 };
 
-#[derive(Debug, PartialEq)]
-pub(super) enum ParsedCommand {
-    Count,
-    Select,
-    CreateTable(u16), // parameter is primary key column index
-    CreateIndex,
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{cte::Recursion, eval::Row, Command};
+use crate::db::page::ColumnContent;
+use lexer::{Token, TokenKind};
+
+pub use error::SyntaxError;
+
+/// Tokens of the SQL text
+pub(super) type Input<'a> = &'a [Token<'a>];
+
+/// One item of the select list
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum ResultColumn {
+    /// `*`
+    All,
+    /// `table.*`
+    AllFrom(String),
+    /// `expr [AS alias]`
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+/// `FROM table [JOIN table ...]`
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct FromClause {
+    pub table: TableRef,
+    pub joins: Vec<Join>,
+}
+
+impl FromClause {
+    /// All tables in the FROM clause in the order they are joined
+    pub(crate) fn tables(&self) -> impl Iterator<Item = &TableRef> {
+        std::iter::once(&self.table).chain(self.joins.iter().map(|j| &j.table))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum TableRef {
+    Table {
+        name: String,
+        alias: Option<String>,
+    },
+    /// Derived table `(SELECT ...) AS alias`
+    Subquery {
+        select: Box<Command>,
+        alias: Option<String>,
+        /// Names of the columns replacing names of the select result columns (CTE column list)
+        columns: Vec<String>,
+    },
+    /// Result of a common table expression computed in advance
+    Materialized {
+        name: String,
+        alias: Option<String>,
+        columns: Vec<String>,
+        rows: Rc<Vec<Row>>,
+    },
+    /// Recursive common table expression computed while its rows are read
+    Recursive {
+        alias: Option<String>,
+        recursion: Rc<Recursion>,
+    },
+}
+
+impl TableRef {
+    /// Name under which the table columns can be referenced
+    pub(crate) fn alias(&self) -> &str {
+        match self {
+            TableRef::Table { name, alias } => alias.as_deref().unwrap_or(name),
+            TableRef::Subquery { alias, .. } => alias.as_deref().unwrap_or_default(),
+            TableRef::Materialized { name, alias, .. } => alias.as_deref().unwrap_or(name),
+            TableRef::Recursive { alias, recursion } => alias.as_deref().unwrap_or(&recursion.name),
+        }
+    }
+}
+
+/// Common table expression `name [(column, ...)] AS (SELECT ...)` of the WITH clause
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Cte {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub select: Command,
+}
+
+/// Operator combining results of two selects
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum CompoundOperator {
+    Union,
+    UnionAll,
+    Intersect,
+    Except,
+}
+
+impl std::fmt::Display for CompoundOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CompoundOperator::Union => "UNION",
+            CompoundOperator::UnionAll => "UNION ALL",
+            CompoundOperator::Intersect => "INTERSECT",
+            CompoundOperator::Except => "EXCEPT",
+        })
+    }
+}
+
+/// `expr [ASC | DESC]` of the ORDER BY clause
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct OrderingTerm {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+/// `LIMIT limit [OFFSET offset]`
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Limit {
+    pub limit: Expr,
+    pub offset: Option<Expr>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Join {
+    pub operator: JoinOperator,
+    pub natural: bool,
+    pub table: TableRef,
+    pub constraint: Option<JoinConstraint>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum JoinOperator {
+    /// `JOIN`, `INNER JOIN`, `CROSS JOIN` or `,`
+    Inner,
+    /// `LEFT JOIN` or `LEFT OUTER JOIN`
+    Left,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum JoinConstraint {
+    On(Expr),
+    Using(Vec<String>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Expr {
+    Column(ColumnRef),
+    Literal(ColumnContent),
+    Binary {
+        op: BinaryOperator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Not(Box<Expr>),
+    /// `expr IS NULL` or `expr IS NOT NULL`
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    /// `expr [NOT] IN (value, ...)`
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+        /// The list has distinct literals in order, converted by the affinity of `expr`,
+        /// so a value is looked up by binary search
+        sorted: bool,
+    },
+    /// `expr [NOT] IN (SELECT ...)`
+    InSelect {
+        expr: Box<Expr>,
+        select: Box<Command>,
+        negated: bool,
+    },
+    /// `EXISTS (SELECT ...)`
+    Exists(Box<Command>),
+    /// Scalar function call `name(args)`
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+    /// Scalar subquery `(SELECT ...)`, its value is the first column of the first row
+    Subquery(Box<Command>),
+    /// Statement parameter `?`, `?NNN`, `:name`, `@name` or `$name`
+    Parameter {
+        /// parameter as written in the SQL
+        name: String,
+        /// 1-based index of the bound value, assigned when the statement is prepared
+        index: Option<usize>,
+    },
+}
+
+impl Expr {
+    pub(crate) fn column(table: Option<&str>, column: &str) -> Self {
+        Expr::Column(ColumnRef {
+            table: table.map(|t| t.to_string()),
+            column: column.to_string(),
+        })
+    }
+
+    pub(crate) fn binary(op: BinaryOperator, left: Expr, right: Expr) -> Self {
+        Expr::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Column(column) => write!(f, "{}", column),
+            Expr::Literal(ColumnContent::Text(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(ColumnContent::Null) => write!(f, "NULL"),
+            Expr::Literal(value) => write!(f, "{}", value),
+            Expr::Binary { op, left, right } => write!(f, "{} {} {}", left, op, right),
+            Expr::Not(expr) => write!(f, "NOT {}", expr),
+            Expr::IsNull { expr, negated } => {
+                write!(f, "{} IS {}NULL", expr, if *negated { "NOT " } else { "" })
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+                ..
+            } => {
+                let list: Vec<_> = list.iter().map(|e| e.to_string()).collect();
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "{} {}IN ({})", expr, not, list.join(", "))
+            }
+            Expr::InSelect { expr, negated, .. } => {
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "{} {}IN (SELECT ...)", expr, not)
+            }
+            Expr::Exists(_) => write!(f, "EXISTS (SELECT ...)"),
+            Expr::Function { name, args } => {
+                let args: Vec<_> = args.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expr::Subquery(_) => write!(f, "(SELECT ...)"),
+            Expr::Parameter { name, .. } => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Reference to a column, optionally qualified by the table name or alias (`apples.name`)
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct ColumnRef {
+    pub table: Option<String>,
+    pub column: String,
+}
+
+impl std::fmt::Display for ColumnRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.table {
+            Some(table) => write!(f, "{}.{}", table, self.column),
+            None => write!(f, "{}", self.column),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum BinaryOperator {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    /// `||` string concatenation
+    Concat,
+}
+
+impl std::fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Eq => "=",
+            BinaryOperator::NotEq => "!=",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::LtEq => "<=",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::GtEq => ">=",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Or => "OR",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::Rem => "%",
+            BinaryOperator::Concat => "||",
+        })
+    }
+}
+
+/// Table definition of CREATE TABLE, expressions (DEFAULT, CHECK, ...) are kept as SQL text
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct TableDef {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+    pub constraints: Vec<TableConstraint>,
+    pub without_rowid: bool,
+    pub strict: bool,
+}
+
+impl TableDef {
+    /// Indexes of the PRIMARY KEY columns in the order of the key, true for DESC columns
+    pub(crate) fn primary_key(&self) -> Vec<(usize, bool)> {
+        let primary_key = self.constraints.iter().find_map(|c| match c {
+            TableConstraint::PrimaryKey(columns) => Some(columns),
+            _ => None,
+        });
+        match primary_key {
+            Some(columns) => columns
+                .iter()
+                .filter_map(|column| {
+                    let i = self
+                        .columns
+                        .iter()
+                        .position(|c| c.name.eq_ignore_ascii_case(&column.name))?;
+                    Some((i, column.descending))
+                })
+                .collect(),
+            None => (0..self.columns.len())
+                .filter(|&i| self.columns[i].primary_key)
+                .map(|i| (i, self.columns[i].primary_key_desc))
+                .collect(),
+        }
+    }
+
+    /// Index of the column which is an alias of the rowid, the `INTEGER PRIMARY KEY` column.
+    /// Like in SQLite, `INTEGER PRIMARY KEY DESC` column constraint is not an alias.
+    pub(crate) fn rowid_column(&self) -> Option<usize> {
+        if self.without_rowid {
+            return None;
+        }
+
+        let primary_key = self.constraints.iter().find_map(|c| match c {
+            TableConstraint::PrimaryKey(columns) => Some(columns.as_slice()),
+            _ => None,
+        });
+        let column = match primary_key {
+            Some([column]) => self
+                .columns
+                .iter()
+                .position(|c| c.name.eq_ignore_ascii_case(&column.name))?,
+            Some(_) => return None,
+            None => self
+                .columns
+                .iter()
+                .position(|c| c.primary_key && !c.primary_key_desc)?,
+        };
+
+        self.columns[column]
+            .type_name
+            .eq_ignore_ascii_case("INTEGER")
+            .then_some(column)
+    }
+
+    /// Indexes SQLite creates for the PRIMARY KEY and UNIQUE constraints, named
+    /// `sqlite_autoindex_<table>_N` in the order of the constraints.
+    /// The rowid alias and the primary key of a WITHOUT ROWID table need no index,
+    /// a constraint on the same columns as a previous one shares its index.
+    pub(crate) fn auto_indexes(&self) -> Vec<IndexDef> {
+        let has_index = !self.without_rowid && self.rowid_column().is_none();
+        let column = |name: &str, descending: bool| IndexedColumn {
+            name: name.to_string(),
+            descending,
+        };
+
+        let mut constraints: Vec<Vec<IndexedColumn>> = Vec::new();
+        for c in &self.columns {
+            if c.primary_key && has_index {
+                constraints.push(vec![column(&c.name, c.primary_key_desc)]);
+            }
+            if c.unique {
+                constraints.push(vec![column(&c.name, false)]);
+            }
+        }
+        for constraint in &self.constraints {
+            match constraint {
+                TableConstraint::PrimaryKey(columns) if has_index => {
+                    constraints.push(columns.clone())
+                }
+                TableConstraint::Unique(columns) => constraints.push(columns.clone()),
+                _ => {}
+            }
+        }
+
+        let mut indexes: Vec<IndexDef> = Vec::new();
+        for columns in constraints {
+            let same_columns = |index: &IndexDef| {
+                index.columns.len() == columns.len()
+                    && index.columns.iter().zip(&columns).all(|(key, c)| {
+                        matches!(&key.expr, Expr::Column(k) if k.column.eq_ignore_ascii_case(&c.name))
+                    })
+            };
+            if indexes.iter().any(same_columns) {
+                continue;
+            }
+
+            let columns = columns
+                .iter()
+                .map(|c| IndexKey {
+                    expr: Expr::column(None, &c.name),
+                    // the key is compared with the collation of the column
+                    collation: self
+                        .columns
+                        .iter()
+                        .find(|column| column.name.eq_ignore_ascii_case(&c.name))
+                        .and_then(|column| column.collation.clone()),
+                    descending: c.descending,
+                })
+                .collect();
+            indexes.push(IndexDef {
+                name: format!("sqlite_autoindex_{}_{}", self.name, indexes.len() + 1),
+                table: self.name.clone(),
+                unique: true,
+                columns,
+                where_cond: None,
+            });
+        }
+        indexes
+    }
+}
+
+/// Column definition `name [type] [constraints]` of CREATE TABLE
+#[derive(Debug, PartialEq, Clone, Default)]
+pub(crate) struct ColumnDef {
+    /// name in its original case
+    pub name: String,
+    /// declared type as written (`VARCHAR(20)`), empty if none
+    pub type_name: String,
+    pub primary_key: bool,
+    /// `PRIMARY KEY DESC`
+    pub primary_key_desc: bool,
+    pub autoincrement: bool,
+    pub not_null: bool,
+    pub unique: bool,
+    /// `DEFAULT value`
+    pub default: Option<String>,
+    /// `COLLATE name`
+    pub collation: Option<String>,
+    /// `CHECK (expr)`
+    pub checks: Vec<String>,
+    /// `REFERENCES table [(columns)]`
+    pub references: Option<ForeignKey>,
+    /// `[GENERATED ALWAYS] AS (expr) [STORED | VIRTUAL]`
+    pub generated: Option<Generated>,
+}
+
+impl ColumnDef {
+    /// Affinity of the column by the rules of SQLite for its declared type
+    pub(crate) fn affinity(&self) -> Affinity {
+        let type_name = self.type_name.to_uppercase();
+        if type_name.contains("INT") {
+            Affinity::Integer
+        } else if ["CHAR", "CLOB", "TEXT"]
+            .iter()
+            .any(|t| type_name.contains(t))
+        {
+            Affinity::Text
+        } else if type_name.is_empty() || type_name.contains("BLOB") {
+            Affinity::Blob
+        } else if ["REAL", "FLOA", "DOUB"]
+            .iter()
+            .any(|t| type_name.contains(t))
+        {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+}
+
+/// Type affinity of a column, the storage class its values are converted to
+/// when they are stored or compared with the column
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub(crate) enum Affinity {
+    Text,
+    Numeric,
+    Integer,
+    Real,
+    /// values are not converted
+    #[default]
+    Blob,
+}
+
+/// Generated column expression
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Generated {
+    pub expr: String,
+    pub stored: bool,
+}
+
+/// `REFERENCES table [(columns)]` of a foreign key
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct ForeignKey {
+    pub table: String,
+    pub columns: Vec<String>,
+}
+
+/// Column of a PRIMARY KEY or UNIQUE constraint `name [COLLATE collation] [ASC | DESC]`
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct IndexedColumn {
+    pub name: String,
+    pub descending: bool,
+}
+
+/// Constraint following the column definitions of CREATE TABLE
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum TableConstraint {
+    PrimaryKey(Vec<IndexedColumn>),
+    Unique(Vec<IndexedColumn>),
+    Check(String),
+    ForeignKey {
+        columns: Vec<String>,
+        references: ForeignKey,
+    },
+}
+
+/// Index definition of CREATE INDEX
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct IndexDef {
+    pub name: String,
+    pub table: String,
+    pub unique: bool,
+    pub columns: Vec<IndexKey>,
+    /// condition of a partial index, only rows satisfying it are in the index
+    pub where_cond: Option<Expr>,
+}
+
+/// Key of CREATE INDEX `expr [COLLATE collation] [ASC | DESC]`, usually a column
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct IndexKey {
+    pub expr: Expr,
+    pub collation: Option<String>,
+    pub descending: bool,
+}
+
+/// Words that can't be used as a table alias without quoting
+const KEYWORDS: &[&str] = &[
+    "select",
+    "from",
+    "where",
+    "join",
+    "inner",
+    "left",
+    "outer",
+    "cross",
+    "natural",
+    "on",
+    "using",
+    "as",
+    "and",
+    "or",
+    "not",
+    "is",
+    "null",
+    "group",
+    "order",
+    "limit",
+    "in",
+    "between",
+    "exists",
+    "with",
+    "recursive",
+    "union",
+    "all",
+    "intersect",
+    "except",
+    "by",
+    "asc",
+    "desc",
+    "offset",
+];
+
+thread_local! {
+    /// The furthest failure of the grammar: number of tokens left and what was expected there.
+    /// `opt` and `many0` swallow errors, so the position of the syntax error is tracked aside.
+    static FURTHEST_FAILURE: RefCell<(usize, Vec<&'static str>)> = const { RefCell::new((usize::MAX, Vec::new())) };
+}
+
+/// Parses the whole SQL text, the statement can be followed by semicolons
+pub(super) fn parse_sql<T>(
+    sql: &str,
+    mut parser: impl FnMut(Input) -> IResult<Input, T>,
+) -> Result<T> {
+    let tokens = lexer::tokenize(sql)?;
+
+    FURTHEST_FAILURE.with_borrow_mut(|furthest| *furthest = (usize::MAX, Vec::new()));
+    let rem = match parser(&tokens) {
+        Ok((rem, value)) => {
+            let semicolons = rem.iter().take_while(|t| t.text == ";").count();
+            let rem = &rem[semicolons..];
+            if rem.is_empty() {
+                return Ok(value);
+            }
+            rem
+        }
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => err.input,
+        Err(nom::Err::Incomplete(_)) => unreachable!("parsers are complete"),
+    };
+    let (mut left, mut expected) = FURTHEST_FAILURE.with_borrow_mut(std::mem::take);
+
+    // text after a complete statement is an error without any expectations
+    if left > rem.len() {
+        (left, expected) = (rem.len(), Vec::new());
+    }
+    let near = tokens.get(tokens.len() - left);
+
+    // misspelled keyword (`FORM`) expects just the keyword it resembles
+    if let Some(kw) = expected
+        .iter()
+        .find(|kw| near.is_some_and(|t| misspelled(t.text, kw)))
+    {
+        expected = vec![kw];
+    }
+    let mut labels: Vec<String> = Vec::new();
+    for label in expected {
+        let label = match label.starts_with(char::is_alphabetic) {
+            true => label.to_string(),
+            false => format!("\"{label}\""),
+        };
+        if !labels.contains(&label) {
+            labels.push(label);
+        }
+    }
+
+    let err = match near {
+        Some(token) => SyntaxError::new(
+            sql,
+            token.offset,
+            format!("near \"{}\": syntax error", token.text),
+            labels,
+        ),
+        None => SyntaxError::new(
+            sql,
+            sql.trim_end().len(),
+            "incomplete input".to_string(),
+            labels,
+        ),
+    };
+    Err(err.into())
+}
+
+/// Word is the keyword with one letter changed or two letters swapped
+fn misspelled(word: &str, keyword: &str) -> bool {
+    let word: Vec<_> = word.to_uppercase().chars().collect();
+    let keyword: Vec<_> = keyword.chars().collect();
+    if word.len() != keyword.len() || !keyword.iter().all(char::is_ascii_uppercase) {
+        return false;
+    }
+
+    let diff: Vec<_> = (0..word.len()).filter(|&i| word[i] != keyword[i]).collect();
+    match diff[..] {
+        [_] => true,
+        [a, b] => b == a + 1 && word[a] == keyword[b] && word[b] == keyword[a],
+        _ => false,
+    }
+}
+
+/// Query or CREATE TABLE, CREATE INDEX or CREATE VIEW statement
+pub(super) fn parse_statement(input: Input) -> IResult<Input, Command> {
+    alt((
+        parse_query,
+        parse_explain_query_plan,
+        parse_create_table,
+        parse_create_index,
+        parse_create_view,
+    ))(input)
+}
+
+/// `[WITH ...] SELECT ... [UNION SELECT ...] [ORDER BY ...] [LIMIT ...]`
+pub(super) fn parse_query(input: Input) -> IResult<Input, Command> {
+    let (rem, with) = opt(parse_with)(input)?;
+    let (rem, select) = parse_compound(rem)?;
+
+    let (rem, order_by) = opt(preceded(
+        pair(keyword("ORDER"), keyword("BY")),
+        separated_list1(symbol(","), parse_ordering_term),
+    ))(rem)?;
+    let (rem, limit) = opt(preceded(keyword("LIMIT"), parse_limit))(rem)?;
+
+    let mut command = select;
+    if order_by.is_some() || limit.is_some() {
+        command = Command::Ordered {
+            select: Box::new(command),
+            order_by: order_by.unwrap_or_default(),
+            limit,
+        };
+    }
+    if let Some((recursive, ctes)) = with {
+        command = Command::With {
+            recursive,
+            ctes,
+            select: Box::new(command),
+        };
+    }
+
+    Ok((rem, command))
+}
+
+/// `WITH [RECURSIVE] name AS (SELECT ...), ...`
+fn parse_with(input: Input) -> IResult<Input, (bool, Vec<Cte>)> {
+    let (rem, _) = keyword("WITH")(input)?;
+    let (rem, recursive) = opt(keyword("RECURSIVE"))(rem)?;
+    let (rem, ctes) = separated_list1(symbol(","), parse_cte)(rem)?;
+
+    Ok((rem, (recursive.is_some(), ctes)))
+}
+
+fn parse_cte(input: Input) -> IResult<Input, Cte> {
+    let (rem, name) = parse_identifier(input)?;
+    let (rem, columns) = opt(parse_column_list)(rem)?;
+    let (rem, _) = keyword("AS")(rem)?;
+    let (rem, select) = parse_subquery(rem)?;
+
+    Ok((
+        rem,
+        Cte {
+            name,
+            columns: columns.unwrap_or_default(),
+            select,
+        },
+    ))
+}
+
+/// `(a, b, c)` column names of a CTE, a view or the USING clause
+fn parse_column_list(input: Input) -> IResult<Input, Vec<String>> {
+    delimited(
+        symbol("("),
+        separated_list1(symbol(","), parse_identifier),
+        symbol(")"),
+    )(input)
+}
+
+/// `SELECT ... UNION [ALL] | INTERSECT | EXCEPT SELECT ...`
+fn parse_compound(input: Input) -> IResult<Input, Command> {
+    let (rem, first) = parse_select(input)?;
+    let (rem, rest) = many0(pair(
+        alt((
+            value(
+                CompoundOperator::UnionAll,
+                pair(keyword("UNION"), keyword("ALL")),
+            ),
+            value(CompoundOperator::Union, keyword("UNION")),
+            value(CompoundOperator::Intersect, keyword("INTERSECT")),
+            value(CompoundOperator::Except, keyword("EXCEPT")),
+        )),
+        parse_select,
+    ))(rem)?;
+
+    let command = rest
+        .into_iter()
+        .fold(first, |left, (op, right)| Command::Compound {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        });
+    Ok((rem, command))
+}
+
+/// `expr [ASC | DESC]`
+fn parse_ordering_term(input: Input) -> IResult<Input, OrderingTerm> {
+    let (rem, expr) = parse_expr(input)?;
+    let (rem, descending) = opt(alt((
+        value(false, keyword("ASC")),
+        value(true, keyword("DESC")),
+    )))(rem)?;
+
+    Ok((
+        rem,
+        OrderingTerm {
+            expr,
+            descending: descending.unwrap_or_default(),
+        },
+    ))
+}
+
+/// `count [OFFSET offset]` or `offset, count`
+fn parse_limit(input: Input) -> IResult<Input, Limit> {
+    let (rem, first) = parse_expr(input)?;
+    let (rem, second) = opt(alt((
+        map(preceded(keyword("OFFSET"), parse_expr), Ok),
+        map(preceded(symbol(","), parse_expr), Err),
+    )))(rem)?;
+
+    let limit = match second {
+        None => Limit {
+            limit: first,
+            offset: None,
+        },
+        Some(Ok(offset)) => Limit {
+            limit: first,
+            offset: Some(offset),
+        },
+        Some(Err(limit)) => Limit {
+            limit,
+            offset: Some(first),
+        },
+    };
+    Ok((rem, limit))
+}
+
+pub(super) fn parse_select(input: Input) -> IResult<Input, Command> {
+    let (rem, _) = keyword("SELECT")(input)?;
+
+    let (rem, count) = opt(preceded(
+        keyword("COUNT"),
+        delimited(
+            symbol("("),
+            alt((map(symbol("*"), str::to_string), parse_field)),
+            symbol(")"),
+        ),
+    ))(rem)?;
+
+    // MIN(column) or MAX(column), true for MAX
+    let (rem, extremum) = match count {
+        Some(_) => (rem, None),
+        None => opt(pair(
+            alt((value(false, keyword("MIN")), value(true, keyword("MAX")))),
+            delimited(symbol("("), parse_field, symbol(")")),
+        ))(rem)?,
+    };
+
+    let (rem, columns) = if count.is_some() || extremum.is_some() {
+        (rem, Vec::new())
+    } else {
+        separated_list1(symbol(","), parse_result_column)(rem)?
+    };
+
+    // SELECT without FROM returns a single row
+    let (rem, from) = opt(preceded(keyword("FROM"), parse_from))(rem)?;
+
+    let (rem, where_cond) = opt(preceded(keyword("WHERE"), parse_expr))(rem)?;
+
+    let command = match (count, extremum) {
+        (Some(column), _) => Command::Count {
+            column: column.to_lowercase(),
+            from,
+            where_cond,
+        },
+        (None, Some((max, column))) => Command::MinMax {
+            max,
+            column: column.to_lowercase(),
+            from,
+            where_cond,
+        },
+        (None, None) => Command::Select {
+            columns,
+            from,
+            where_cond,
+        },
+    };
+
+    Ok((rem, command))
+}
+
+fn parse_result_column(input: Input) -> IResult<Input, ResultColumn> {
+    alt((
+        value(ResultColumn::All, symbol("*")),
+        map(
+            terminated(parse_identifier, pair(symbol("."), symbol("*"))),
+            ResultColumn::AllFrom,
+        ),
+        map(
+            pair(parse_expr, opt(preceded(opt(keyword("AS")), parse_alias))),
+            |(expr, alias)| ResultColumn::Expr { expr, alias },
+        ),
+    ))(input)
+}
+
+fn parse_from(input: Input) -> IResult<Input, FromClause> {
+    let (rem, table) = parse_table_ref(input)?;
+    let (rem, joins) = many0(parse_join)(rem)?;
+
+    Ok((rem, FromClause { table, joins }))
+}
+
+fn parse_table_ref(input: Input) -> IResult<Input, TableRef> {
+    let (rem, subquery) = opt(parse_subquery)(input)?;
+    let (rem, name) = match subquery {
+        Some(_) => (rem, None),
+        None => map(parse_identifier, Some)(rem)?,
+    };
+
+    let (rem, alias) = opt(preceded(opt(keyword("AS")), parse_alias))(rem)?;
+
+    let table_ref = match (subquery, name) {
+        (Some(select), _) => TableRef::Subquery {
+            select: Box::new(select),
+            alias,
+            columns: Vec::new(),
+        },
+        (None, name) => TableRef::Table {
+            name: name.unwrap_or_default(),
+            alias,
+        },
+    };
+
+    Ok((rem, table_ref))
+}
+
+/// `(SELECT ...)`
+fn parse_subquery(input: Input) -> IResult<Input, Command> {
+    delimited(symbol("("), parse_query, symbol(")"))(input)
+}
+
+fn parse_join(input: Input) -> IResult<Input, Join> {
+    // comma join: FROM a, b
+    let comma_join = map(preceded(symbol(","), parse_table_ref), |table| Join {
+        operator: JoinOperator::Inner,
+        natural: false,
+        table,
+        constraint: None,
+    });
+
+    let join_operator = tuple((
+        opt(keyword("NATURAL")),
+        opt(alt((
+            value(
+                JoinOperator::Left,
+                pair(keyword("LEFT"), opt(keyword("OUTER"))),
+            ),
+            value(JoinOperator::Inner, keyword("INNER")),
+            value(JoinOperator::Inner, keyword("CROSS")),
+        ))),
+        keyword("JOIN"),
+    ));
+
+    let join_constraint = alt((
+        map(preceded(keyword("ON"), parse_expr), JoinConstraint::On),
+        map(
+            preceded(keyword("USING"), parse_column_list),
+            JoinConstraint::Using,
+        ),
+    ));
+
+    let explicit_join = map(
+        tuple((join_operator, parse_table_ref, opt(join_constraint))),
+        |((natural, operator, _), table, constraint)| Join {
+            operator: operator.unwrap_or(JoinOperator::Inner),
+            natural: natural.is_some(),
+            table,
+            constraint,
+        },
+    );
+
+    alt((comma_join, explicit_join))(input)
+}
+
+/// Parses an expression (operators by ascending precedence: OR, AND, NOT, comparison,
+/// `+ -`, `* / %`, `||`)
+pub(super) fn parse_expr(input: Input) -> IResult<Input, Expr> {
+    parse_or(input)
+}
+
+/// Value of the column `DEFAULT`, a bare identifier is a string, TRUE and FALSE are 1 and 0
+pub(super) fn parse_default_value(input: Input) -> IResult<Input, Expr> {
+    alt((
+        value(Expr::Literal(ColumnContent::Int(1)), keyword("TRUE")),
+        value(Expr::Literal(ColumnContent::Int(0)), keyword("FALSE")),
+        token("identifier", |t| match &t.kind {
+            TokenKind::Word
+                if !["NULL", "CURRENT_TIME", "CURRENT_DATE", "CURRENT_TIMESTAMP"]
+                    .iter()
+                    .any(|kw| kw.eq_ignore_ascii_case(t.text)) =>
+            {
+                Some(Expr::Literal(ColumnContent::Text(t.text.to_string())))
+            }
+            TokenKind::QuotedIdentifier(name) => {
+                Some(Expr::Literal(ColumnContent::Text(name.clone())))
+            }
+            _ => None,
+        }),
+        parse_expr,
+    ))(input)
+}
+
+fn parse_or(input: Input) -> IResult<Input, Expr> {
+    parse_binary(input, value(BinaryOperator::Or, keyword("OR")), parse_and)
+}
+
+fn parse_and(input: Input) -> IResult<Input, Expr> {
+    parse_binary(input, value(BinaryOperator::And, keyword("AND")), parse_not)
+}
+
+fn parse_not(input: Input) -> IResult<Input, Expr> {
+    alt((
+        map(preceded(keyword("NOT"), parse_not), |e| {
+            Expr::Not(Box::new(e))
+        }),
+        parse_comparison,
+    ))(input)
+}
+
+fn parse_comparison(input: Input) -> IResult<Input, Expr> {
+    let (rem, left) = parse_additive(input)?;
+
+    let operator = alt((
+        value(BinaryOperator::Eq, symbol("==")),
+        value(BinaryOperator::Eq, symbol("=")),
+        value(BinaryOperator::NotEq, symbol("!=")),
+        value(BinaryOperator::NotEq, symbol("<>")),
+        value(BinaryOperator::LtEq, symbol("<=")),
+        value(BinaryOperator::Lt, symbol("<")),
+        value(BinaryOperator::GtEq, symbol(">=")),
+        value(BinaryOperator::Gt, symbol(">")),
+    ));
+
+    let (rem, right) = opt(pair(operator, parse_additive))(rem)?;
+    if let Some((op, right)) = right {
+        return Ok((rem, Expr::binary(op, left, right)));
+    }
+
+    // expr [NOT] BETWEEN low AND high is expr >= low AND expr <= high
+    let (rem, between) = opt(tuple((
+        opt(keyword("NOT")),
+        preceded(keyword("BETWEEN"), parse_additive),
+        preceded(keyword("AND"), parse_additive),
+    )))(rem)?;
+    if let Some((negated, low, high)) = between {
+        let between = Expr::binary(
+            BinaryOperator::And,
+            Expr::binary(BinaryOperator::GtEq, left.clone(), low),
+            Expr::binary(BinaryOperator::LtEq, left, high),
+        );
+        return Ok((
+            rem,
+            match negated {
+                Some(_) => Expr::Not(Box::new(between)),
+                None => between,
+            },
+        ));
+    }
+
+    // expr IS [NOT] NULL
+    let (rem, is_null) = opt(preceded(
+        keyword("IS"),
+        terminated(opt(keyword("NOT")), keyword("NULL")),
+    ))(rem)?;
+    if let Some(negated) = is_null {
+        return Ok((
+            rem,
+            Expr::IsNull {
+                expr: Box::new(left),
+                negated: negated.is_some(),
+            },
+        ));
+    }
+
+    // expr [NOT] IN (SELECT ...) or expr [NOT] IN (value, ...)
+    let (rem, in_list) = opt(pair(
+        opt(keyword("NOT")),
+        preceded(keyword("IN"), parse_in_list),
+    ))(rem)?;
+    if let Some((negated, list)) = in_list {
+        let expr = Box::new(left);
+        let negated = negated.is_some();
+        let in_list = match list {
+            Ok(select) => Expr::InSelect {
+                expr,
+                select: Box::new(select),
+                negated,
+            },
+            Err(list) => Expr::InList {
+                expr,
+                list,
+                negated,
+                sorted: false,
+            },
+        };
+        return Ok((rem, in_list));
+    }
+
+    Ok((rem, left))
+}
+
+fn parse_additive(input: Input) -> IResult<Input, Expr> {
+    let operator = alt((
+        value(BinaryOperator::Add, symbol("+")),
+        value(BinaryOperator::Sub, symbol("-")),
+    ));
+    parse_binary(input, operator, parse_multiplicative)
+}
+
+fn parse_multiplicative(input: Input) -> IResult<Input, Expr> {
+    let operator = alt((
+        value(BinaryOperator::Mul, symbol("*")),
+        value(BinaryOperator::Div, symbol("/")),
+        value(BinaryOperator::Rem, symbol("%")),
+    ));
+    parse_binary(input, operator, parse_concat)
+}
+
+fn parse_concat(input: Input) -> IResult<Input, Expr> {
+    parse_binary(
+        input,
+        value(BinaryOperator::Concat, symbol("||")),
+        parse_primary,
+    )
+}
+
+/// Parses left associative binary operators of the same precedence
+fn parse_binary<'a>(
+    input: Input<'a>,
+    operator: impl FnMut(Input<'a>) -> IResult<Input<'a>, BinaryOperator>,
+    operand: fn(Input<'a>) -> IResult<Input<'a>, Expr>,
+) -> IResult<Input<'a>, Expr> {
+    let (rem, first) = operand(input)?;
+    let (rem, rest) = many0(pair(operator, operand))(rem)?;
+
+    let expr = rest
+        .into_iter()
+        .fold(first, |left, (op, right)| Expr::binary(op, left, right));
+    Ok((rem, expr))
+}
+
+/// Parses a subquery (`Ok`) or a list of values (`Err`) of the IN operator
+fn parse_in_list(input: Input) -> IResult<Input, Result<Command, Vec<Expr>>> {
+    alt((
+        map(parse_subquery, Ok),
+        map(
+            delimited(
+                symbol("("),
+                separated_list0(symbol(","), parse_expr),
+                symbol(")"),
+            ),
+            Err,
+        ),
+    ))(input)
+}
+
+fn parse_primary(input: Input) -> IResult<Input, Expr> {
+    alt((
+        map(parse_subquery, |select| Expr::Subquery(Box::new(select))),
+        map(preceded(keyword("EXISTS"), parse_subquery), |select| {
+            Expr::Exists(Box::new(select))
+        }),
+        delimited(symbol("("), parse_expr, symbol(")")),
+        map(parse_literal, Expr::Literal),
+        parse_parameter,
+        parse_function,
+        map(parse_column_ref, Expr::Column),
+    ))(input)
+}
+
+/// `name(args)`
+fn parse_function(input: Input) -> IResult<Input, Expr> {
+    let (rem, name) = parse_identifier(input)?;
+    let (rem, args) = delimited(
+        symbol("("),
+        separated_list0(symbol(","), parse_expr),
+        symbol(")"),
+    )(rem)?;
+
+    Ok((rem, Expr::Function { name, args }))
+}
+
+fn parse_parameter(input: Input) -> IResult<Input, Expr> {
+    token("parameter", |t| {
+        (t.kind == TokenKind::Parameter).then(|| Expr::Parameter {
+            name: t.text.to_string(),
+            index: None,
+        })
+    })(input)
+}
+
+fn parse_literal(input: Input) -> IResult<Input, ColumnContent> {
+    let number = |sign: i64| {
+        token("number", move |t| match t.kind {
+            TokenKind::Integer(i) => Some(ColumnContent::Int(i.wrapping_mul(sign))),
+            // the lowest integer is out of the range of a positive one
+            TokenKind::Float(_)
+                if sign < 0 && t.text.parse::<u64>() == Ok(i64::MIN.unsigned_abs()) =>
+            {
+                Some(ColumnContent::Int(i64::MIN))
+            }
+            TokenKind::Float(x) => Some(ColumnContent::Float(x * sign as f64)),
+            _ => None,
+        })
+    };
+
+    alt((
+        token("string", |t| match &t.kind {
+            TokenKind::String(s) => Some(ColumnContent::Text(s.clone())),
+            TokenKind::Blob(b) => Some(ColumnContent::Blob(b.clone())),
+            _ => None,
+        }),
+        number(1),
+        preceded(symbol("-"), number(-1)),
+        preceded(symbol("+"), number(1)),
+        value(ColumnContent::Null, keyword("NULL")),
+    ))(input)
+}
+
+fn parse_column_ref(input: Input) -> IResult<Input, ColumnRef> {
+    let (rem, first) = parse_identifier(input)?;
+    let (rem, second) = opt(preceded(symbol("."), parse_identifier))(rem)?;
+
+    let column_ref = match second {
+        Some(column) => ColumnRef {
+            table: Some(first),
+            column,
+        },
+        None => ColumnRef {
+            table: None,
+            column: first,
+        },
+    };
+
+    Ok((rem, column_ref))
+}
+
+/// Table or column name, either bare or quoted
+fn parse_identifier(input: Input) -> IResult<Input, String> {
+    token("identifier", |t| match &t.kind {
+        TokenKind::Word => Some(t.text.to_lowercase()),
+        TokenKind::QuotedIdentifier(name) => Some(name.to_lowercase()),
+        _ => None,
+    })(input)
+}
+
+/// Alias can be any identifier except a bare keyword (`FROM apples WHERE ...`)
+fn parse_alias(input: Input) -> IResult<Input, String> {
+    token("identifier", |t| match &t.kind {
+        TokenKind::Word if KEYWORDS.iter().any(|kw| kw.eq_ignore_ascii_case(t.text)) => None,
+        TokenKind::Word => Some(t.text.to_lowercase()),
+        TokenKind::QuotedIdentifier(name) => Some(name.to_lowercase()),
+        _ => None,
+    })(input)
+}
+
+/// Case insensitive keyword
+fn keyword<'a>(kw: &'static str) -> impl FnMut(Input<'a>) -> IResult<Input<'a>, &'a str> {
+    token(kw, move |t| {
+        (t.kind == TokenKind::Word && t.text.eq_ignore_ascii_case(kw)).then_some(t.text)
+    })
+}
+
+/// Operator or punctuation
+fn symbol<'a>(symbol: &'static str) -> impl FnMut(Input<'a>) -> IResult<Input<'a>, &'a str> {
+    token(symbol, move |t| {
+        (t.kind == TokenKind::Symbol && t.text == symbol).then_some(t.text)
+    })
+}
+
+/// Parses the next token by `f`, fails if `f` returns None.
+/// The failure is recorded with the `expected` description for the syntax error.
+fn token<'a, O>(
+    expected: &'static str,
+    f: impl Fn(&'a Token<'a>) -> Option<O>,
+) -> impl FnMut(Input<'a>) -> IResult<Input<'a>, O> {
+    move |input: Input<'a>| {
+        let kind = match input.split_first() {
+            Some((token, rem)) => match f(token) {
+                Some(output) => return Ok((rem, output)),
+                None => ErrorKind::Tag,
+            },
+            None => ErrorKind::Eof,
+        };
+
+        FURTHEST_FAILURE.with_borrow_mut(|(left, labels)| {
+            if input.len() < *left {
+                *left = input.len();
+                labels.clear();
+            }
+            if input.len() == *left {
+                labels.push(expected);
+            }
+        });
+
+        Err(nom::Err::Error(Error::new(input, kind)))
+    }
 }
 
-#[derive(Debug, PartialEq)]
-pub(super) struct Parsed {
-    pub command: ParsedCommand,
-    pub columns: Vec<String>,
-    pub table: String,
-    pub where_cond: Option<Condition>, // WHERE color = 'Yellow'
+/// `CREATE [TEMP] TABLE [IF NOT EXISTS] name (column-def, ... [, table-constraint, ...]) [table-options]`
+pub(super) fn parse_create_table(input: Input) -> IResult<Input, Command> {
+    /*
+    CREATE TABLE apples
+    (
+        id integer primary key autoincrement,
+        name text,
+        color text
+    )
+    */
+    let (rem, _) = keyword("CREATE")(input)?;
+    let (rem, _) = opt(alt((keyword("TEMPORARY"), keyword("TEMP"))))(rem)?;
+    let (rem, _) = keyword("TABLE")(rem)?;
+    let (rem, _) = opt(tuple((keyword("IF"), keyword("NOT"), keyword("EXISTS"))))(rem)?;
+    let (rem, _schema) = opt(terminated(parse_field, symbol(".")))(rem)?;
+    let (rem, name) = parse_field(rem)?;
+    let (rem, _) = symbol("(")(rem)?;
+    let (rem, columns) = separated_list1(symbol(","), parse_column_def)(rem)?;
+    // SQLite allows table constraints without the separating comma
+    let (rem, constraints) = many0(preceded(opt(symbol(",")), parse_table_constraint))(rem)?;
+    let (rem, _) = symbol(")")(rem)?;
+    let (rem, options) = separated_list0(
+        symbol(","),
+        alt((
+            value("WITHOUT ROWID", pair(keyword("WITHOUT"), keyword("ROWID"))),
+            value("STRICT", keyword("STRICT")),
+        )),
+    )(rem)?;
+
+    Ok((
+        rem,
+        Command::CreateTable(TableDef {
+            name,
+            columns,
+            constraints,
+            without_rowid: options.contains(&"WITHOUT ROWID"),
+            strict: options.contains(&"STRICT"),
+        }),
+    ))
 }
 
-#[derive(Debug, PartialEq)]
-pub(super) struct Condition {
-    pub column: String,
-    pub value: String,
+/// Column definition `name [type] [constraints]`
+fn parse_column_def(input: Input) -> IResult<Input, ColumnDef> {
+    // words starting a table constraint are not column names
+    let (rem, name) = token("name", |t| match &t.kind {
+        TokenKind::Word
+            if ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"]
+                .iter()
+                .any(|kw| kw.eq_ignore_ascii_case(t.text)) =>
+        {
+            None
+        }
+        TokenKind::Word => Some(t.text.to_string()),
+        TokenKind::QuotedIdentifier(name) | TokenKind::String(name) => Some(name.clone()),
+        _ => None,
+    })(input)?;
+    let (rem, type_name) = opt(parse_type_name)(rem)?;
+    let (rem, constraints) = many0(parse_column_constraint)(rem)?;
+
+    let mut column = ColumnDef {
+        name,
+        type_name: type_name.unwrap_or_default(),
+        ..ColumnDef::default()
+    };
+    for constraint in constraints {
+        match constraint {
+            ColumnConstraint::PrimaryKey {
+                descending,
+                autoincrement,
+            } => {
+                column.primary_key = true;
+                column.primary_key_desc = descending;
+                column.autoincrement = autoincrement;
+            }
+            ColumnConstraint::NotNull => column.not_null = true,
+            ColumnConstraint::Null => {}
+            ColumnConstraint::Unique => column.unique = true,
+            ColumnConstraint::Check(expr) => column.checks.push(expr),
+            ColumnConstraint::Default(value) => column.default = Some(value),
+            ColumnConstraint::Collate(collation) => column.collation = Some(collation),
+            ColumnConstraint::References(foreign_key) => column.references = Some(foreign_key),
+            ColumnConstraint::Generated(generated) => column.generated = Some(generated),
+        }
+    }
+
+    Ok((rem, column))
 }
 
-pub(super) fn parse_select(sql: &str) -> IResult<&str, Parsed> {
-    let (rem, _) = tag_no_case("SELECT")(sql)?;
-    let (rem, _) = space1(rem)?;
+/// Constraint of one column in CREATE TABLE
+#[derive(Clone)]
+enum ColumnConstraint {
+    PrimaryKey {
+        descending: bool,
+        autoincrement: bool,
+    },
+    NotNull,
+    Null,
+    Unique,
+    Check(String),
+    Default(String),
+    Collate(String),
+    References(ForeignKey),
+    Generated(Generated),
+}
 
-    let (rem, count) = opt(preceded(
-        tag_no_case("COUNT"),
-        alt((
-            delimited(tag("("), parse_field, tag(")")),
-            delimited(tag("("), tag("*"), tag(")")),
-        )),
+/// Type name `VARCHAR(20)`, `UNSIGNED BIG INT` or `DECIMAL(10, 5)`
+fn parse_type_name(input: Input) -> IResult<Input, String> {
+    let type_word = token("type name", |t| match &t.kind {
+        TokenKind::Word
+            if [
+                "CONSTRAINT",
+                "PRIMARY",
+                "NOT",
+                "NULL",
+                "UNIQUE",
+                "CHECK",
+                "DEFAULT",
+                "COLLATE",
+                "REFERENCES",
+                "GENERATED",
+                "AS",
+            ]
+            .iter()
+            .any(|kw| kw.eq_ignore_ascii_case(t.text)) =>
+        {
+            None
+        }
+        TokenKind::Word => Some(t.text),
+        TokenKind::QuotedIdentifier(_) => Some(t.text),
+        _ => None,
+    });
+    let signed_number = |input| {
+        let (rem, sign) = opt(alt((symbol("+"), symbol("-"))))(input)?;
+        let (rem, number) = token("number", |t| match t.kind {
+            TokenKind::Integer(_) | TokenKind::Float(_) => Some(t.text),
+            _ => None,
+        })(rem)?;
+        Ok((rem, format!("{}{}", sign.unwrap_or_default(), number)))
+    };
+
+    let (rem, words) = many1(type_word)(input)?;
+    let (rem, size) = opt(delimited(
+        symbol("("),
+        separated_list1(symbol(","), signed_number),
+        symbol(")"),
     ))(rem)?;
 
-    let mut command = ParsedCommand::Select;
+    let mut type_name = words.join(" ");
+    if let Some(size) = size {
+        type_name.push_str(&format!("({})", size.join(",")));
+    }
+    Ok((rem, type_name))
+}
 
-    let (rem, columns) = if let Some(column) = count {
-        command = ParsedCommand::Count;
-        (rem, vec![column])
-    } else {
-        alt((
-            many1(tag("*")),
-            separated_list1(tuple((space0, tag(","), space0)), parse_field),
-        ))(rem)?
-    };
+fn parse_column_constraint(input: Input) -> IResult<Input, ColumnConstraint> {
+    let (rem, _) = opt(pair(keyword("CONSTRAINT"), parse_field))(input)?;
+
+    alt((
+        map(
+            tuple((
+                keyword("PRIMARY"),
+                keyword("KEY"),
+                opt(alt((
+                    value(false, keyword("ASC")),
+                    value(true, keyword("DESC")),
+                ))),
+                opt(parse_conflict_clause),
+                opt(keyword("AUTOINCREMENT")),
+            )),
+            |(_, _, descending, _, autoincrement)| ColumnConstraint::PrimaryKey {
+                descending: descending.unwrap_or(false),
+                autoincrement: autoincrement.is_some(),
+            },
+        ),
+        value(
+            ColumnConstraint::NotNull,
+            tuple((keyword("NOT"), keyword("NULL"), opt(parse_conflict_clause))),
+        ),
+        value(
+            ColumnConstraint::Null,
+            pair(keyword("NULL"), opt(parse_conflict_clause)),
+        ),
+        value(
+            ColumnConstraint::Unique,
+            pair(keyword("UNIQUE"), opt(parse_conflict_clause)),
+        ),
+        map(preceded(keyword("CHECK"), parse_parenthesized), |expr| {
+            ColumnConstraint::Check(sql_text(expr))
+        }),
+        map(
+            preceded(keyword("DEFAULT"), parse_default),
+            ColumnConstraint::Default,
+        ),
+        map(
+            preceded(keyword("COLLATE"), parse_field),
+            ColumnConstraint::Collate,
+        ),
+        map(parse_foreign_key, ColumnConstraint::References),
+        map(
+            tuple((
+                opt(pair(keyword("GENERATED"), keyword("ALWAYS"))),
+                keyword("AS"),
+                parse_parenthesized,
+                opt(alt((
+                    value(true, keyword("STORED")),
+                    value(false, keyword("VIRTUAL")),
+                ))),
+            )),
+            |(_, _, expr, stored)| {
+                ColumnConstraint::Generated(Generated {
+                    expr: sql_text(expr),
+                    stored: stored.unwrap_or(false),
+                })
+            },
+        ),
+    ))(rem)
+}
 
-    let columns = columns.into_iter().map(|c| c.to_lowercase()).collect();
+/// Default value `DEFAULT -1`, `DEFAULT 'text'`, `DEFAULT CURRENT_TIMESTAMP` or `DEFAULT (expr)`
+fn parse_default(input: Input) -> IResult<Input, String> {
+    if let Ok((rem, _)) = parse_parenthesized(input) {
+        let len = input.len() - rem.len();
+        return Ok((rem, sql_text(&input[..len])));
+    }
 
-    let (rem, _) = space1(rem)?;
-    let (rem, _) = tag_no_case("FROM")(rem)?;
-    let (rem, _) = space1(rem)?;
-    let (rem, table) = terminated(parse_field, space0)(rem)?;
+    let (rem, sign) = opt(alt((symbol("+"), symbol("-"))))(input)?;
+    let (rem, value) = token("default value", |t| match t.kind {
+        TokenKind::Symbol | TokenKind::Parameter => None,
+        _ => Some(t.text),
+    })(rem)?;
 
-    let mut where_cond = None;
+    Ok((rem, format!("{}{}", sign.unwrap_or_default(), value)))
+}
 
-    let (rem, cond) = opt(map(
+/// `ON CONFLICT ROLLBACK | ABORT | FAIL | IGNORE | REPLACE`
+fn parse_conflict_clause(input: Input) -> IResult<Input, ()> {
+    value(
+        (),
         tuple((
-            tag_no_case("WHERE"),
-            space1,
-            separated_pair(parse_field, tuple((space0, tag("="), space0)), parse_field),
+            keyword("ON"),
+            keyword("CONFLICT"),
+            alt((
+                keyword("ROLLBACK"),
+                keyword("ABORT"),
+                keyword("FAIL"),
+                keyword("IGNORE"),
+                keyword("REPLACE"),
+            )),
         )),
-        |s| s.2,
-    ))(rem)?;
+    )(input)
+}
 
-    if let Some(cond) = cond {
-        where_cond = Some(Condition {
-            column: cond.0.to_string(),
-            value: cond.1.to_string(),
-        })
-    }
+/// `REFERENCES table [(columns)] [ON DELETE action] [MATCH name] [[NOT] DEFERRABLE ...]`
+fn parse_foreign_key(input: Input) -> IResult<Input, ForeignKey> {
+    let action = alt((
+        value((), pair(keyword("SET"), keyword("NULL"))),
+        value((), pair(keyword("SET"), keyword("DEFAULT"))),
+        value((), keyword("CASCADE")),
+        value((), keyword("RESTRICT")),
+        value((), pair(keyword("NO"), keyword("ACTION"))),
+    ));
+    let (rem, _) = keyword("REFERENCES")(input)?;
+    let (rem, table) = parse_field(rem)?;
+    let (rem, columns) = opt(delimited(
+        symbol("("),
+        separated_list1(symbol(","), parse_field),
+        symbol(")"),
+    ))(rem)?;
+    let (rem, _) = many0(alt((
+        value(
+            (),
+            tuple((
+                keyword("ON"),
+                alt((keyword("DELETE"), keyword("UPDATE"))),
+                action,
+            )),
+        ),
+        value((), pair(keyword("MATCH"), parse_field)),
+    )))(rem)?;
+    let (rem, _) = opt(tuple((
+        opt(keyword("NOT")),
+        keyword("DEFERRABLE"),
+        opt(pair(
+            keyword("INITIALLY"),
+            alt((keyword("DEFERRED"), keyword("IMMEDIATE"))),
+        )),
+    )))(rem)?;
 
     Ok((
         rem,
-        Parsed {
-            command,
-            columns,
-            table: table.to_lowercase(),
-            where_cond,
+        ForeignKey {
+            table,
+            columns: columns.unwrap_or_default(),
         },
     ))
 }
 
-pub(super) fn parse_create_table(sql: &str) -> IResult<&str, Parsed> {
-    /*
-    CREATE TABLE apples
-    (
-        id integer primary key autoincrement,
-        name text,
-        color text
-    )
-    */
-    let (rem, _) = multispace0(sql)?;
-    let (rem, _) = tag_no_case("CREATE")(rem)?;
-    let (rem, _) = space1(rem)?;
-    let (rem, _) = tag_no_case("TABLE")(rem)?;
-    let (rem, _) = space1(rem)?;
-    let (rem, table) = parse_field(rem)?;
-    let (rem, _) = multispace1(rem)?;
-    let (rem, columns) = parse_columns(rem)?;
+/// `[CONSTRAINT name] PRIMARY KEY (columns) | UNIQUE (columns) | CHECK (expr) | FOREIGN KEY (columns) REFERENCES ...`
+fn parse_table_constraint(input: Input) -> IResult<Input, TableConstraint> {
+    let (rem, _) = opt(pair(keyword("CONSTRAINT"), parse_field))(input)?;
+    let indexed_columns = || {
+        terminated(
+            delimited(
+                symbol("("),
+                separated_list1(symbol(","), parse_indexed_column),
+                symbol(")"),
+            ),
+            opt(parse_conflict_clause),
+        )
+    };
 
-    let mut primary_key = 0;
-    let columns = columns
-        .into_iter()
-        .enumerate()
-        .map(|(i, (col, is_primary))| {
-            if is_primary {
-                primary_key = i as u16;
-            }
-            col.to_string()
-        })
-        .collect();
+    alt((
+        map(
+            preceded(pair(keyword("PRIMARY"), keyword("KEY")), indexed_columns()),
+            TableConstraint::PrimaryKey,
+        ),
+        map(
+            preceded(keyword("UNIQUE"), indexed_columns()),
+            TableConstraint::Unique,
+        ),
+        map(preceded(keyword("CHECK"), parse_parenthesized), |expr| {
+            TableConstraint::Check(sql_text(expr))
+        }),
+        map(
+            tuple((
+                keyword("FOREIGN"),
+                keyword("KEY"),
+                delimited(
+                    symbol("("),
+                    separated_list1(symbol(","), parse_field),
+                    symbol(")"),
+                ),
+                parse_foreign_key,
+            )),
+            |(_, _, columns, references)| TableConstraint::ForeignKey {
+                columns,
+                references,
+            },
+        ),
+    ))(rem)
+}
+
+/// `name [COLLATE collation] [ASC | DESC]`
+fn parse_indexed_column(input: Input) -> IResult<Input, IndexedColumn> {
+    let (rem, name) = parse_field(input)?;
+    let (rem, _) = opt(pair(keyword("COLLATE"), parse_field))(rem)?;
+    let (rem, descending) = opt(alt((
+        value(false, keyword("ASC")),
+        value(true, keyword("DESC")),
+    )))(rem)?;
 
     Ok((
         rem,
-        Parsed {
-            command: ParsedCommand::CreateTable(primary_key),
-            columns,
-            table: table.to_string(),
-            where_cond: None,
+        IndexedColumn {
+            name,
+            descending: descending.unwrap_or(false),
         },
     ))
 }
 
-pub(super) fn parse_create_index(sql: &str) -> IResult<&str, Parsed> {
+/// Tokens inside parentheses, nested parentheses are included
+fn parse_parenthesized(input: Input) -> IResult<Input, Input> {
+    let (rem, _) = symbol("(")(input)?;
+
+    let mut depth = 0;
+    for (i, t) in rem.iter().enumerate() {
+        match (t.kind == TokenKind::Symbol, t.text) {
+            (true, "(") => depth += 1,
+            (true, ")") if depth == 0 => return Ok((&rem[i + 1..], &rem[..i])),
+            (true, ")") => depth -= 1,
+            _ => {}
+        }
+    }
+
+    // the closing parenthesis is missing, this fails at the end of the input
+    let (rem, _) = symbol(")")(&rem[rem.len()..])?;
+    Ok((rem, rem))
+}
+
+/// SQL text of the tokens, whitespace and comments between them are replaced by a space
+fn sql_text(tokens: Input) -> String {
+    let mut text = String::new();
+    let mut end = None;
+    for t in tokens {
+        if end.is_some_and(|end| end < t.offset) {
+            text.push(' ');
+        }
+        text.push_str(t.text);
+        end = Some(t.offset + t.text.len());
+    }
+    text
+}
+/// `CREATE [UNIQUE] INDEX [IF NOT EXISTS] [schema.]name ON table (key, ...) [WHERE expr]`
+pub(super) fn parse_create_index(input: Input) -> IResult<Input, Command> {
     /* CREATE INDEX idx_companies_country on companies (country); */
-    let (rem, _) = multispace0(sql)?;
-    let (rem, _) = tag_no_case("CREATE")(rem)?;
-    let (rem, _) = space1(rem)?;
-    let (rem, _) = tag_no_case("INDEX")(rem)?;
-    let (rem, _) = space1(rem)?;
-    let (rem, _index_name) = parse_field(rem)?;
-    let (rem, _) = multispace1(rem)?;
-    let (rem, _) = tag_no_case("ON")(rem)?;
-    let (rem, _) = multispace1(rem)?;
+    let (rem, _) = keyword("CREATE")(input)?;
+    let (rem, unique) = opt(keyword("UNIQUE"))(rem)?;
+    let (rem, _) = keyword("INDEX")(rem)?;
+    let (rem, _) = opt(tuple((keyword("IF"), keyword("NOT"), keyword("EXISTS"))))(rem)?;
+    let (rem, _schema) = opt(terminated(parse_field, symbol(".")))(rem)?;
+    let (rem, name) = parse_field(rem)?;
+    let (rem, _) = keyword("ON")(rem)?;
     let (rem, table) = parse_field(rem)?;
-    let (rem, _) = multispace1(rem)?;
     let (rem, columns) = delimited(
-        tag("("),
-        separated_list1(tuple((space0, tag(","), space0)), parse_field),
-        tag(")"),
+        symbol("("),
+        separated_list1(symbol(","), parse_index_key),
+        symbol(")"),
     )(rem)?;
-
-    let columns = columns.into_iter().map(|s| s.to_string()).collect();
+    let (rem, where_cond) = opt(preceded(keyword("WHERE"), parse_expr))(rem)?;
 
     Ok((
         rem,
-        Parsed {
-            command: ParsedCommand::CreateIndex,
+        Command::CreateIndex(IndexDef {
+            name,
+            table,
+            unique: unique.is_some(),
             columns,
-            table: table.to_string(),
-            where_cond: None,
-        },
+            where_cond,
+        }),
     ))
 }
 
-fn parse_field(input: &str) -> IResult<&str, &str> {
-    let (rem, v) = alt((
-        delimited(
-            // field in quotes can contain space or _
-            alt((char('"'), char('\''))),
-            take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == ' '),
-            alt((char('"'), char('\''))),
-        ),
-        // regular field
-        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-    ))(input)?;
+/// `expr [COLLATE collation] [ASC | DESC]`
+fn parse_index_key(input: Input) -> IResult<Input, IndexKey> {
+    let (rem, expr) = parse_expr(input)?;
+    let (rem, collation) = opt(preceded(keyword("COLLATE"), parse_field))(rem)?;
+    let (rem, descending) = opt(alt((
+        value(false, keyword("ASC")),
+        value(true, keyword("DESC")),
+    )))(rem)?;
 
-    Ok((rem, v))
+    Ok((
+        rem,
+        IndexKey {
+            expr,
+            collation,
+            descending: descending.unwrap_or(false),
+        },
+    ))
 }
 
-fn parse_column_name(input: &str) -> IResult<&str, (&str, bool)> {
-    let (rem, v) = terminated(
-        many1(tuple((multispace0, parse_field, multispace0))),
-        alt((tag(","), multispace0)),
-    )(input)?;
-
-    let is_primary_key = v.iter().any(|t| t.1 == "primary");
+/// `EXPLAIN QUERY PLAN select`
+fn parse_explain_query_plan(input: Input) -> IResult<Input, Command> {
+    let (rem, _) = tuple((keyword("EXPLAIN"), keyword("QUERY"), keyword("PLAN")))(input)?;
+    let (rem, select) = parse_query(rem)?;
 
-    Ok((rem, (v[0].1, is_primary_key)))
+    Ok((rem, Command::ExplainQueryPlan(Box::new(select))))
 }
 
-fn all_columns(input: &str) -> IResult<&str, Vec<(&str, bool)>> {
-    let (rem, v) = many1(parse_column_name)(input)?;
-    Ok((rem, v))
-}
+/// `CREATE [TEMP] VIEW [IF NOT EXISTS] name [(columns)] AS select`
+pub(super) fn parse_create_view(input: Input) -> IResult<Input, Command> {
+    let (rem, _) = keyword("CREATE")(input)?;
+    let (rem, _) = opt(alt((keyword("TEMPORARY"), keyword("TEMP"))))(rem)?;
+    let (rem, _) = keyword("VIEW")(rem)?;
+    let (rem, _) = opt(tuple((keyword("IF"), keyword("NOT"), keyword("EXISTS"))))(rem)?;
+    let (rem, name) = parse_identifier(rem)?;
+    let (rem, columns) = opt(parse_column_list)(rem)?;
+    let (rem, _) = keyword("AS")(rem)?;
+    let (rem, select) = parse_query(rem)?;
 
-fn parse_columns(input: &str) -> IResult<&str, Vec<(&str, bool)>> {
-    let (rem, _) = multispace0(input)?;
-    let (rem, v) = delimited(tag("("), all_columns, tag(")"))(rem)?;
-    let (rem, _) = multispace0(rem)?;
+    Ok((
+        rem,
+        Command::CreateView {
+            name,
+            columns: columns.unwrap_or_default(),
+            select: Box::new(select),
+        },
+    ))
+}
 
-    Ok((rem, v))
+/// Name in CREATE TABLE or CREATE INDEX in its original case, bare or quoted
+fn parse_field(input: Input) -> IResult<Input, String> {
+    token("name", |t| match &t.kind {
+        TokenKind::Word => Some(t.text.to_string()),
+        TokenKind::QuotedIdentifier(name) | TokenKind::String(name) => Some(name.clone()),
+        _ => None,
+    })(input)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Parses the SQL by the parser, returns the remaining tokens and the result
+    fn parse<T>(
+        mut parser: impl FnMut(Input) -> IResult<Input, T>,
+        sql: &str,
+    ) -> Result<(String, T)> {
+        let tokens = lexer::tokenize(sql)?;
+        let (rem, value) = parser(&tokens).map_err(|_| anyhow::anyhow!("can't parse {sql}"))?;
+        let rem: Vec<_> = rem.iter().map(|t| t.text).collect();
+        Ok((rem.join(" "), value))
+    }
+
+    fn from(table: &str) -> Option<FromClause> {
+        Some(FromClause {
+            table: TableRef::Table {
+                name: table.to_string(),
+                alias: None,
+            },
+            joins: Vec::new(),
+        })
+    }
+
+    fn column(name: &str) -> ResultColumn {
+        ResultColumn::Expr {
+            expr: Expr::column(None, name),
+            alias: None,
+        }
+    }
+
     #[test]
     fn test_parse_field() {
         let sql = "name ,";
-        let o = parse_field(sql);
+        let o = parse(parse_field, sql);
         let o = o.unwrap();
-        assert_eq!(o, (" ,", "name"));
+        assert_eq!(o, (",".to_string(), "name".to_string()));
 
         let sql = "year_founded2, another";
-        let o = parse_field(sql);
+        let o = parse(parse_field, sql);
         let o = o.unwrap();
-        assert_eq!(o, (", another", "year_founded2"));
+        assert_eq!(o, (", another".to_string(), "year_founded2".to_string()));
 
         let sql = "\"size range\" text";
-        let o = parse_field(sql);
+        let o = parse(parse_field, sql);
         let o = o.unwrap();
-        assert_eq!(o, (" text", "size range"));
+        assert_eq!(o, ("text".to_string(), "size range".to_string()));
     }
 
     #[test]
     fn test_parse_sql_count_asterix_uppercase() {
         let sql = "SELECT COUNT(*) FROM oranges     ";
-        let c = parse_select(sql);
+        let c = parse(parse_select, sql);
         let c = c.unwrap();
         assert_eq!(
             c.1,
-            Parsed {
-                command: ParsedCommand::Count,
-                columns: vec!["*".to_string()],
-                table: "oranges".to_string(),
+            Command::Count {
+                column: "*".to_string(),
+                from: from("oranges"),
                 where_cond: None,
             }
         );
@@ -243,30 +1838,62 @@ mod tests {
     #[test]
     fn test_parse_sql_count_column_uppercase() {
         let sql = "SELECT COUNT(NAME) FROM ORANGES";
-        let c = parse_select(sql);
+        let c = parse(parse_select, sql);
         let c = c.unwrap();
         assert_eq!(
             c.1,
-            Parsed {
-                command: ParsedCommand::Count,
-                columns: vec!["name".to_string()],
-                table: "oranges".to_string(),
+            Command::Count {
+                column: "name".to_string(),
+                from: from("oranges"),
+                where_cond: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_sql_min_max() {
+        let (_, c) = parse(parse_select, "SELECT MAX(ID) FROM ORANGES").unwrap();
+        assert_eq!(
+            c,
+            Command::MinMax {
+                max: true,
+                column: "id".to_string(),
+                from: from("oranges"),
                 where_cond: None,
             }
         );
+        let (_, c) = parse(parse_select, "SELECT min(name) FROM oranges").unwrap();
+        assert!(matches!(c, Command::MinMax { max: false, .. }));
+    }
+
+    #[test]
+    fn test_parse_explain_query_plan() {
+        let c = parse_sql(
+            "explain query plan SELECT name FROM oranges",
+            parse_statement,
+        )
+        .unwrap();
+        assert_eq!(
+            c,
+            Command::ExplainQueryPlan(Box::new(Command::Select {
+                columns: vec![column("name")],
+                from: from("oranges"),
+                where_cond: None,
+            }))
+        );
+        assert!(parse_sql("EXPLAIN SELECT name FROM oranges", parse_statement).is_err());
     }
 
     #[test]
     fn test_parse_sql_select_column_uppercase() {
         let sql = "SELECT NAME FROM ORANGES";
-        let c = parse_select(sql);
+        let c = parse(parse_select, sql);
         let c = c.unwrap();
         assert_eq!(
             c.1,
-            Parsed {
-                command: ParsedCommand::Select,
-                columns: vec!["name".to_string()],
-                table: "oranges".to_string(),
+            Command::Select {
+                columns: vec![column("name")],
+                from: from("oranges"),
                 where_cond: None,
             }
         );
@@ -275,14 +1902,13 @@ mod tests {
     #[test]
     fn test_parse_sql_select_multiple_columns_uppercase() {
         let sql = "SELECT id, name ,descr FROM oranges";
-        let c = parse_select(sql);
+        let c = parse(parse_select, sql);
         let c = c.unwrap();
         assert_eq!(
             c.1,
-            Parsed {
-                command: ParsedCommand::Select,
-                columns: vec!["id".to_string(), "name".to_string(), "descr".to_string()],
-                table: "oranges".to_string(),
+            Command::Select {
+                columns: vec![column("id"), column("name"), column("descr")],
+                from: from("oranges"),
                 where_cond: None,
             }
         );
@@ -291,18 +1917,18 @@ mod tests {
     #[test]
     fn test_parse_sql_select_with_where_clause() {
         let sql = "SELECT name, color FROM apples WHERE color = 'Yellow'";
-        let c = parse_select(sql);
+        let c = parse(parse_select, sql);
         let c = c.unwrap();
         assert_eq!(
             c.1,
-            Parsed {
-                command: ParsedCommand::Select,
-                columns: vec!["name".to_string(), "color".to_string()],
-                table: "apples".to_string(),
-                where_cond: Some(Condition {
-                    column: "color".to_string(),
-                    value: "Yellow".to_string()
-                }),
+            Command::Select {
+                columns: vec![column("name"), column("color")],
+                from: from("apples"),
+                where_cond: Some(Expr::binary(
+                    BinaryOperator::Eq,
+                    Expr::column(None, "color"),
+                    Expr::Literal(ColumnContent::Text("Yellow".to_string()))
+                )),
             }
         );
     }
@@ -310,68 +1936,116 @@ mod tests {
     #[test]
     fn test_parse_sql_select_asterix() {
         let sql = "SELECT * FROM oranges";
-        let c = parse_select(sql);
+        let c = parse(parse_select, sql);
         let c = c.unwrap();
         assert_eq!(
             c.1,
-            Parsed {
-                command: ParsedCommand::Select,
-                columns: vec!["*".to_string()],
-                table: "oranges".to_string(),
+            Command::Select {
+                columns: vec![ResultColumn::All],
+                from: from("oranges"),
                 where_cond: None,
             }
         );
     }
 
     #[test]
-    fn test_parse_column_name() {
+    fn test_parse_column_def() {
         let sql = "\n	id integer primary key autoincrement, \n";
-        let c = parse_column_name(sql);
-        let (_, column) = c.unwrap();
-        assert_eq!(column, ("id", true));
+        let c = parse(parse_column_def, sql);
+        let (rem, column) = c.unwrap();
+        assert_eq!(rem, ",");
+        assert_eq!(
+            column,
+            ColumnDef {
+                name: "id".to_string(),
+                type_name: "integer".to_string(),
+                primary_key: true,
+                autoincrement: true,
+                ..ColumnDef::default()
+            }
+        );
 
         let sql = "\n	color text \n";
-        let c = parse_column_name(sql);
+        let c = parse(parse_column_def, sql);
         let (_, column) = c.unwrap();
-        assert_eq!(column, ("color", false));
-    }
+        assert_eq!(
+            column,
+            ColumnDef {
+                name: "color".to_string(),
+                type_name: "text".to_string(),
+                ..ColumnDef::default()
+            }
+        );
 
-    #[test]
-    fn test_all_columns() {
-        // multiline
-        let sql = "\n id integer primary key autoincrement,
-	name text,
-	color text\n";
-        let c = all_columns(sql);
-        let (_, columns) = c.unwrap();
+        let sql = "price DECIMAL (10, -2) CONSTRAINT positive CHECK (price > 0 AND (price < 100))
+            NOT NULL ON CONFLICT FAIL DEFAULT -1 COLLATE NOCASE UNIQUE
+            REFERENCES prices(value) ON DELETE SET NULL DEFERRABLE INITIALLY DEFERRED";
+        let c = parse(parse_column_def, sql);
+        let (rem, column) = c.unwrap();
+        assert_eq!(rem, "");
         assert_eq!(
-            columns,
-            vec![("id", true), ("name", false), ("color", false)]
+            column,
+            ColumnDef {
+                name: "price".to_string(),
+                type_name: "DECIMAL(10,-2)".to_string(),
+                not_null: true,
+                unique: true,
+                default: Some("-1".to_string()),
+                collation: Some("NOCASE".to_string()),
+                checks: vec!["price > 0 AND (price < 100)".to_string()],
+                references: Some(ForeignKey {
+                    table: "prices".to_string(),
+                    columns: vec!["value".to_string()],
+                }),
+                ..ColumnDef::default()
+            }
         );
 
-        // one line
-        let sql = "id integer primary key autoincrement,name text,color text";
-        let c = all_columns(sql);
-        let (_, columns) = c.unwrap();
+        let sql = "total UNSIGNED BIG INT GENERATED ALWAYS AS (price*count) STORED";
+        let (_, column) = parse(parse_column_def, sql).unwrap();
+        assert_eq!(column.type_name, "UNSIGNED BIG INT");
         assert_eq!(
-            columns,
-            vec![("id", true), ("name", false), ("color", false)]
+            column.generated,
+            Some(Generated {
+                expr: "price*count".to_string(),
+                stored: true,
+            })
         );
-    }
 
-    #[test]
-    fn test_parse_columns() {
-        let sql = "(\n	id integer primary key autoincrement,
-	name text,
-	color text\n)";
-        let c = parse_columns(sql);
-        let (_, columns) = c.unwrap();
+        let sql = "created DEFAULT (datetime('now', 'localtime'))";
+        let (_, column) = parse(parse_column_def, sql).unwrap();
+        assert_eq!(column.type_name, "");
         assert_eq!(
-            columns,
-            vec![("id", true), ("name", false), ("color", false)]
+            column.default.as_deref(),
+            Some("(datetime('now', 'localtime'))")
         );
     }
 
+    #[test]
+    fn test_parse_default_value() {
+        for (sql, expected) in [
+            ("-7", Expr::Literal(ColumnContent::Int(-7))),
+            (
+                "'Oslo'",
+                Expr::Literal(ColumnContent::Text("Oslo".to_string())),
+            ),
+            ("NULL", Expr::Literal(ColumnContent::Null)),
+            ("TRUE", Expr::Literal(ColumnContent::Int(1))),
+            ("false", Expr::Literal(ColumnContent::Int(0))),
+            ("xyz", Expr::Literal(ColumnContent::Text("xyz".to_string()))),
+            (
+                "\"Xyz\"",
+                Expr::Literal(ColumnContent::Text("Xyz".to_string())),
+            ),
+        ] {
+            assert_eq!(
+                parse_sql(sql, parse_default_value).unwrap(),
+                expected,
+                "{sql}"
+            );
+        }
+    }
+
     #[test]
     fn test_parse_sql_create_table() {
         let sql = "\n CREATE TABLE companies2
@@ -383,53 +2057,606 @@ mod tests {
 	color text
 )
 ";
-        let c = parse_create_table(sql);
-        let c = c.unwrap();
+        let c = parse(parse_create_table, sql);
+        let (_, c) = c.unwrap();
+        let Command::CreateTable(table) = c else {
+            panic!("expected CREATE TABLE, got {c:?}");
+        };
+        assert_eq!(table.name, "companies2");
         assert_eq!(
-            c.1,
-            Parsed {
-                command: ParsedCommand::CreateTable(0),
-                columns: vec![
-                    "id".to_string(),
-                    "name".to_string(),
-                    "year_produced".to_string(),
-                    "size range".to_string(),
-                    "color".to_string()
-                ],
-                table: "companies2".to_string(),
-                where_cond: None,
-            },
+            table
+                .columns
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["id", "name", "year_produced", "size range", "color"]
+        );
+        assert_eq!(table.rowid_column(), Some(0));
+
+        // one line
+        let sql = "CREATE TABLE apples(id integer primary key autoincrement,name text,color text)";
+        let (_, c) = parse(parse_create_table, sql).unwrap();
+        let Command::CreateTable(table) = c else {
+            panic!("expected CREATE TABLE, got {c:?}");
+        };
+        assert_eq!(table.columns.len(), 3);
+        assert_eq!(table.rowid_column(), Some(0));
+    }
+
+    #[test]
+    fn test_parse_create_table_constraints() {
+        let table = |sql| match parse_sql(sql, parse_create_table) {
+            Ok(Command::CreateTable(table)) => table,
+            c => panic!("expected CREATE TABLE, got {c:?}"),
+        };
+
+        let t = table(
+            "CREATE TABLE IF NOT EXISTS main.orders (
+                id INT, customer TEXT, code TEXT,
+                CONSTRAINT pk PRIMARY KEY (id DESC, customer COLLATE NOCASE) ON CONFLICT ROLLBACK,
+                UNIQUE (code)
+                CHECK (id > 0),
+                FOREIGN KEY (customer) REFERENCES customers (name) ON UPDATE CASCADE
+            ) WITHOUT ROWID, STRICT",
+        );
+        assert_eq!(t.name, "orders");
+        assert_eq!(
+            t.constraints,
+            vec![
+                TableConstraint::PrimaryKey(vec![
+                    IndexedColumn {
+                        name: "id".to_string(),
+                        descending: true,
+                    },
+                    IndexedColumn {
+                        name: "customer".to_string(),
+                        descending: false,
+                    },
+                ]),
+                TableConstraint::Unique(vec![IndexedColumn {
+                    name: "code".to_string(),
+                    descending: false,
+                }]),
+                TableConstraint::Check("id > 0".to_string()),
+                TableConstraint::ForeignKey {
+                    columns: vec!["customer".to_string()],
+                    references: ForeignKey {
+                        table: "customers".to_string(),
+                        columns: vec!["name".to_string()],
+                    },
+                },
+            ]
+        );
+        assert!(t.without_rowid);
+        assert!(t.strict);
+        assert_eq!(t.rowid_column(), None);
+
+        // only a column of type INTEGER is an alias of the rowid
+        for (sql, rowid_column) in [
+            ("CREATE TABLE t (a TEXT, id INTEGER PRIMARY KEY)", Some(1)),
+            (
+                "CREATE TABLE t (a TEXT, id INTEGER, PRIMARY KEY (id))",
+                Some(1),
+            ),
+            ("CREATE TABLE t (a TEXT, id INT PRIMARY KEY)", None),
+            ("CREATE TABLE t (a TEXT, id INTEGER PRIMARY KEY DESC)", None),
+            (
+                "CREATE TABLE t (a TEXT, id INTEGER, PRIMARY KEY (a, id))",
+                None,
+            ),
+            ("CREATE TABLE t (a TEXT PRIMARY KEY, id INTEGER)", None),
+            ("CREATE TABLE t (name text, \"primary\" text)", None),
+        ] {
+            assert_eq!(table(sql).rowid_column(), rowid_column, "{sql}");
+        }
+
+        assert_eq!(
+            table("CREATE TABLE t (a, b, c, PRIMARY KEY (c DESC, a)) WITHOUT ROWID").primary_key(),
+            vec![(2, true), (0, false)]
+        );
+        assert_eq!(
+            table("CREATE TABLE t (a, b PRIMARY KEY DESC)").primary_key(),
+            vec![(1, true)]
+        );
+        assert!(table("CREATE TABLE t (a, b)").primary_key().is_empty());
+
+        let keys = |sql| {
+            table(sql)
+                .auto_indexes()
+                .into_iter()
+                .map(|index| {
+                    let keys = index.columns.iter().map(|key| {
+                        let desc = if key.descending { " DESC" } else { "" };
+                        match &key.collation {
+                            Some(collation) => format!("{} COLLATE {collation}{desc}", key.expr),
+                            None => format!("{}{desc}", key.expr),
+                        }
+                    });
+                    format!("{}({})", index.name, keys.collect::<Vec<_>>().join(", "))
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(
+                "CREATE TABLE t (x UNIQUE, y TEXT PRIMARY KEY, z, UNIQUE (z, x DESC), UNIQUE (x))"
+            ),
+            [
+                "sqlite_autoindex_t_1(x)",
+                "sqlite_autoindex_t_2(y)",
+                "sqlite_autoindex_t_3(z, x DESC)"
+            ]
         );
+        assert_eq!(
+            keys("CREATE TABLE t (id INTEGER PRIMARY KEY, e UNIQUE COLLATE NOCASE)"),
+            ["sqlite_autoindex_t_1(e COLLATE NOCASE)"]
+        );
+        assert_eq!(
+            keys("CREATE TABLE t (k, v UNIQUE, PRIMARY KEY (k)) WITHOUT ROWID"),
+            ["sqlite_autoindex_t_1(v)"]
+        );
+        assert!(keys("CREATE TABLE t (a, b)").is_empty());
     }
 
     #[test]
     fn test_parse_sql_create_index() {
         let sql = "\n CREATE INDEX idx_companies_country\n\ton companies (country)
         ";
-        let c = parse_create_index(sql);
+        let c = parse(parse_create_index, sql);
         let c = c.unwrap();
         assert_eq!(
             c.1,
-            Parsed {
-                command: ParsedCommand::CreateIndex,
-                columns: vec!["country".to_string()],
+            Command::CreateIndex(IndexDef {
+                name: "idx_companies_country".to_string(),
                 table: "companies".to_string(),
+                unique: false,
+                columns: vec![IndexKey {
+                    expr: Expr::column(None, "country"),
+                    collation: None,
+                    descending: false,
+                }],
                 where_cond: None,
-            },
+            }),
         );
 
-        let sql = "\n CREATE INDEX idx_companies_country\n\ton companies_2 (name, country)
-        ";
-        let c = parse_create_index(sql);
-        let c = c.unwrap();
+        let sql = "CREATE UNIQUE INDEX IF NOT EXISTS main.idx_users
+            ON users (lower(email) COLLATE NOCASE, created_at DESC) WHERE deleted = 0";
+        let (rem, c) = parse(parse_create_index, sql).unwrap();
+        assert_eq!(rem, "");
         assert_eq!(
-            c.1,
-            Parsed {
-                command: ParsedCommand::CreateIndex,
-                columns: vec!["name".to_string(), "country".to_string()],
-                table: "companies_2".to_string(),
-                where_cond: None,
-            },
+            c,
+            Command::CreateIndex(IndexDef {
+                name: "idx_users".to_string(),
+                table: "users".to_string(),
+                unique: true,
+                columns: vec![
+                    IndexKey {
+                        expr: Expr::Function {
+                            name: "lower".to_string(),
+                            args: vec![Expr::column(None, "email")],
+                        },
+                        collation: Some("NOCASE".to_string()),
+                        descending: false,
+                    },
+                    IndexKey {
+                        expr: Expr::column(None, "created_at"),
+                        collation: None,
+                        descending: true,
+                    },
+                ],
+                where_cond: Some(Expr::binary(
+                    BinaryOperator::Eq,
+                    Expr::column(None, "deleted"),
+                    Expr::Literal(ColumnContent::Int(0)),
+                )),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_parse_expr() {
+        let sql = "a.id = b.a_id AND (color != 'Red' OR size >= -2) AND NOT name IS NULL";
+        let (rem, expr) = parse(parse_expr, sql).unwrap();
+        assert_eq!(rem, "");
+        assert_eq!(
+            expr,
+            Expr::binary(
+                BinaryOperator::And,
+                Expr::binary(
+                    BinaryOperator::And,
+                    Expr::binary(
+                        BinaryOperator::Eq,
+                        Expr::column(Some("a"), "id"),
+                        Expr::column(Some("b"), "a_id"),
+                    ),
+                    Expr::binary(
+                        BinaryOperator::Or,
+                        Expr::binary(
+                            BinaryOperator::NotEq,
+                            Expr::column(None, "color"),
+                            Expr::Literal(ColumnContent::Text("Red".to_string())),
+                        ),
+                        Expr::binary(
+                            BinaryOperator::GtEq,
+                            Expr::column(None, "size"),
+                            Expr::Literal(ColumnContent::Int(-2)),
+                        ),
+                    ),
+                ),
+                Expr::Not(Box::new(Expr::IsNull {
+                    expr: Box::new(Expr::column(None, "name")),
+                    negated: false,
+                })),
+            )
+        );
+
+        let (rem, expr) = parse(parse_expr, "x > -3.5 OR y = x'CAFE'").unwrap();
+        assert_eq!(rem, "");
+        assert_eq!(
+            expr,
+            Expr::binary(
+                BinaryOperator::Or,
+                Expr::binary(
+                    BinaryOperator::Gt,
+                    Expr::column(None, "x"),
+                    Expr::Literal(ColumnContent::Float(-3.5)),
+                ),
+                Expr::binary(
+                    BinaryOperator::Eq,
+                    Expr::column(None, "y"),
+                    Expr::Literal(ColumnContent::Blob(vec![0xca, 0xfe])),
+                ),
+            )
+        );
+
+        let (rem, expr) = parse(parse_expr, "x NOT BETWEEN 1 AND y + 1 AND z").unwrap();
+        assert_eq!(rem, "");
+        let Expr::Binary { left, .. } = expr else {
+            panic!("{expr:?}");
+        };
+        assert_eq!(
+            *left,
+            Expr::Not(Box::new(Expr::binary(
+                BinaryOperator::And,
+                Expr::binary(
+                    BinaryOperator::GtEq,
+                    Expr::column(None, "x"),
+                    Expr::Literal(ColumnContent::Int(1)),
+                ),
+                Expr::binary(
+                    BinaryOperator::LtEq,
+                    Expr::column(None, "x"),
+                    Expr::binary(
+                        BinaryOperator::Add,
+                        Expr::column(None, "y"),
+                        Expr::Literal(ColumnContent::Int(1)),
+                    ),
+                ),
+            )))
+        );
+    }
+
+    #[test]
+    fn test_parse_sql_select_joins() {
+        let sql = "SELECT a.name, o.* FROM apples AS a JOIN oranges o ON a.id = o.id
+            LEFT OUTER JOIN pears USING (id, name), plums NATURAL LEFT JOIN kiwis WHERE a.id > 1";
+        let (rem, c) = parse(parse_select, sql).unwrap();
+        assert_eq!(rem, "");
+
+        let table = |name: &str, alias: Option<&str>| TableRef::Table {
+            name: name.to_string(),
+            alias: alias.map(|a| a.to_string()),
+        };
+        assert_eq!(
+            c,
+            Command::Select {
+                columns: vec![
+                    ResultColumn::Expr {
+                        expr: Expr::column(Some("a"), "name"),
+                        alias: None,
+                    },
+                    ResultColumn::AllFrom("o".to_string()),
+                ],
+                from: Some(FromClause {
+                    table: table("apples", Some("a")),
+                    joins: vec![
+                        Join {
+                            operator: JoinOperator::Inner,
+                            natural: false,
+                            table: table("oranges", Some("o")),
+                            constraint: Some(JoinConstraint::On(Expr::binary(
+                                BinaryOperator::Eq,
+                                Expr::column(Some("a"), "id"),
+                                Expr::column(Some("o"), "id"),
+                            ))),
+                        },
+                        Join {
+                            operator: JoinOperator::Left,
+                            natural: false,
+                            table: table("pears", None),
+                            constraint: Some(JoinConstraint::Using(vec![
+                                "id".to_string(),
+                                "name".to_string()
+                            ])),
+                        },
+                        Join {
+                            operator: JoinOperator::Inner,
+                            natural: false,
+                            table: table("plums", None),
+                            constraint: None,
+                        },
+                        Join {
+                            operator: JoinOperator::Left,
+                            natural: true,
+                            table: table("kiwis", None),
+                            constraint: None,
+                        },
+                    ],
+                }),
+                where_cond: Some(Expr::binary(
+                    BinaryOperator::Gt,
+                    Expr::column(Some("a"), "id"),
+                    Expr::Literal(ColumnContent::Int(1)),
+                )),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_sql_select_subqueries() {
+        let sql = "SELECT t.name, (SELECT COUNT(*) FROM oranges) AS total
+            FROM (SELECT name, id FROM apples) t
+            WHERE id NOT IN (1, 2) AND EXISTS (SELECT * FROM pears WHERE pears.id IN (SELECT id FROM kiwis))";
+        let (rem, c) = parse(parse_select, sql).unwrap();
+        assert_eq!(rem, "");
+
+        assert_eq!(
+            c,
+            Command::Select {
+                columns: vec![
+                    ResultColumn::Expr {
+                        expr: Expr::column(Some("t"), "name"),
+                        alias: None,
+                    },
+                    ResultColumn::Expr {
+                        expr: Expr::Subquery(Box::new(Command::Count {
+                            column: "*".to_string(),
+                            from: from("oranges"),
+                            where_cond: None,
+                        })),
+                        alias: Some("total".to_string()),
+                    },
+                ],
+                from: Some(FromClause {
+                    table: TableRef::Subquery {
+                        select: Box::new(Command::Select {
+                            columns: vec![column("name"), column("id")],
+                            from: from("apples"),
+                            where_cond: None,
+                        }),
+                        alias: Some("t".to_string()),
+                        columns: Vec::new(),
+                    },
+                    joins: Vec::new(),
+                }),
+                where_cond: Some(Expr::binary(
+                    BinaryOperator::And,
+                    Expr::InList {
+                        expr: Box::new(Expr::column(None, "id")),
+                        list: vec![
+                            Expr::Literal(ColumnContent::Int(1)),
+                            Expr::Literal(ColumnContent::Int(2)),
+                        ],
+                        negated: true,
+                        sorted: false,
+                    },
+                    Expr::Exists(Box::new(Command::Select {
+                        columns: vec![ResultColumn::All],
+                        from: from("pears"),
+                        where_cond: Some(Expr::InSelect {
+                            expr: Box::new(Expr::column(Some("pears"), "id")),
+                            select: Box::new(Command::Select {
+                                columns: vec![column("id")],
+                                from: from("kiwis"),
+                                where_cond: None,
+                            }),
+                            negated: false,
+                        }),
+                    })),
+                )),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_sql_with_recursive() {
+        let sql = "WITH RECURSIVE cnt(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM cnt WHERE x < 10)
+            SELECT x * 2 - 1 || '.' FROM cnt";
+        let (rem, c) = parse(parse_query, sql).unwrap();
+        assert_eq!(rem, "");
+
+        let int = |i| Expr::Literal(ColumnContent::Int(i));
+        assert_eq!(
+            c,
+            Command::With {
+                recursive: true,
+                ctes: vec![Cte {
+                    name: "cnt".to_string(),
+                    columns: vec!["x".to_string()],
+                    select: Command::Compound {
+                        op: CompoundOperator::UnionAll,
+                        left: Box::new(Command::Select {
+                            columns: vec![ResultColumn::Expr {
+                                expr: int(1),
+                                alias: None,
+                            }],
+                            from: None,
+                            where_cond: None,
+                        }),
+                        right: Box::new(Command::Select {
+                            columns: vec![ResultColumn::Expr {
+                                expr: Expr::binary(
+                                    BinaryOperator::Add,
+                                    Expr::column(None, "x"),
+                                    int(1)
+                                ),
+                                alias: None,
+                            }],
+                            from: from("cnt"),
+                            where_cond: Some(Expr::binary(
+                                BinaryOperator::Lt,
+                                Expr::column(None, "x"),
+                                int(10)
+                            )),
+                        }),
+                    },
+                }],
+                select: Box::new(Command::Select {
+                    columns: vec![ResultColumn::Expr {
+                        expr: Expr::binary(
+                            BinaryOperator::Sub,
+                            Expr::binary(BinaryOperator::Mul, Expr::column(None, "x"), int(2)),
+                            Expr::binary(
+                                BinaryOperator::Concat,
+                                int(1),
+                                Expr::Literal(ColumnContent::Text(".".to_string()))
+                            ),
+                        ),
+                        alias: None,
+                    }],
+                    from: from("cnt"),
+                    where_cond: None,
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_sql_compound_ordered() {
+        let sql = "SELECT id FROM apples INTERSECT SELECT id FROM oranges EXCEPT SELECT 2
+            ORDER BY id DESC, 1 LIMIT 2, 3";
+        let (rem, c) = parse(parse_query, sql).unwrap();
+        assert_eq!(rem, "");
+
+        let int = |i| Expr::Literal(ColumnContent::Int(i));
+        let select = |table| Command::Select {
+            columns: vec![ResultColumn::Expr {
+                expr: Expr::column(None, "id"),
+                alias: None,
+            }],
+            from: from(table),
+            where_cond: None,
+        };
+        assert_eq!(
+            c,
+            Command::Ordered {
+                select: Box::new(Command::Compound {
+                    op: CompoundOperator::Except,
+                    left: Box::new(Command::Compound {
+                        op: CompoundOperator::Intersect,
+                        left: Box::new(select("apples")),
+                        right: Box::new(select("oranges")),
+                    }),
+                    right: Box::new(Command::Select {
+                        columns: vec![ResultColumn::Expr {
+                            expr: int(2),
+                            alias: None,
+                        }],
+                        from: None,
+                        where_cond: None,
+                    }),
+                }),
+                order_by: vec![
+                    OrderingTerm {
+                        expr: Expr::column(None, "id"),
+                        descending: true,
+                    },
+                    OrderingTerm {
+                        expr: int(1),
+                        descending: false,
+                    },
+                ],
+                limit: Some(Limit {
+                    limit: int(3),
+                    offset: Some(int(2)),
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_sql_create_view() {
+        let sql =
+            "CREATE VIEW IF NOT EXISTS red(id, \"Apple Name\") AS SELECT id, name FROM apples";
+        let (rem, c) = parse(parse_create_view, sql).unwrap();
+        assert_eq!(rem, "");
+        assert_eq!(
+            c,
+            Command::CreateView {
+                name: "red".to_string(),
+                columns: vec!["id".to_string(), "apple name".to_string()],
+                select: Box::new(Command::Select {
+                    columns: vec![
+                        ResultColumn::Expr {
+                            expr: Expr::column(None, "id"),
+                            alias: None,
+                        },
+                        ResultColumn::Expr {
+                            expr: Expr::column(None, "name"),
+                            alias: None,
+                        },
+                    ],
+                    from: from("apples"),
+                    where_cond: None,
+                }),
+            }
+        );
+
+        let (rem, c) = parse(
+            parse_create_view,
+            "CREATE TEMP VIEW v AS SELECT 1 UNION SELECT 2",
+        )
+        .unwrap();
+        assert_eq!(rem, "");
+        assert!(matches!(
+            c,
+            Command::CreateView { select, .. } if matches!(*select, Command::Compound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let error = |sql| {
+            parse_sql(sql, parse_statement)
+                .unwrap_err()
+                .downcast::<SyntaxError>()
+                .unwrap()
+        };
+
+        let err = error("SELECT * FORM apples");
+        assert_eq!(err.message, "near \"FORM\": syntax error");
+        assert_eq!((err.line, err.column), (1, 10));
+        assert_eq!(err.expected, vec!["FROM"]);
+        assert_eq!(
+            err.to_string(),
+            "near \"FORM\": syntax error at line 1 column 10, expected FROM"
         );
+
+        let err = error("SELECT name\nFROM apples\nWHERE id IN (1, 2;");
+        assert_eq!(err.message, "near \";\": syntax error");
+        assert_eq!((err.line, err.column), (3, 18));
+        assert_eq!(err.source_line, "WHERE id IN (1, 2;");
+        assert!(err.expected.contains(&"\")\"".to_string()));
+
+        let err = error("SELECT name FROM apples WHERE id =");
+        assert_eq!(err.message, "incomplete input");
+        assert_eq!((err.line, err.column), (1, 35));
+
+        let err = error("SELECT 1 FROM apples; SELECT 2");
+        assert_eq!(err.message, "near \"SELECT\": syntax error");
+        assert!(err.expected.is_empty());
+
+        assert!(misspelled("form", "FROM"));
+        assert!(misspelled("WHERA", "WHERE"));
+        assert!(!misspelled("apples", "FROM"));
     }
 }
@@ -0,0 +1,101 @@
+use std::fmt;
+
+/// Error in the SQL text with its position, like
+/// `near "FORM": syntax error at line 1 column 10, expected FROM`
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxError {
+    /// What is wrong, `near "FORM": syntax error` or `unrecognized token: "'abc"`
+    pub message: String,
+    /// Line of the error, starting at 1
+    pub line: usize,
+    /// Column of the error in characters, starting at 1
+    pub column: usize,
+    /// Tokens that would be accepted at the position of the error
+    pub expected: Vec<String>,
+    /// Text of the line with the error
+    pub source_line: String,
+}
+
+impl SyntaxError {
+    /// Error at the byte `offset` of the SQL text
+    pub(crate) fn new(sql: &str, offset: usize, message: String, expected: Vec<String>) -> Self {
+        let before = &sql[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line_end = sql[offset..].find('\n').map_or(sql.len(), |i| offset + i);
+
+        Self {
+            message,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            expected,
+            source_line: sql[line_start..line_end].trim_end_matches('\r').to_string(),
+        }
+    }
+
+    /// The line with the error and a caret under the position of the error
+    pub fn snippet(&self) -> String {
+        // tabs are kept so that the caret is aligned
+        let padding: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        format!("  {}\n  {}^--- error here", self.source_line, padding)
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {} column {}",
+            self.message, self.line, self.column
+        )?;
+
+        if let Some((last, rest)) = self.expected.split_last() {
+            write!(f, ", expected ")?;
+            if !rest.is_empty() {
+                write!(f, "{} or ", rest.join(", "))?;
+            }
+            write!(f, "{last}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syntax_error() {
+        let sql = "SELECT name\n\tFORM apples";
+        let err = SyntaxError::new(
+            sql,
+            sql.find("FORM").unwrap(),
+            "near \"FORM\": syntax error".to_string(),
+            vec!["FROM".to_string()],
+        );
+        assert_eq!((err.line, err.column), (2, 2));
+        assert_eq!(err.source_line, "\tFORM apples");
+        assert_eq!(
+            err.to_string(),
+            "near \"FORM\": syntax error at line 2 column 2, expected FROM"
+        );
+        assert_eq!(err.snippet(), "  \tFORM apples\n  \t^--- error here");
+
+        let err = SyntaxError {
+            expected: vec!["\",\"".to_string(), "FROM".to_string(), "WHERE".to_string()],
+            ..err
+        };
+        assert_eq!(
+            err.to_string(),
+            "near \"FORM\": syntax error at line 2 column 2, expected \",\", FROM or WHERE"
+        );
+    }
+}
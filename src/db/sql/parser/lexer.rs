@@ -0,0 +1,293 @@
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag, tag_no_case, take_until, take_while, take_while1},
+    character::complete::{
+        char, digit0, digit1, hex_digit0, hex_digit1, multispace1, one_of, satisfy,
+    },
+    combinator::{map, map_res, not, opt, peek, recognize, rest, value, verify},
+    multi::{fold_many0, many0_count},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    IResult,
+};
+
+use super::SyntaxError;
+
+/// Token of the SQL text
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Token<'a> {
+    pub kind: TokenKind,
+    /// Token as written in the SQL text
+    pub text: &'a str,
+    /// Byte offset of the token in the SQL text
+    pub offset: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum TokenKind {
+    /// Keyword or identifier `SELECT`, `apples`
+    Word,
+    /// Identifier in quotes `"size range"`, `[size range]` or `` `size range` ``, without the quotes
+    QuotedIdentifier(String),
+    /// String literal `'O''Brien'`, without the quotes
+    String(String),
+    /// Integer literal `42` or `0x2A`
+    Integer(i64),
+    /// Floating point literal `3.5` or `1e10`, also integer literal too big for 64 bits
+    Float(f64),
+    /// Blob literal `x'CAFE'`
+    Blob(Vec<u8>),
+    /// Statement parameter `?`, `?1`, `:name`, `@name` or `$name`
+    Parameter,
+    /// Operator or punctuation `(`, `,`, `<=`, `||`, ...
+    Symbol,
+}
+
+/// Splits the SQL text into tokens, whitespace and comments are skipped
+pub(crate) fn tokenize(sql: &str) -> Result<Vec<Token<'_>>, SyntaxError> {
+    let mut tokens = Vec::new();
+
+    let (mut rem, _) = whitespace(sql).unwrap_or((sql, 0));
+    while !rem.is_empty() {
+        let Ok((next, kind)) = token(rem) else {
+            return Err(SyntaxError::new(
+                sql,
+                sql.len() - rem.len(),
+                format!("unrecognized token: \"{}\"", unrecognized(rem)),
+                Vec::new(),
+            ));
+        };
+        tokens.push(Token {
+            kind,
+            text: &rem[..rem.len() - next.len()],
+            offset: sql.len() - rem.len(),
+        });
+        (rem, _) = whitespace(next).unwrap_or((next, 0));
+    }
+
+    Ok(tokens)
+}
+
+/// Spaces, `-- comment` up to the end of the line and `/* comment */`
+fn whitespace(input: &str) -> IResult<&str, usize> {
+    many0_count(alt((
+        multispace1,
+        recognize(pair(tag("--"), take_while(|c| c != '\n'))),
+        recognize(tuple((tag("/*"), take_until("*/"), tag("*/")))),
+        // unterminated comment continues to the end of the SQL text
+        recognize(pair(tag("/*"), rest)),
+    )))(input)
+}
+
+fn token(input: &str) -> IResult<&str, TokenKind> {
+    alt((
+        map(blob, TokenKind::Blob),
+        map(quoted("'"), TokenKind::String),
+        map(
+            alt((
+                quoted("\""),
+                quoted("`"),
+                map(
+                    delimited(char('['), take_while(|c| c != ']'), char(']')),
+                    |s: &str| s.to_string(),
+                ),
+            )),
+            TokenKind::QuotedIdentifier,
+        ),
+        terminated(number, not(peek(satisfy(is_identifier_char)))),
+        value(
+            TokenKind::Word,
+            preceded(
+                // invalid blob literal is not a word followed by a string
+                not(pair(one_of("xX"), char('\''))),
+                pair(
+                    satisfy(|c| c.is_alphabetic() || c == '_'),
+                    take_while(|c| is_identifier_char(c) || c == '$'),
+                ),
+            ),
+        ),
+        value(
+            TokenKind::Parameter,
+            alt((
+                pair(char('?'), digit0),
+                pair(one_of(":@$"), take_while1(is_identifier_char)),
+            )),
+        ),
+        value(TokenKind::Symbol, symbol),
+    ))(input)
+}
+
+/// Text in quotes, quote inside is written twice (`'O''Brien'`)
+fn quoted<'a>(quote: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, String> {
+    delimited(
+        tag(quote),
+        fold_many0(
+            alt((is_not(quote), value(quote, pair(tag(quote), tag(quote))))),
+            String::new,
+            |mut s, part| {
+                s.push_str(part);
+                s
+            },
+        ),
+        tag(quote),
+    )
+}
+
+/// `x'CAFE'`
+fn blob(input: &str) -> IResult<&str, Vec<u8>> {
+    map_res(
+        preceded(
+            one_of("xX"),
+            delimited(
+                char('\''),
+                verify(hex_digit0, |hex: &str| hex.len() % 2 == 0),
+                char('\''),
+            ),
+        ),
+        |hex: &str| {
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                .collect::<Result<Vec<_>, _>>()
+        },
+    )(input)
+}
+
+/// `42`, `0x2A`, `3.5`, `.5` or `1e10`
+fn number(input: &str) -> IResult<&str, TokenKind> {
+    alt((
+        // hexadecimal integer is 64 bits in two's complement
+        map_res(preceded(tag_no_case("0x"), hex_digit1), |hex| {
+            u64::from_str_radix(hex, 16).map(|i| TokenKind::Integer(i as i64))
+        }),
+        map_res(
+            recognize(pair(
+                alt((
+                    recognize(pair(digit1, opt(pair(char('.'), digit0)))),
+                    recognize(pair(char('.'), digit1)),
+                )),
+                opt(tuple((one_of("eE"), opt(one_of("+-")), digit1))),
+            )),
+            decimal,
+        ),
+    ))(input)
+}
+
+/// Integer if it has no decimal point or exponent and fits in 64 bits, otherwise float
+fn decimal(number: &str) -> Result<TokenKind, std::num::ParseFloatError> {
+    if !number.contains(['.', 'e', 'E']) {
+        if let Ok(i) = number.parse() {
+            return Ok(TokenKind::Integer(i));
+        }
+    }
+    number.parse().map(TokenKind::Float)
+}
+
+fn symbol(input: &str) -> IResult<&str, &str> {
+    alt((
+        alt((
+            tag("||"),
+            tag("<="),
+            tag(">="),
+            tag("<>"),
+            tag("!="),
+            tag("=="),
+            tag("<<"),
+            tag(">>"),
+        )),
+        recognize(one_of("(),.;*=<>+-/%&|~")),
+    ))(input)
+}
+
+/// The unrecognized token for the error message, unterminated quoted text up to the end
+fn unrecognized(input: &str) -> &str {
+    match input.chars().next() {
+        Some('\'' | '"' | '`' | '[') => input.trim_end(),
+        Some('x' | 'X') if input[1..].starts_with('\'') => match input[2..].find('\'') {
+            Some(end) => &input[..end + 3],
+            None => input.trim_end(),
+        },
+        Some(c) if c.is_ascii_digit() => input
+            .split(|c: char| !is_identifier_char(c) && c != '.')
+            .next()
+            .unwrap_or(input),
+        Some(c) => &input[..c.len_utf8()],
+        None => input,
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(sql: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(sql)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, t.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            kinds("SELECT \"size range\", [a b], `c` FROM t -- comment\n/* block */;"),
+            vec![
+                (TokenKind::Word, "SELECT"),
+                (
+                    TokenKind::QuotedIdentifier("size range".to_string()),
+                    "\"size range\""
+                ),
+                (TokenKind::Symbol, ","),
+                (TokenKind::QuotedIdentifier("a b".to_string()), "[a b]"),
+                (TokenKind::Symbol, ","),
+                (TokenKind::QuotedIdentifier("c".to_string()), "`c`"),
+                (TokenKind::Word, "FROM"),
+                (TokenKind::Word, "t"),
+                (TokenKind::Symbol, ";"),
+            ]
+        );
+        assert_eq!(
+            kinds("'O''Brien'||x'CAFE'<=-0x2A"),
+            vec![
+                (TokenKind::String("O'Brien".to_string()), "'O''Brien'"),
+                (TokenKind::Symbol, "||"),
+                (TokenKind::Blob(vec![0xca, 0xfe]), "x'CAFE'"),
+                (TokenKind::Symbol, "<="),
+                (TokenKind::Symbol, "-"),
+                (TokenKind::Integer(42), "0x2A"),
+            ]
+        );
+        assert_eq!(
+            kinds("1.5e3 .5 9223372036854775808 ?1 :name /* open"),
+            vec![
+                (TokenKind::Float(1500.0), "1.5e3"),
+                (TokenKind::Float(0.5), ".5"),
+                (
+                    TokenKind::Float(9223372036854775808.0),
+                    "9223372036854775808"
+                ),
+                (TokenKind::Parameter, "?1"),
+                (TokenKind::Parameter, ":name"),
+            ]
+        );
+        assert_eq!(tokenize("  a").unwrap()[0].offset, 2);
+    }
+
+    #[test]
+    fn test_unrecognized() {
+        for (sql, token) in [
+            ("SELECT 'abc", "'abc"),
+            ("SELECT 1abc", "1abc"),
+            ("SELECT x'ABC'", "x'ABC'"),
+            ("SELECT #", "#"),
+        ] {
+            let err = tokenize(sql).unwrap_err();
+            assert_eq!(err.message, format!("unrecognized token: \"{token}\""));
+            assert_eq!((err.line, err.column), (1, 8));
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+
+/// Steps a query is executed by, as shown by EXPLAIN QUERY PLAN
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QueryPlan {
+    pub steps: Vec<PlanStep>,
+}
+
+/// How rows are found or processed by a step of the query
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlanStep {
+    /// All rows of the table are read, from the index having all used columns if some
+    Scan {
+        table: String,
+        index: Option<PlanIndex>,
+    },
+    /// Rows of the table are looked up by the constraints of the keys like `id=?`
+    Search {
+        table: String,
+        index: Option<PlanIndex>,
+        constraints: Vec<String>,
+    },
+    /// Rows found by several searches, each of them for a value of IN or a side of OR
+    MultiIndexOr(Vec<PlanStep>),
+    /// Rows are sorted in a temporary b-tree, for the clause like `ORDER BY`
+    TempBTree { clause: String },
+    /// Part of the query with its own steps, like a subquery or a SELECT of a compound query
+    Nested {
+        detail: String,
+        steps: Vec<PlanStep>,
+    },
+    /// The only row of SELECT without FROM
+    ConstantRow,
+}
+
+/// B-tree the rows of a table are looked up in
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlanIndex {
+    /// The table b-tree keyed by the rowid
+    IntegerPrimaryKey,
+    /// The b-tree of a WITHOUT ROWID table keyed by the primary key
+    PrimaryKey,
+    /// Index of the table, the table is not read if the index is `covering` all used columns
+    Index { name: String, covering: bool },
+}
+
+impl QueryPlan {
+    /// Rows of EXPLAIN QUERY PLAN: the id of the step, the id of its parent step
+    /// (0 for the top steps) and the description of the step
+    pub fn rows(&self) -> Vec<(usize, usize, String)> {
+        let mut rows = Vec::new();
+        add_rows(&self.steps, 0, &mut rows);
+        rows
+    }
+}
+
+fn add_rows(steps: &[PlanStep], parent: usize, rows: &mut Vec<(usize, usize, String)>) {
+    for step in steps {
+        rows.push((rows.len() + 1, parent, step.to_string()));
+        let id = rows.len();
+        match step {
+            PlanStep::MultiIndexOr(searches) => {
+                for (i, search) in searches.iter().enumerate() {
+                    rows.push((rows.len() + 1, id, format!("INDEX {}", i + 1)));
+                    add_rows(std::slice::from_ref(search), rows.len(), rows);
+                }
+            }
+            PlanStep::Nested { steps, .. } => add_rows(steps, id, rows),
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for PlanIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanIndex::IntegerPrimaryKey => write!(f, "INTEGER PRIMARY KEY"),
+            PlanIndex::PrimaryKey => write!(f, "PRIMARY KEY"),
+            PlanIndex::Index {
+                name,
+                covering: true,
+            } => write!(f, "COVERING INDEX {}", name),
+            PlanIndex::Index { name, .. } => write!(f, "INDEX {}", name),
+        }
+    }
+}
+
+impl fmt::Display for PlanStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanStep::Scan { table, index } => {
+                write!(f, "SCAN {}", table)?;
+                if let Some(index) = index {
+                    write!(f, " USING {}", index)?;
+                }
+                Ok(())
+            }
+            PlanStep::Search {
+                table,
+                index,
+                constraints,
+            } => {
+                write!(f, "SEARCH {}", table)?;
+                if let Some(index) = index {
+                    write!(f, " USING {}", index)?;
+                }
+                if !constraints.is_empty() {
+                    write!(f, " ({})", constraints.join(" AND "))?;
+                }
+                Ok(())
+            }
+            PlanStep::MultiIndexOr(_) => write!(f, "MULTI-INDEX OR"),
+            PlanStep::TempBTree { clause } => write!(f, "USE TEMP B-TREE FOR {}", clause),
+            PlanStep::Nested { detail, .. } => write!(f, "{}", detail),
+            PlanStep::ConstantRow => write!(f, "SCAN CONSTANT ROW"),
+        }
+    }
+}
+
+/// Tree of the steps like in the sqlite3 shell
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "QUERY PLAN")?;
+        let rows = self.rows();
+        write_tree(f, &rows, 0, "")
+    }
+}
+
+fn write_tree(
+    f: &mut fmt::Formatter<'_>,
+    rows: &[(usize, usize, String)],
+    parent: usize,
+    indent: &str,
+) -> fmt::Result {
+    let children: Vec<_> = rows.iter().filter(|(_, p, _)| *p == parent).collect();
+    for (i, (id, _, detail)) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        writeln!(
+            f,
+            "{}{}{}",
+            indent,
+            if last { "`--" } else { "|--" },
+            detail
+        )?;
+        let indent = format!("{}{}", indent, if last { "   " } else { "|  " });
+        write_tree(f, rows, *id, &indent)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_plan_display() {
+        let plan = QueryPlan {
+            steps: vec![
+                PlanStep::MultiIndexOr(vec![
+                    PlanStep::Search {
+                        table: "users".to_string(),
+                        index: Some(PlanIndex::Index {
+                            name: "idx_users_email".to_string(),
+                            covering: false,
+                        }),
+                        constraints: vec!["email=?".to_string()],
+                    },
+                    PlanStep::Search {
+                        table: "users".to_string(),
+                        index: Some(PlanIndex::IntegerPrimaryKey),
+                        constraints: vec!["rowid>?".to_string(), "rowid<?".to_string()],
+                    },
+                ]),
+                PlanStep::TempBTree {
+                    clause: "ORDER BY".to_string(),
+                },
+            ],
+        };
+
+        assert_eq!(
+            plan.rows(),
+            vec![
+                (1, 0, "MULTI-INDEX OR".to_string()),
+                (2, 1, "INDEX 1".to_string()),
+                (
+                    3,
+                    2,
+                    "SEARCH users USING INDEX idx_users_email (email=?)".to_string()
+                ),
+                (4, 1, "INDEX 2".to_string()),
+                (
+                    5,
+                    4,
+                    "SEARCH users USING INTEGER PRIMARY KEY (rowid>? AND rowid<?)".to_string()
+                ),
+                (6, 0, "USE TEMP B-TREE FOR ORDER BY".to_string()),
+            ]
+        );
+        assert_eq!(
+            plan.to_string(),
+            "QUERY PLAN
+|--MULTI-INDEX OR
+|  |--INDEX 1
+|  |  `--SEARCH users USING INDEX idx_users_email (email=?)
+|  `--INDEX 2
+|     `--SEARCH users USING INTEGER PRIMARY KEY (rowid>? AND rowid<?)
+`--USE TEMP B-TREE FOR ORDER BY
+"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::{bail, Result};
+
+use super::page::ColumnContent;
+use super::sql::{self, Command, QueryPlan, Rows};
+use super::DB;
+
+/// Parsed SQL statement with parameters `?`, `?NNN`, `:name`, `@name` or `$name`
+/// whose values are bound before the execution
+#[derive(Debug, Clone)]
+pub struct Statement {
+    command: Command,
+    /// names of the parameters by their index - 1, None for `?`
+    parameters: Vec<Option<String>>,
+    values: Vec<ColumnContent>,
+}
+
+impl Statement {
+    pub(crate) fn new(sql: &str) -> Result<Self> {
+        let mut command = sql::parse_command(sql)?;
+        let parameters = command.number_parameters()?;
+        let values = vec![ColumnContent::Null; parameters.len()];
+
+        Ok(Self {
+            command,
+            parameters,
+            values,
+        })
+    }
+
+    /// Number of the parameters, that is the largest parameter index
+    pub fn parameter_count(&self) -> usize {
+        self.parameters.len()
+    }
+
+    /// Index of the named parameter including its prefix (`:name`)
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        self.parameters
+            .iter()
+            .position(|n| n.as_deref() == Some(name))
+            .map(|i| i + 1)
+    }
+
+    /// Binds the value to the parameter with the 1-based index
+    pub fn bind(&mut self, index: usize, value: impl Into<ColumnContent>) -> Result<()> {
+        if index < 1 || index > self.values.len() {
+            bail!(
+                "parameter index {} out of range - should be between 1 and {}",
+                index,
+                self.values.len()
+            );
+        }
+        self.values[index - 1] = value.into();
+        Ok(())
+    }
+
+    /// Binds the value to the named parameter, the name includes its prefix (`:name`)
+    pub fn bind_named(&mut self, name: &str, value: impl Into<ColumnContent>) -> Result<()> {
+        match self.parameter_index(name) {
+            Some(index) => self.bind(index, value),
+            None => bail!("no such parameter: {}", name),
+        }
+    }
+
+    /// Prepares the statement to be executed again, like `sqlite3_reset` the bound values
+    /// are kept. Executions leave no state in the statement, so there is nothing else to reset.
+    pub fn reset(&mut self) {}
+
+    /// Clears the bound values, parameters without a value are NULL
+    pub fn clear_bindings(&mut self) {
+        self.values.fill(ColumnContent::Null);
+    }
+
+    /// Executes the statement with the currently bound values
+    pub fn execute(&self, db: &mut DB) -> Result<Vec<Vec<String>>> {
+        match self.parameters.is_empty() {
+            true => self.command.execute(db),
+            false => self.command.bind_parameters(&self.values).execute(db),
+        }
+    }
+
+    /// Returns the result rows of the statement with the currently bound values,
+    /// read one at a time as they are iterated over
+    pub fn query<'a>(&self, db: &'a mut DB) -> Result<Rows<'a>> {
+        match self.parameters.is_empty() {
+            true => self.command.rows(db),
+            false => self.command.bind_parameters(&self.values).rows(db),
+        }
+    }
+
+    /// Returns the plan of the statement with the currently bound values, like EXPLAIN QUERY PLAN
+    pub fn query_plan(&self, db: &mut DB) -> Result<QueryPlan> {
+        match self.parameters.is_empty() {
+            true => self.command.query_plan(db),
+            false => self.command.bind_parameters(&self.values).query_plan(db),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parameters() {
+        let db = DB::new("sample.db").unwrap();
+
+        let stmt = db
+            .prepare("SELECT ?, :a, ?5, (SELECT @b FROM apples WHERE id = :a), ?, $c")
+            .unwrap();
+        assert_eq!(stmt.parameter_count(), 8);
+        assert_eq!(stmt.parameter_index(":a"), Some(2));
+        assert_eq!(stmt.parameter_index("?5"), Some(5));
+        assert_eq!(stmt.parameter_index("@b"), Some(6));
+        assert_eq!(stmt.parameter_index("$c"), Some(8));
+        assert_eq!(stmt.parameter_index("?"), None);
+        assert_eq!(stmt.parameter_index("a"), None);
+
+        let err = db.prepare("SELECT ?0").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "variable number must be between ?1 and ?32766"
+        );
+    }
+
+    #[test]
+    fn test_execute() {
+        let mut db = DB::new("sample.db").unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT name FROM apples WHERE color = :color OR id = ?2 ORDER BY id")
+            .unwrap();
+        // unbound parameters are NULL
+        assert_eq!(stmt.execute(&mut db).unwrap(), Vec::<Vec<String>>::new());
+
+        stmt.bind_named(":color", "Red").unwrap();
+        assert_eq!(stmt.execute(&mut db).unwrap(), vec![vec!["Fuji"]]);
+        stmt.bind(2, 4).unwrap();
+        assert_eq!(
+            stmt.execute(&mut db).unwrap(),
+            vec![vec!["Fuji"], vec!["Golden Delicious"]]
+        );
+        stmt.bind(1, "Yellow".to_string()).unwrap();
+        stmt.bind(2, None::<i64>).unwrap();
+        assert_eq!(
+            stmt.execute(&mut db).unwrap(),
+            vec![vec!["Golden Delicious"]]
+        );
+
+        // the statement runs again with the same values
+        stmt.reset();
+        assert_eq!(
+            stmt.execute(&mut db).unwrap(),
+            vec![vec!["Golden Delicious"]]
+        );
+        stmt.clear_bindings();
+        assert_eq!(stmt.execute(&mut db).unwrap(), Vec::<Vec<String>>::new());
+
+        let err = stmt.bind(3, 1).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter index 3 out of range - should be between 1 and 2"
+        );
+        let err = stmt.bind_named(":colour", "Red").unwrap_err();
+        assert_eq!(err.to_string(), "no such parameter: :colour");
+
+        // values are compared like literals, also in LIMIT and IN lists
+        let mut stmt = db
+            .prepare("SELECT id FROM oranges WHERE id IN (?, ?) OR name = ? LIMIT ?")
+            .unwrap();
+        stmt.bind(1, 2).unwrap();
+        stmt.bind(2, "5").unwrap();
+        stmt.bind(3, "Mandarin").unwrap();
+        stmt.bind(4, 2).unwrap();
+        assert_eq!(stmt.execute(&mut db).unwrap(), vec![vec!["1"], vec!["2"]]);
+    }
+}
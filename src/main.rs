@@ -1,6 +1,6 @@
 use anyhow::{bail, Context, Result};
 
-use codecrafters_sqlite::db::DB;
+use codecrafters_sqlite::db::{SyntaxError, DB};
 
 fn main() -> Result<()> {
     // Parse arguments
@@ -40,7 +40,12 @@ fn main() -> Result<()> {
         ".tables" => {
             let db = DB::new(&args[1]).context("open DB")?;
 
-            for name in db.table_names(false) {
+            // like sqlite3, list tables and views sorted by name
+            let mut names = db.table_names(false);
+            names.extend(db.view_names());
+            names.sort();
+
+            for name in names {
                 print!("{}   ", name);
             }
             println!();
@@ -57,22 +62,34 @@ fn main() -> Result<()> {
             if sql.to_uppercase().starts_with(".") {
                 bail!("Invalid command: {}!", sql);
             }
-            if !sql.to_uppercase().starts_with("SELECT") {
+            let upper = sql.to_uppercase();
+            let explain = upper.starts_with("EXPLAIN");
+            if !upper.starts_with("SELECT") && !upper.starts_with("WITH") && !explain {
                 bail!("Invalid SQL command: {}; only SELECT is supported!", sql);
             }
 
             let mut db = DB::new(&args[1]).context("open DB")?;
-            let rows = db.execute(sql)?;
-
-            for row in rows {
-                let n_columns = row.len();
-                for (col_i, column) in row.iter().enumerate() {
-                    print!("{}", column);
-                    if col_i < n_columns - 1 {
-                        print!("|");
+            // like sqlite3, show the plan as a tree
+            let result = match explain {
+                true => db.query_plan(sql).map(|plan| print!("{}", plan)),
+                // rows are printed as soon as they are read
+                false => db.query(sql).and_then(|rows| {
+                    for row in rows {
+                        let values: Vec<String> = row?.iter().map(|v| v.to_string()).collect();
+                        println!("{}", values.join("|"));
+                    }
+                    Ok(())
+                }),
+            };
+            if let Err(err) = result {
+                match err.downcast_ref::<SyntaxError>() {
+                    // like sqlite3, show where the error is
+                    Some(err) => {
+                        eprintln!("Error: {}\n{}", err, err.snippet());
+                        std::process::exit(1);
                     }
+                    None => return Err(err),
                 }
-                println!();
             }
         }
     }